@@ -114,6 +114,8 @@ export function DeployDialog({
           metadata: {
             deployment_time: result.details.timestamp,
             tx_hash: result.transaction_hash,
+            upload_tx_hash: result.upload_transaction_hash,
+            wasm_hash: result.wasm_hash,
             fee: result.fee,
             ledger_sequence: result.details.ledger_sequence,
             deployer_address: result.details.deployer_address,
@@ -261,7 +263,7 @@ export function DeployDialog({
                   </div>
                   <div className="flex-1">
                     <div className="text-sm font-medium">Contract Size</div>
-                    <div className="text-xs text-muted-foreground">{wasmSizeKB} KB optimized WASM</div>
+                    <div className="text-xs text-muted-foreground">{wasmSizeKB} KB WASM</div>
                   </div>
                 </div>
               )}
@@ -1,16 +1,35 @@
 import axios from 'axios';
-import { CompilationResult, DeploymentResult, MethodCallResult } from './types';
+import {
+  CompilationResult,
+  ContractEventEntry,
+  ContractFailure,
+  DeploymentResult,
+  AuthEntry,
+  MethodCallResult,
+  SignedAuthEntry,
+  TransactionCost,
+} from './types';
 import { API_URL } from './stellar-config';
+import { supabase } from './supabase';
 
 // Create axios instance with default config
 const api = axios.create({
   baseURL: API_URL,
   headers: {
     'Content-Type': 'application/json',
-    'Authorization': `Bearer ${import.meta.env.VITE_API_KEY}`
   },
 });
 
+// The backend identifies the user from their Supabase access token
+api.interceptors.request.use(async (config) => {
+  const { data } = await supabase.auth.getSession();
+  const token = data.session?.access_token;
+  if (token) {
+    config.headers.Authorization = `Bearer ${token}`;
+  }
+  return config;
+});
+
 interface CompileRequest {
   user_id: string;
   project_id: string;
@@ -20,7 +39,9 @@ interface CompileRequest {
 interface DeployRequest {
   user_id: string;
   project_id: string;
-  account_secret?: string;
+  network?: string;
+  constructor_args?: any; // For contracts with a __constructor
+  with_logs?: boolean; // Deploy the build with logs; not allowed on mainnet
 }
 
 interface MethodCallRequest {
@@ -28,7 +49,75 @@ interface MethodCallRequest {
   method_name: string;
   args: any;
   method_type: 'view' | 'call';
-  source_account?: string;
+  network?: string;
+  auth?: SignedAuthEntry[];
+  test_signers?: string[]; // Test signer addresses the server signs for
+}
+
+// Unsigned transaction for the user's wallet to sign
+export interface PreparedTransaction {
+  xdr: string;
+  hash: string;
+  network: string;
+  network_passphrase: string;
+  fee: string;
+  step: 'upload' | 'create' | null; // Deploys take two transactions
+  wasm_hash: string | null;
+  result: any;
+  logs: string[];
+  events: ContractEventEntry[];
+  cost: TransactionCost | null;
+  auth: AuthEntry[]; // Submittable once every entry is signed
+}
+
+export interface SubmitResult {
+  success: boolean;
+  transaction_hash: string;
+  ledger_sequence: number | null;
+  result: any; // The contract id after a create step
+  fee: string | null;
+  error: string | null;
+  failure: ContractFailure | null;
+  logs: string[];
+  events: ContractEventEntry[];
+  cost: TransactionCost | null;
+}
+
+// A call run in a local, in-memory environment
+export interface ExecuteResult {
+  success: boolean;
+  contract_id: string | null; // null if the contract could not be created
+  result: any;
+  error: string | null;
+  failure: ContractFailure | null;
+  logs: string[];
+  events: ContractEventEntry[];
+  budget: {
+    cpu_instructions: number;
+    cpu_limit: number;
+    memory_bytes: number;
+    memory_limit: number;
+  };
+  storage: Array<{
+    contract_id: string;
+    durability: 'instance' | 'persistent' | 'temporary';
+    key: any;
+    value: any;
+    live_until_ledger: number | null;
+  }>;
+}
+
+export interface ArgError {
+  path: string; // e.g. "order.items[2].price"; empty for the call as a whole
+  message: string;
+}
+
+export interface ArgValidation {
+  valid: boolean;
+  contract_id: string;
+  method_name: string;
+  parameters: { name: string; type: string }[];
+  errors: ArgError[];
 }
 
 interface ApiResponse<T> {
@@ -52,7 +141,109 @@ interface CompileResponse {
     compilation_time: number;
     project_path: string;
   };
-  spec: any | null; // Backend sends 'spec', we map it to 'abi' in CompilationResult
+  spec: ContractSpec | null; // Backend sends 'spec', we map it to 'abi' in CompilationResult
+}
+
+// Type definition as parsed from the contract's spec section
+type SpecType =
+  | { type: 'option'; value: SpecType }
+  | { type: 'result'; ok: SpecType; error: SpecType }
+  | { type: 'vec'; element: SpecType }
+  | { type: 'map'; key: SpecType; value: SpecType }
+  | { type: 'tuple'; elements: SpecType[] }
+  | { type: 'bytes_n'; n: number }
+  | { type: 'udt'; name: string }
+  | { type: string };
+
+interface ContractSpec {
+  functions: Array<{
+    name: string;
+    doc: string;
+    inputs: Array<{ name: string; doc: string; type: SpecType }>;
+    outputs: SpecType[];
+  }>;
+  [section: string]: unknown;
+}
+
+/**
+ * Render a spec type the way it is written in Rust, e.g. Option<Vec<u32>>
+ */
+function formatSpecType(specType: SpecType): string {
+  const t = specType as any;
+  switch (t.type) {
+    case 'option':
+      return `Option<${formatSpecType(t.value)}>`;
+    case 'result':
+      return `Result<${formatSpecType(t.ok)}, ${formatSpecType(t.error)}>`;
+    case 'vec':
+      return `Vec<${formatSpecType(t.element)}>`;
+    case 'map':
+      return `Map<${formatSpecType(t.key)}, ${formatSpecType(t.value)}>`;
+    case 'tuple':
+      return `(${t.elements.map(formatSpecType).join(', ')})`;
+    case 'bytes_n':
+      return `BytesN<${t.n}>`;
+    case 'udt':
+      return t.name;
+    case 'void':
+      return '()';
+    case 'muxed_address':
+      return 'MuxedAddress';
+    default:
+      return t.type;
+  }
+}
+
+/**
+ * Convert the contract spec into the ABI method list the UI renders
+ */
+function specToAbi(spec: ContractSpec | null): any[] {
+  if (!spec) return [];
+
+  return spec.functions.map((fn) => ({
+    type: 'function',
+    name: fn.name,
+    doc: fn.doc,
+    inputs: fn.inputs.map((input) => ({
+      name: input.name,
+      doc: input.doc,
+      type: formatSpecType(input.type),
+    })),
+    outputs: fn.outputs.map((output) => ({ type: formatSpecType(output) })),
+  }));
+}
+
+interface CompileJob {
+  job_id: string;
+  status: 'queued' | 'running' | 'succeeded' | 'failed' | 'cancelled';
+  queue_position: number | null;
+  result: CompileResponse | null;
+  error: {
+    code: string;
+    message: string;
+    details: string | null;
+  } | null;
+}
+
+const JOB_POLL_INTERVAL_MS = 1000;
+
+/**
+ * Poll a compile job until the backend reports it finished
+ */
+async function waitForCompileJob(jobId: string): Promise<CompileJob> {
+  for (;;) {
+    const { data: response } = await api.get<ApiResponse<CompileJob>>(`/jobs/${jobId}`);
+
+    if (!response.success || !response.data) {
+      throw new Error(response.error?.message || 'Compilation failed');
+    }
+
+    if (response.data.status !== 'queued' && response.data.status !== 'running') {
+      return response.data;
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, JOB_POLL_INTERVAL_MS));
+  }
 }
 
 /**
@@ -70,25 +261,33 @@ export async function compileContract(
       code,
     };
 
-    const { data: response } = await api.post<ApiResponse<CompileResponse>>('/compile', payload);
+    // Compilation is queued on the backend; wait for the job to finish
+    const { data: queued } = await api.post<ApiResponse<CompileJob>>('/compile', payload);
 
-    if (!response.success || !response.data) {
-      throw new Error(response.error?.message || 'Compilation failed');
+    if (!queued.success || !queued.data) {
+      throw new Error(queued.error?.message || 'Compilation failed');
+    }
+
+    const job = await waitForCompileJob(queued.data.job_id);
+    const result = job.result;
+
+    if (!result) {
+      throw new Error(job.error?.message || 'Compilation failed');
     }
 
     return {
-      success: response.data.success,
-      exit_code: response.data.exit_code,
-      stdout: response.data.stdout,
-      stderr: response.data.stderr,
+      success: result.success,
+      exit_code: result.exit_code,
+      stdout: result.stdout,
+      stderr: result.stderr,
       details: {
-        status: response.data.details.status,
-        compilation_time: response.data.details.compilation_time,
-        project_path: response.data.details.project_path,
-        wasm_size: response.data.details.wasm_size,
-        optimized: response.data.details.optimized,
+        status: result.details.status,
+        compilation_time: result.details.compilation_time,
+        project_path: result.details.project_path,
+        wasm_size: result.details.wasm_size,
+        optimized: result.details.optimized,
       },
-      abi: response.data.spec || [], // Stellar uses 'spec' instead of 'abi'
+      abi: specToAbi(result.spec), // Stellar uses 'spec' instead of 'abi'
       code_snapshot: code,
     };
   } catch (error) {
@@ -108,13 +307,17 @@ export async function compileContract(
 export async function deployContract(
   userId: string,
   projectId: string,
-  accountSecret?: string
+  network?: string,
+  constructorArgs?: any,
+  withLogs?: boolean
 ): Promise<DeploymentResult> {
   try {
     const payload: DeployRequest = {
       user_id: userId,
       project_id: projectId,
-      account_secret: accountSecret,
+      network,
+      constructor_args: constructorArgs,
+      with_logs: withLogs,
     };
 
     const { data: response } = await api.post<ApiResponse<DeploymentResult>>('/deploy', payload);
@@ -143,7 +346,9 @@ export async function invokeContractMethod(
   methodName: string,
   args: any,
   methodType: 'view' | 'call',
-  sourceAccount?: string
+  network?: string,
+  auth?: SignedAuthEntry[],
+  testSigners?: string[]
 ): Promise<MethodCallResult> {
   try {
     const payload: MethodCallRequest = {
@@ -151,7 +356,9 @@ export async function invokeContractMethod(
       method_name: methodName,
       args,
       method_type: methodType,
-      source_account: sourceAccount,
+      network,
+      auth,
+      test_signers: testSigners,
     };
 
     const { data: response } = await api.post<ApiResponse<MethodCallResult>>('/invoke', payload);
@@ -195,4 +402,138 @@ export async function invokeContractMethod(
 }
 
 // Backwards compatibility alias
-export const callContractMethod = invokeContractMethod;
\ No newline at end of file
+export const callContractMethod = invokeContractMethod;
+
+async function postOrThrow<T>(path: string, payload: unknown, fallback: string): Promise<T> {
+  try {
+    const { data: response } = await api.post<ApiResponse<T>>(path, payload);
+
+    if (!response.success || !response.data) {
+      throw new Error(response.error?.details || response.error?.message || fallback);
+    }
+
+    return response.data;
+  } catch (error) {
+    if (axios.isAxiosError(error) && error.response?.data) {
+      const apiError = error.response.data as ApiResponse<any>;
+      throw new Error(apiError.error?.details || apiError.error?.message || fallback);
+    }
+    throw error instanceof Error ? error : new Error(fallback);
+  }
+}
+
+/**
+ * Prepare the next deploy transaction (wasm upload, then contract creation)
+ * for the wallet with the given public key to sign
+ */
+export function prepareDeploy(
+  userId: string,
+  projectId: string,
+  publicKey: string,
+  network?: string,
+  constructorArgs?: any,
+  withLogs?: boolean // Pass the same value for the upload and create steps
+): Promise<PreparedTransaction> {
+  return postOrThrow(
+    '/deploy/prepare',
+    {
+      user_id: userId,
+      project_id: projectId,
+      public_key: publicKey,
+      network,
+      constructor_args: constructorArgs,
+      with_logs: withLogs,
+    },
+    'Failed to prepare deployment'
+  );
+}
+
+/**
+ * Prepare a contract call for the wallet with the given public key to sign.
+ * If other addresses must authorize it, prepare again with their signed
+ * `auth` entries.
+ */
+export function prepareInvoke(
+  contractId: string,
+  methodName: string,
+  args: any,
+  publicKey: string,
+  network?: string,
+  auth?: SignedAuthEntry[],
+  testSigners?: string[]
+): Promise<PreparedTransaction> {
+  return postOrThrow(
+    '/invoke/prepare',
+    {
+      contract_id: contractId,
+      method_name: methodName,
+      args,
+      public_key: publicKey,
+      network,
+      auth,
+      test_signers: testSigners,
+    },
+    'Failed to prepare contract call'
+  );
+}
+
+/**
+ * Addresses the server signs authorization entries for
+ */
+export async function getTestSigners(): Promise<string[]> {
+  const { data: response } = await api.get<ApiResponse<{ signers: string[] }>>('/auth/test-signers');
+  if (!response.success || !response.data) {
+    throw new Error(response.error?.message || 'Failed to load test signers');
+  }
+  return response.data.signers;
+}
+
+/**
+ * Submit a wallet-signed transaction envelope and wait for its result
+ */
+export function submitSignedTransaction(xdr: string, network?: string): Promise<SubmitResult> {
+  return postOrThrow('/transactions/submit', { xdr, network }, 'Failed to submit transaction');
+}
+/**
+ * Run a method of the project's compiled contract locally, without a
+ * network. Each run deploys it into an empty ledger first. The contract's
+ * build with logs is used (built on first use), so `log!` output is returned.
+ */
+export function executeLocally(
+  userId: string,
+  projectId: string,
+  methodName: string,
+  args: any,
+  constructorArgs?: any,
+  contract?: string
+): Promise<ExecuteResult> {
+  return postOrThrow(
+    '/execute',
+    {
+      user_id: userId,
+      project_id: projectId,
+      contract,
+      method_name: methodName,
+      args,
+      constructor_args: constructorArgs,
+    },
+    'Failed to execute contract method'
+  );
+}
+
+/**
+ * Check a call's arguments against the deployed contract's interface
+ * without sending a transaction
+ */
+export function validateArgs(
+  contractId: string,
+  methodName: string,
+  args: any,
+  network?: string
+): Promise<ArgValidation> {
+  return postOrThrow(
+    `/contracts/${encodeURIComponent(contractId)}/validate-args`,
+    { method_name: methodName, args, network },
+    'Failed to validate arguments'
+  );
+}
@@ -47,12 +47,18 @@ export interface DeploymentResult {
   contract_id: string;
   explorer_url: string;
   fee?: string; // Stellar uses 'fee' instead of 'gas_used'
-  proof_tx_hash?: string;
+  wasm_hash: string;
+  upload_transaction_hash: string | null; // null when the wasm was already uploaded
   details: {
     network: string;
     ledger_sequence: number; // Stellar uses ledger_sequence instead of block_height
     timestamp: string;
     deployer_address: string; // Stellar uses 'address' not 'account'
+    salt: string;
+    upload_fee?: string;
+    create_fee?: string;
+    upload_cost?: TransactionCost | null;
+    create_cost?: TransactionCost | null;
   };
 }
 
@@ -92,7 +98,68 @@ export interface MethodCallResult {
   logs: string[];
   fee?: string; // Stellar uses 'fee' instead of 'gas_used'
   error?: string;
+  failure?: ContractFailure | null; // Decoded cause when the call failed
   raw_output?: string;
+  events?: ContractEventEntry[]; // `logs` has one line for each
+  cost?: TransactionCost | null;
+  auth?: AuthEntry[]; // Authorizations the call needs
+}
+
+export interface AuthEntry {
+  address: string | null; // null when the transaction's own signature covers it
+  invocation: string; // e.g. "CA...XYZ.transfer"
+  signed: boolean;
+  signature_expiration_ledger: number | null;
+  xdr: string; // SorobanAuthorizationEntry, base64
+  preimage: string | null; // What wallets' signAuthEntry takes, base64
+  hash: string | null; // Hex SHA-256 of the preimage, which the signature covers
+}
+
+// An entry from `auth`, sent back with its signature
+export interface SignedAuthEntry {
+  xdr: string;
+  signature?: string; // Hex ed25519 signature of the entry's hash
+}
+
+export interface ContractEventEntry {
+  event_type: 'contract' | 'system' | 'diagnostic';
+  contract_id: string | null;
+  topics: any[];
+  data: any;
+  in_successful_call: boolean; // False if the emitting call was rolled back
+}
+
+export interface TransactionCost {
+  resources: ResourceUsage;
+  fees: FeeBreakdown;
+}
+
+export interface ResourceUsage {
+  cpu_instructions: number;
+  memory_bytes: number | null; // Only known from simulation
+  read_entries: number;
+  write_entries: number;
+  read_bytes: number;
+  write_bytes: number;
+  events_bytes: number; // Contract events plus the return value
+}
+
+// Stroops; the resource fee is split into its parts once applied
+export interface FeeBreakdown {
+  inclusion_fee: string;
+  resource_fee: string;
+  non_refundable_fee: string | null;
+  refundable_fee: string | null;
+  rent_fee: string | null;
+  fee_charged: string | null;
+}
+
+export interface ContractFailure {
+  error_type: string; // "Contract", or a host category such as "Budget" or "Auth"
+  code: number;
+  name: string | null; // Error enum variant, or the host error code
+  doc: string | null;
+  explanation: string;
 }
 
 export interface ABICall {
@@ -1,21 +1,29 @@
 #![cfg(test)]
 
 use super::*;
-use soroban_sdk::{vec, Env, String};
+use soroban_sdk::Env;
 
 #[test]
-fn test() {
+fn test_increment() {
     let env = Env::default();
-    let contract_id = env.register(Contract, ());
-    let client = ContractClient::new(&env, &contract_id);
+    let contract_id = env.register(Counter, ());
+    let client = CounterClient::new(&env, &contract_id);
 
-    let words = client.hello(&String::from_str(&env, "Dev"));
-    assert_eq!(
-        words,
-        vec![
-            &env,
-            String::from_str(&env, "Hello"),
-            String::from_str(&env, "Dev"),
-        ]
-    );
+    assert_eq!(client.get_count(), 0);
+    assert_eq!(client.increment(), 1);
+    assert_eq!(client.increment(), 2);
+    assert_eq!(client.get_count(), 2);
+}
+
+#[test]
+fn test_set_and_reset() {
+    let env = Env::default();
+    let contract_id = env.register(Counter, ());
+    let client = CounterClient::new(&env, &contract_id);
+
+    client.set_count(&41);
+    assert_eq!(client.increment(), 42);
+
+    client.reset();
+    assert_eq!(client.get_count(), 0);
 }
@@ -0,0 +1,513 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use anyhow::{Context, Result};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::future::{ready, Ready};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+use crate::models::ApiResponse;
+
+// Supabase signs user sessions for this audience
+const DEFAULT_AUDIENCE: &str = "authenticated";
+
+// Keys are refetched at most this often, even for tokens with an unknown kid
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+const JWKS_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+// EventSource can't set headers, so SSE clients pass the token in the query.
+// Only the build event stream accepts it there.
+const ACCESS_TOKEN_PARAM: &str = "access_token";
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String, // Supabase auth user id
+}
+
+/// The caller, as established by a verified Supabase access token
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: String,
+}
+
+impl AuthenticatedUser {
+    /// Check a `user_id` a request body claims against the token's
+    pub fn check_claimed(&self, claimed: Option<&str>) -> Result<()> {
+        match claimed {
+            Some(claimed) if claimed != self.user_id => Err(anyhow::anyhow!(
+                "Request is for user {} but the access token belongs to {}",
+                claimed,
+                self.user_id
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Set by `require_user`; a route outside it is a wiring bug
+        ready(
+            req.extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or_else(|| {
+                    actix_web::error::ErrorInternalServerError("Route is not authenticated")
+                }),
+        )
+    }
+}
+
+enum KeySource {
+    /// Legacy Supabase projects sign with a shared HS256 secret
+    Secret(DecodingKey),
+    /// Asymmetric signing keys published at the project's JWKS endpoint
+    Jwks(JwksCache),
+}
+
+struct JwksCache {
+    url: String,
+    client: reqwest::Client,
+    keys: RwLock<CachedKeys>,
+}
+
+#[derive(Default)]
+struct CachedKeys {
+    keys: HashMap<String, (DecodingKey, Algorithm)>,
+    fetched_at: Option<Instant>,
+}
+
+/// Verifies Supabase access tokens
+pub struct Authenticator {
+    keys: KeySource,
+    audience: String,
+    issuer: Option<String>,
+}
+
+impl Authenticator {
+    /// Configure from SUPABASE_JWKS_URL or SUPABASE_JWT_SECRET, plus the
+    /// optional SUPABASE_JWT_AUDIENCE and SUPABASE_JWT_ISSUER
+    pub fn from_env() -> Result<Self> {
+        let keys = match (
+            env::var("SUPABASE_JWKS_URL"),
+            env::var("SUPABASE_JWT_SECRET"),
+        ) {
+            (Ok(url), _) if !url.is_empty() => {
+                info!("Verifying access tokens against JWKS at {}", url);
+                KeySource::Jwks(JwksCache {
+                    url,
+                    client: reqwest::Client::new(),
+                    keys: RwLock::new(CachedKeys::default()),
+                })
+            }
+            (_, Ok(secret)) if !secret.is_empty() => {
+                info!("Verifying access tokens with the shared JWT secret");
+                KeySource::Secret(DecodingKey::from_secret(secret.as_bytes()))
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Set SUPABASE_JWKS_URL or SUPABASE_JWT_SECRET to verify access tokens"
+                ))
+            }
+        };
+
+        Ok(Self {
+            keys,
+            audience: env::var("SUPABASE_JWT_AUDIENCE")
+                .unwrap_or_else(|_| DEFAULT_AUDIENCE.to_string()),
+            issuer: env::var("SUPABASE_JWT_ISSUER")
+                .ok()
+                .filter(|issuer| !issuer.is_empty()),
+        })
+    }
+
+    /// Verify a token's signature, expiry, audience and issuer
+    pub async fn verify(&self, token: &str) -> Result<AuthenticatedUser> {
+        let header = jsonwebtoken::decode_header(token).context("Malformed access token")?;
+
+        let claims = match &self.keys {
+            KeySource::Secret(key) => self.decode(token, key, Algorithm::HS256)?,
+            KeySource::Jwks(jwks) => {
+                let kid = header.kid.context("Access token has no key id")?;
+                let (key, algorithm) = jwks.key(&kid).await?;
+                if header.alg != algorithm {
+                    return Err(anyhow::anyhow!(
+                        "Access token algorithm {:?} does not match key {}",
+                        header.alg,
+                        kid
+                    ));
+                }
+                self.decode(token, &key, algorithm)?
+            }
+        };
+
+        Ok(AuthenticatedUser {
+            user_id: claims.sub,
+        })
+    }
+
+    fn decode(&self, token: &str, key: &DecodingKey, algorithm: Algorithm) -> Result<Claims> {
+        let mut validation = Validation::new(algorithm);
+        validation.set_audience(&[&self.audience]);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        jsonwebtoken::decode::<Claims>(token, key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| anyhow::anyhow!("Invalid access token: {}", e))
+    }
+}
+
+impl JwksCache {
+    async fn key(&self, kid: &str) -> Result<(DecodingKey, Algorithm)> {
+        {
+            let cached = self.keys.read().await;
+            let fresh = cached
+                .fetched_at
+                .is_some_and(|fetched_at| fetched_at.elapsed() < JWKS_MAX_AGE);
+            if let Some(key) = cached.keys.get(kid).filter(|_| fresh) {
+                return Ok(key.clone());
+            }
+        }
+
+        let mut cached = self.keys.write().await;
+        let recently_fetched = cached
+            .fetched_at
+            .is_some_and(|fetched_at| fetched_at.elapsed() < JWKS_MIN_REFRESH);
+        if !recently_fetched {
+            match self.fetch().await {
+                Ok(keys) => cached.keys = keys,
+                // Keep serving the keys we have if the endpoint is down
+                Err(e) => warn!("Failed to refresh JWKS from {}: {}", self.url, e),
+            }
+            cached.fetched_at = Some(Instant::now());
+        }
+
+        cached
+            .keys
+            .get(kid)
+            .cloned()
+            .with_context(|| format!("Unknown signing key {}", kid))
+    }
+
+    async fn fetch(&self) -> Result<HashMap<String, (DecodingKey, Algorithm)>> {
+        let set: JwkSet = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .context("Failed to fetch JWKS")?
+            .error_for_status()
+            .context("JWKS endpoint returned an error")?
+            .json()
+            .await
+            .context("Invalid JWKS document")?;
+
+        let keys = usable_keys(&set);
+        info!("Loaded {} signing keys from {}", keys.len(), self.url);
+        Ok(keys)
+    }
+}
+
+/// Keys of a JWKS that can verify tokens, by key id
+fn usable_keys(set: &JwkSet) -> HashMap<String, (DecodingKey, Algorithm)> {
+    let mut keys = HashMap::new();
+    for jwk in &set.keys {
+        let (Some(kid), Some(algorithm)) = (&jwk.common.key_id, jwk.common.key_algorithm) else {
+            continue;
+        };
+        // Only asymmetric keys; a published HMAC key would let anyone sign
+        let algorithm = match Algorithm::from_str(&algorithm.to_string()) {
+            Ok(algorithm)
+                if !matches!(
+                    algorithm,
+                    Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+                ) =>
+            {
+                algorithm
+            }
+            _ => continue,
+        };
+        match DecodingKey::from_jwk(jwk) {
+            Ok(key) => {
+                keys.insert(kid.clone(), (key, algorithm));
+            }
+            Err(e) => warn!("Skipping unusable JWKS key {}: {}", kid, e),
+        }
+    }
+    keys
+}
+
+/// Bearer token from the Authorization header, or `?access_token=` for SSE
+fn access_token(req: &ServiceRequest) -> Option<String> {
+    if let Some(header) = req.headers().get(actix_web::http::header::AUTHORIZATION) {
+        return header
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+    }
+
+    if !is_event_stream(req) {
+        return None;
+    }
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.get(ACCESS_TOKEN_PARAM).cloned())
+}
+
+/// Whether a request subscribes to `GET /compile/{job_id}/events`
+fn is_event_stream(req: &ServiceRequest) -> bool {
+    let segments: Vec<&str> = req.path().trim_matches('/').split('/').collect();
+    req.method() == Method::GET && matches!(segments.as_slice(), ["compile", _, "events"])
+}
+
+/// Middleware rejecting requests without a valid access token with 401, and
+/// making the caller available to handlers as `AuthenticatedUser`
+pub async fn require_user(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let authenticator = req
+        .app_data::<web::Data<Authenticator>>()
+        .cloned()
+        .ok_or_else(|| {
+            actix_web::error::ErrorInternalServerError("Authenticator not configured")
+        })?;
+
+    let verified = match access_token(&req) {
+        Some(token) => authenticator.verify(&token).await,
+        None => Err(anyhow::anyhow!(
+            "Send a Supabase access token as 'Authorization: Bearer <token>'"
+        )),
+    };
+
+    match verified {
+        Ok(user) => {
+            req.extensions_mut().insert(user);
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        Err(e) => {
+            warn!("Rejected unauthenticated request to {}: {}", req.path(), e);
+            let response = HttpResponse::Unauthorized().json(ApiResponse::<()>::error(
+                "UNAUTHORIZED",
+                "Missing or invalid access token",
+                Some(e.to_string()),
+            ));
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde::Serialize;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const SECRET: &[u8] = b"super-secret-jwt-token-with-at-least-32-characters";
+
+    #[derive(Serialize)]
+    struct TestClaims<'a> {
+        sub: &'a str,
+        aud: &'a str,
+        exp: u64,
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn token(header: &Header, secret: &[u8], aud: &str, exp: u64) -> String {
+        let claims = TestClaims {
+            sub: "user-1",
+            aud,
+            exp,
+        };
+        jsonwebtoken::encode(header, &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn with_secret() -> Authenticator {
+        Authenticator {
+            keys: KeySource::Secret(DecodingKey::from_secret(SECRET)),
+            audience: DEFAULT_AUDIENCE.to_string(),
+            issuer: None,
+        }
+    }
+
+    /// A JWKS authenticator whose keys were just fetched, so none are refetched
+    fn with_jwks(keys: HashMap<String, (DecodingKey, Algorithm)>) -> Authenticator {
+        Authenticator {
+            keys: KeySource::Jwks(JwksCache {
+                url: "http://127.0.0.1:9/jwks".to_string(),
+                client: reqwest::Client::new(),
+                keys: RwLock::new(CachedKeys {
+                    keys,
+                    fetched_at: Some(Instant::now()),
+                }),
+            }),
+            audience: DEFAULT_AUDIENCE.to_string(),
+            issuer: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn secret_tokens_verify() {
+        let token = token(&Header::default(), SECRET, DEFAULT_AUDIENCE, now() + 600);
+        let user = with_secret().verify(&token).await.unwrap();
+        assert_eq!(user.user_id, "user-1");
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_rejected() {
+        // Past the default 60 second leeway
+        let token = token(&Header::default(), SECRET, DEFAULT_AUDIENCE, now() - 600);
+        let err = with_secret().verify(&token).await.unwrap_err();
+        assert!(err.to_string().contains("ExpiredSignature"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn tokens_for_other_audiences_are_rejected() {
+        let token = token(&Header::default(), SECRET, "anon", now() + 600);
+        let err = with_secret().verify(&token).await.unwrap_err();
+        assert!(err.to_string().contains("InvalidAudience"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn tokens_signed_with_another_secret_are_rejected() {
+        let token = token(
+            &Header::default(),
+            b"another-secret-jwt-token-with-at-least-32-characters",
+            DEFAULT_AUDIENCE,
+            now() + 600,
+        );
+        let err = with_secret().verify(&token).await.unwrap_err();
+        assert!(err.to_string().contains("InvalidSignature"), "{}", err);
+    }
+
+    #[test]
+    fn claimed_user_must_match_the_token() {
+        let user = AuthenticatedUser {
+            user_id: "user-1".to_string(),
+        };
+        assert!(user.check_claimed(None).is_ok());
+        assert!(user.check_claimed(Some("user-1")).is_ok());
+        let err = user.check_claimed(Some("user-2")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Request is for user user-2 but the access token belongs to user-1"
+        );
+    }
+
+    #[tokio::test]
+    async fn jwks_tokens_must_use_their_keys_algorithm() {
+        // An HS256 token naming an RS256 key, e.g. signed with its public key
+        let authenticator = with_jwks(HashMap::from([(
+            "rsa".to_string(),
+            (DecodingKey::from_secret(SECRET), Algorithm::RS256),
+        )]));
+        let header = Header {
+            kid: Some("rsa".to_string()),
+            ..Header::default()
+        };
+        let token = token(&header, SECRET, DEFAULT_AUDIENCE, now() + 600);
+        let err = authenticator.verify(&token).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Access token algorithm HS256 does not match key rsa"
+        );
+    }
+
+    #[tokio::test]
+    async fn jwks_tokens_need_a_key_id() {
+        let authenticator = with_jwks(HashMap::new());
+        let token = token(&Header::default(), SECRET, DEFAULT_AUDIENCE, now() + 600);
+        let err = authenticator.verify(&token).await.unwrap_err();
+        assert_eq!(err.to_string(), "Access token has no key id");
+    }
+
+    #[test]
+    fn published_hmac_keys_are_skipped() {
+        let public_key = ed25519_dalek::SigningKey::from_bytes(&[1; 32]).verifying_key();
+        let set: JwkSet = serde_json::from_value(serde_json::json!({
+            "keys": [
+                {
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "x": URL_SAFE_NO_PAD.encode(public_key.as_bytes()),
+                    "alg": "EdDSA",
+                    "kid": "ed"
+                },
+                {
+                    "kty": "oct",
+                    "k": URL_SAFE_NO_PAD.encode(SECRET),
+                    "alg": "HS256",
+                    "kid": "hmac"
+                },
+                {
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "x": URL_SAFE_NO_PAD.encode(public_key.as_bytes()),
+                    "kid": "no-alg"
+                }
+            ]
+        }))
+        .unwrap();
+
+        let keys = usable_keys(&set);
+        assert_eq!(keys.keys().collect::<Vec<_>>(), vec!["ed"]);
+        assert_eq!(keys["ed"].1, Algorithm::EdDSA);
+    }
+
+    #[test]
+    fn query_tokens_are_only_taken_by_the_event_stream() {
+        let token_of = |request: TestRequest| access_token(&request.to_srv_request());
+
+        assert_eq!(
+            token_of(TestRequest::get().uri("/compile/job-1/events?access_token=t")),
+            Some("t".to_string())
+        );
+        assert_eq!(
+            token_of(TestRequest::get().uri("/jobs/job-1?access_token=t")),
+            None
+        );
+        assert_eq!(
+            token_of(TestRequest::post().uri("/compile/job-1/events?access_token=t")),
+            None
+        );
+        assert_eq!(
+            token_of(TestRequest::post().uri("/deploy?access_token=t")),
+            None
+        );
+        assert_eq!(
+            token_of(
+                TestRequest::get()
+                    .uri("/jobs/job-1")
+                    .insert_header(("Authorization", "Bearer header-token"))
+            ),
+            Some("header-token".to_string())
+        );
+    }
+}
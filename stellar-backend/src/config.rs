@@ -0,0 +1,15 @@
+use log::warn;
+use std::env;
+
+/// An environment variable parsed as `T`. Unset and empty variables give
+/// the default, as do invalid values, with a warning.
+pub fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) if value.is_empty() => default,
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            warn!("Invalid value {:?} for {}, using default", value, name);
+            default
+        }),
+        Err(_) => default,
+    }
+}
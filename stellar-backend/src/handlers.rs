@@ -1,8 +1,145 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{http::header, web, HttpResponse, Responder};
+use futures_util::stream::{self, StreamExt};
 use log::{error, info};
+use stellar_xdr::curr::ScAddress;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
 
-use crate::models::{ApiResponse, CompileRequest, DeployRequest, InvokeRequest};
-use crate::services::{compilation, deployment, invocation};
+use crate::models::{
+    ApiResponse, JobStatus, CompileRequest, DeployRequest, ExecuteRequest,
+    InvokeRequest, PrepareDeployRequest, PrepareInvokeRequest, SubmitTransactionRequest, TestRequest,
+    ValidateArgsRequest,
+};
+use crate::services::build_limits::{BuildControl, BuildLimits};
+use crate::services::build_stream::BuildStreams;
+use crate::services::compilation::{self, BuildEnv, ContractSources, WasmVariant};
+use crate::services::jobs::JobQueue;
+use crate::services::networks::{NetworkProfile, Networks};
+use crate::services::sandbox::Sandbox;
+use crate::services::sdk_versions::SdkVersions;
+use crate::services::invocation::CallEnv;
+use crate::services::spec_cache::SpecCache;
+use crate::services::{
+    authorization, deployment, execution, invocation, testing, transaction, wallet,
+};
+use crate::auth::AuthenticatedUser;
+use crate::rate_limit::{RateLimiter, DEPLOY_QUOTA, INVOKE_QUOTA};
+use crate::storage::{self, ProjectRef};
+
+fn forbidden(e: anyhow::Error) -> HttpResponse {
+    error!("Forbidden request: {}", e);
+    HttpResponse::Forbidden().json(ApiResponse::<()>::error(
+        "FORBIDDEN",
+        "Request does not match the authenticated user",
+        Some(e.to_string()),
+    ))
+}
+
+fn unknown_network(e: anyhow::Error) -> HttpResponse {
+    error!("Unknown network: {}", e);
+    HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+        "UNKNOWN_NETWORK",
+        "Unknown network",
+        Some(e.to_string()),
+    ))
+}
+
+fn deploy_not_allowed(network: &NetworkProfile) -> HttpResponse {
+    error!("Refused deploy to {}", network.name);
+    HttpResponse::Forbidden().json(ApiResponse::<()>::error(
+        "DEPLOY_NOT_ALLOWED",
+        "Deploying to this network is not allowed",
+        Some(format!("Network {} does not accept deploys", network.name)),
+    ))
+}
+
+fn server_signing_not_allowed(network: &NetworkProfile) -> HttpResponse {
+    error!("Refused server-signed call on {}", network.name);
+    HttpResponse::Forbidden().json(ApiResponse::<()>::error(
+        "SERVER_SIGNING_NOT_ALLOWED",
+        "The server does not sign calls on this network",
+        Some(format!(
+            "Network {} does not allow server-signed calls; sign with a wallet via /invoke/prepare",
+            network.name
+        )),
+    ))
+}
+
+fn test_signers_not_allowed(e: anyhow::Error) -> HttpResponse {
+    error!("Refused test signers: {}", e);
+    HttpResponse::Forbidden().json(ApiResponse::<()>::error(
+        "TEST_SIGNERS_NOT_ALLOWED",
+        "The requested test signers can't be used",
+        Some(e.to_string()),
+    ))
+}
+
+fn logs_build_not_allowed(network: &NetworkProfile) -> HttpResponse {
+    error!("Refused deploy with logs to {}", network.name);
+    HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+        "LOGS_BUILD_NOT_ALLOWED",
+        "The build with logs can't be deployed to this network",
+        Some(format!("Network {} only accepts release builds", network.name)),
+    ))
+}
+
+fn logs_build_failed(e: anyhow::Error) -> HttpResponse {
+    error!("Build with logs failed: {}", e);
+    HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
+        "LOGS_BUILD_ERROR",
+        "Failed to build the contract with logs",
+        Some(e.to_string()),
+    ))
+}
+
+/// What building a contract outside the compile queue takes, as a single
+/// handler argument
+type BuildServices = (
+    web::Data<SdkVersions>,
+    web::Data<BuildLimits>,
+    web::Data<dyn Sandbox>,
+    web::Data<JobQueue>,
+);
+
+/// Build the project's contract with logs if that build is missing or older
+/// than the last compile. Like a test run, the build waits for the project's
+/// other builds and a free worker slot, and is cancelled if the client leaves.
+async fn ensure_logs_build(
+    project: &ProjectRef,
+    contract: Option<&str>,
+    (sdk_versions, limits, sandbox, jobs): &BuildServices,
+) -> anyhow::Result<()> {
+    if !compilation::logs_build_stale(project, contract)? {
+        return Ok(());
+    }
+    let sdk = sdk_versions.for_project(&storage::project_dir(project)?)?;
+
+    let cancel = CancellationToken::new();
+    let _cancel_on_drop = cancel.clone().drop_guard();
+    let control = BuildControl::new(*limits.get_ref(), cancel.clone());
+    let build = compilation::build_with_logs(
+        project,
+        contract,
+        BuildEnv {
+            sdk: &sdk,
+            sandbox: sandbox.get_ref(),
+            control: &control,
+        },
+    );
+
+    jobs.run_exclusive(project, &cancel, build)
+        .await
+        .unwrap_or_else(|| Err(anyhow::anyhow!("Build with logs was cancelled before it started")))
+}
+
+fn invalid_project_id(e: anyhow::Error) -> HttpResponse {
+    error!("Invalid project reference: {}", e);
+    HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+        "INVALID_PROJECT_ID",
+        "Invalid user or project id",
+        Some(e.to_string()),
+    ))
+}
 
 pub async fn health_handler() -> impl Responder {
     HttpResponse::Ok().json(serde_json::json!({
@@ -12,42 +149,326 @@ pub async fn health_handler() -> impl Responder {
     }))
 }
 
-pub async fn compile_handler(req: web::Json<CompileRequest>) -> impl Responder {
+pub async fn sdk_versions_handler(sdk_versions: web::Data<SdkVersions>) -> impl Responder {
+    HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "versions": sdk_versions.list(),
+        "default": sdk_versions.default_version(),
+    })))
+}
+
+pub async fn networks_handler(networks: web::Data<Networks>) -> impl Responder {
+    HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "networks": networks.list(),
+        "default": networks.default_network(),
+    })))
+}
+
+pub async fn compile_handler(
+    user: AuthenticatedUser,
+    req: web::Json<CompileRequest>,
+    jobs: web::Data<JobQueue>,
+    sdk_versions: web::Data<SdkVersions>,
+) -> impl Responder {
     info!(
         "Received compile request for project {} by user {}",
-        req.project_id, req.user_id
+        req.project_id, user.user_id
     );
 
-    match compilation::compile_contract(&req.code, &req.user_id, &req.project_id).await {
-        Ok(result) => {
-            info!(
-                "Compilation completed for project {}: {}",
-                req.project_id,
-                if result.success { "success" } else { "failed" }
-            );
-            HttpResponse::Ok().json(ApiResponse::success(result))
+    if let Err(e) = user.check_claimed(req.user_id.as_deref()) {
+        return forbidden(e);
+    }
+
+    // Ids become path components, so reject anything but plain identifiers
+    let project = match ProjectRef::new(&user.user_id, &req.project_id) {
+        Ok(project) => project,
+        Err(e) => return invalid_project_id(e),
+    };
+
+    let sdk = match sdk_versions.resolve(req.sdk_version.as_deref()) {
+        Ok(sdk) => sdk,
+        Err(e) => {
+            error!("Unsupported SDK version for project {}: {}", req.project_id, e);
+            return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+                "UNSUPPORTED_SDK_VERSION",
+                "Unsupported soroban-sdk version",
+                Some(e.to_string()),
+            ));
+        }
+    };
+
+    let sources = match ContractSources::from_request(
+        req.code.as_deref(),
+        req.files.as_ref(),
+        req.contract.as_deref(),
+    ) {
+        Ok(sources) => sources,
+        Err(e) => {
+            error!("Invalid sources for project {}: {}", req.project_id, e);
+            return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+                "INVALID_SOURCE_FILES",
+                "Invalid contract source files",
+                Some(e.to_string()),
+            ));
         }
+    };
+
+    let job = match jobs.enqueue(project, req.contract.as_deref(), sources, sdk) {
+        Ok(job) => job,
         Err(e) => {
-            error!("Compilation error for project {}: {}", req.project_id, e);
+            error!("Refused compile request for project {}: {}", req.project_id, e);
+            return HttpResponse::ServiceUnavailable().json(ApiResponse::<()>::error(
+                "QUEUE_FULL",
+                "Too many compile jobs are waiting; try again shortly",
+                Some(e.to_string()),
+            ));
+        }
+    };
+    info!(
+        "Queued compile job {} for project {} at position {:?}",
+        job.job_id, req.project_id, job.queue_position
+    );
+
+    HttpResponse::Accepted().json(ApiResponse::success(job))
+}
+
+pub async fn job_status_handler(
+    user: AuthenticatedUser,
+    job_id: web::Path<String>,
+    jobs: web::Data<JobQueue>,
+) -> impl Responder {
+    match jobs.get(&job_id, &user.user_id) {
+        Some(job) => HttpResponse::Ok().json(ApiResponse::success(job)),
+        None => HttpResponse::NotFound().json(ApiResponse::<()>::error(
+            "JOB_NOT_FOUND",
+            "Compile job not found",
+            Some(format!("No job with id {}", job_id)),
+        )),
+    }
+}
+
+/// Cancel a queued or running compile job
+pub async fn cancel_job_handler(
+    user: AuthenticatedUser,
+    job_id: web::Path<String>,
+    jobs: web::Data<JobQueue>,
+) -> impl Responder {
+    match jobs.cancel(&job_id, &user.user_id) {
+        Some(job) if matches!(job.status, JobStatus::Succeeded | JobStatus::Failed) => {
+            HttpResponse::Conflict().json(ApiResponse::<()>::error(
+                "JOB_ALREADY_FINISHED",
+                "Compile job has already finished",
+                Some(format!("Job {} is {:?}", job_id, job.status)),
+            ))
+        }
+        Some(job) => HttpResponse::Ok().json(ApiResponse::success(job)),
+        None => HttpResponse::NotFound().json(ApiResponse::<()>::error(
+            "JOB_NOT_FOUND",
+            "Compile job not found",
+            Some(format!("No job with id {}", job_id)),
+        )),
+    }
+}
+
+/// Stream build output for a compile job as Server-Sent Events.
+///
+/// Replays everything published so far, then forwards new lines live until
+/// the final `result` (or `error`) event.
+pub async fn compile_events_handler(
+    user: AuthenticatedUser,
+    job_id: web::Path<String>,
+    jobs: web::Data<JobQueue>,
+    streams: web::Data<BuildStreams>,
+) -> impl Responder {
+    let job_id = job_id.into_inner();
+    if jobs.get(&job_id, &user.user_id).is_none() {
+        return HttpResponse::NotFound().json(ApiResponse::<()>::error(
+            "JOB_NOT_FOUND",
+            "Compile job not found",
+            Some(format!("No job with id {}", job_id)),
+        ));
+    }
+    info!("Client subscribed to build events for job {}", job_id);
+
+    let (history, receiver) = streams.subscribe(&job_id);
+
+    let live = stream::unfold(receiver, |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let next = if event.is_terminal() { None } else { Some(receiver) };
+                    return Some((event, next));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    error!("Build event subscriber lagged, skipped {} events", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let events = stream::iter(history)
+        .chain(live)
+        .map(|event| {
+            let payload = serde_json::to_string(&event).unwrap_or_default();
+            Ok::<_, actix_web::Error>(web::Bytes::from(format!(
+                "event: {}\ndata: {}\n\n",
+                event.name(),
+                payload
+            )))
+        });
+
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(events)
+}
+
+pub async fn test_handler(
+    user: AuthenticatedUser,
+    req: web::Json<TestRequest>,
+    sdk_versions: web::Data<SdkVersions>,
+    limits: web::Data<BuildLimits>,
+    sandbox: web::Data<dyn Sandbox>,
+    jobs: web::Data<JobQueue>,
+) -> impl Responder {
+    info!(
+        "Received test request for project {} by user {}",
+        req.project_id, user.user_id
+    );
+
+    if let Err(e) = user.check_claimed(req.user_id.as_deref()) {
+        return forbidden(e);
+    }
+
+    // Ids become path components, so reject anything but plain identifiers
+    let project = match ProjectRef::new(&user.user_id, &req.project_id) {
+        Ok(project) => project,
+        Err(e) => return invalid_project_id(e),
+    };
+
+    let sdk = match sdk_versions.resolve(req.sdk_version.as_deref()) {
+        Ok(sdk) => sdk,
+        Err(e) => {
+            error!("Unsupported SDK version for project {}: {}", req.project_id, e);
+            return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+                "UNSUPPORTED_SDK_VERSION",
+                "Unsupported soroban-sdk version",
+                Some(e.to_string()),
+            ));
+        }
+    };
+
+    if let Some(Err(e)) = req.filter.as_deref().map(testing::validate_filter) {
+        return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+            "INVALID_TEST_FILTER",
+            "Invalid test filter",
+            Some(e.to_string()),
+        ));
+    }
+
+    let sources = match ContractSources::from_request(
+        req.code.as_deref(),
+        req.files.as_ref(),
+        req.contract.as_deref(),
+    ) {
+        Ok(sources) => sources,
+        Err(e) => {
+            error!("Invalid sources for project {}: {}", req.project_id, e);
+            return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+                "INVALID_SOURCE_FILES",
+                "Invalid contract source files",
+                Some(e.to_string()),
+            ));
+        }
+    };
+
+    // Tests wait for the project's builds and a free worker slot; if the
+    // client goes away the run is cancelled and its processes killed
+    let cancel = CancellationToken::new();
+    let _cancel_on_drop = cancel.clone().drop_guard();
+    let control = BuildControl::new(*limits.get_ref(), cancel.clone());
+    let run = testing::run_contract_tests(
+        &sources,
+        &project,
+        req.contract.as_deref(),
+        req.filter.as_deref(),
+        BuildEnv {
+            sdk: &sdk,
+            sandbox: sandbox.get_ref(),
+            control: &control,
+        },
+    );
+
+    match jobs.run_exclusive(&project, &cancel, run).await.unwrap_or_else(|| {
+        Err(anyhow::anyhow!("Test run was cancelled before it started"))
+    }) {
+        Ok(result) => HttpResponse::Ok().json(ApiResponse::success(result)),
+        Err(e) => {
+            error!("Test run error for project {}: {}", req.project_id, e);
             HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
-                "COMPILATION_ERROR",
-                "Failed to compile contract",
+                "TEST_ERROR",
+                "Failed to run contract tests",
                 Some(e.to_string()),
             ))
         }
     }
 }
 
-pub async fn deploy_handler(req: web::Json<DeployRequest>) -> impl Responder {
+pub async fn deploy_handler(
+    user: AuthenticatedUser,
+    req: web::Json<DeployRequest>,
+    networks: web::Data<Networks>,
+    build: BuildServices,
+    limiter: web::Data<RateLimiter>,
+) -> impl Responder {
     info!(
         "Received deploy request for project {} by user {}",
-        req.project_id, req.user_id
+        req.project_id, user.user_id
     );
 
+    if let Err(e) = user.check_claimed(req.user_id.as_deref()) {
+        return forbidden(e);
+    }
+
+    // Ids become path components, so reject anything but plain identifiers
+    let project = match ProjectRef::new(&user.user_id, &req.project_id) {
+        Ok(project) => project,
+        Err(e) => return invalid_project_id(e),
+    };
+
+    let network = match networks.resolve(req.network.as_deref()) {
+        Ok(network) => network,
+        Err(e) => return unknown_network(e),
+    };
+    if !network.allow_deploy {
+        return deploy_not_allowed(&network);
+    }
+
+    let variant = if req.with_logs.unwrap_or(false) {
+        if network.is_mainnet() {
+            return logs_build_not_allowed(&network);
+        }
+        if let Err(e) = ensure_logs_build(&project, req.contract.as_deref(), &build).await {
+            return logs_build_failed(e);
+        }
+        WasmVariant::WithLogs
+    } else {
+        WasmVariant::Release
+    };
+
+    // Only requests that get as far as deploying count toward the quota
+    if let Err(response) = limiter.charge_quota(DEPLOY_QUOTA, &user.user_id).await {
+        return response;
+    }
+
     match deployment::deploy_contract(
-        &req.user_id,
-        &req.project_id,
-        req.account_secret.as_deref(),
+        &project,
+        req.contract.as_deref(),
+        &network,
+        variant,
+        req.constructor_args.as_ref(),
     )
     .await
     {
@@ -69,18 +490,97 @@ pub async fn deploy_handler(req: web::Json<DeployRequest>) -> impl Responder {
     }
 }
 
-pub async fn invoke_handler(req: web::Json<InvokeRequest>) -> impl Responder {
+/// Run a method of the project's compiled contract locally, without a network.
+/// The contract's build with logs is used, and built first if needed.
+pub async fn execute_handler(
+    user: AuthenticatedUser,
+    req: web::Json<ExecuteRequest>,
+    build: BuildServices,
+) -> impl Responder {
+    info!(
+        "Received execute request for project {} method {} by user {}",
+        req.project_id, req.method_name, user.user_id
+    );
+
+    if let Err(e) = user.check_claimed(req.user_id.as_deref()) {
+        return forbidden(e);
+    }
+
+    let project = match ProjectRef::new(&user.user_id, &req.project_id) {
+        Ok(project) => project,
+        Err(e) => return invalid_project_id(e),
+    };
+
+    if let Err(e) = ensure_logs_build(&project, req.contract.as_deref(), &build).await {
+        return logs_build_failed(e);
+    }
+
+    match execution::execute(
+        &project,
+        req.contract.as_deref(),
+        &req.method_name,
+        &req.args,
+        req.constructor_args.as_ref(),
+    )
+    .await
+    {
+        Ok(result) => HttpResponse::Ok().json(ApiResponse::success(result)),
+        Err(e) => {
+            error!("Execution error for project {}: {}", req.project_id, e);
+            HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
+                "EXECUTION_ERROR",
+                "Failed to execute contract method",
+                Some(e.to_string()),
+            ))
+        }
+    }
+}
+
+pub async fn invoke_handler(
+    user: AuthenticatedUser,
+    req: web::Json<InvokeRequest>,
+    networks: web::Data<Networks>,
+    spec_cache: web::Data<SpecCache>,
+    limiter: web::Data<RateLimiter>,
+) -> impl Responder {
     info!(
         "Received invoke request for contract {} method {}",
         req.contract_id, req.method_name
     );
 
+    let network = match networks.resolve(req.network.as_deref()) {
+        Ok(network) => network,
+        Err(e) => return unknown_network(e),
+    };
+    // Anything but a view is submitted and paid for by the server account
+    if req.method_type != "view" && !network.server_signing_allowed() {
+        return server_signing_not_allowed(&network);
+    }
+    let test_signers = match authorization::requested_signers(
+        &network,
+        req.test_signers.as_deref().unwrap_or_default(),
+    ) {
+        Ok(signers) => signers,
+        Err(e) => return test_signers_not_allowed(e),
+    };
+    // Views are only simulated, so only other calls count toward the quota
+    if req.method_type != "view" {
+        if let Err(response) = limiter.charge_quota(INVOKE_QUOTA, &user.user_id).await {
+            return response;
+        }
+    }
+
     match invocation::invoke_contract(
         &req.contract_id,
         &req.method_name,
         &req.args,
         &req.method_type,
-        req.source_account.as_deref(),
+        CallEnv {
+            network: &network,
+            spec_cache: &spec_cache,
+        },
+        req.auth.as_deref().unwrap_or_default(),
+        &test_signers,
     )
     .await
     {
@@ -111,3 +611,226 @@ pub async fn invoke_handler(req: web::Json<InvokeRequest>) -> impl Responder {
         }
     }
 }
+
+pub async fn prepare_deploy_handler(
+    user: AuthenticatedUser,
+    req: web::Json<PrepareDeployRequest>,
+    networks: web::Data<Networks>,
+    build: BuildServices,
+) -> impl Responder {
+    info!(
+        "Received deploy prepare request for project {} by user {}",
+        req.project_id, user.user_id
+    );
+
+    if let Err(e) = user.check_claimed(req.user_id.as_deref()) {
+        return forbidden(e);
+    }
+
+    // Ids become path components, so reject anything but plain identifiers
+    let project = match ProjectRef::new(&user.user_id, &req.project_id) {
+        Ok(project) => project,
+        Err(e) => return invalid_project_id(e),
+    };
+
+    let network = match networks.resolve(req.network.as_deref()) {
+        Ok(network) => network,
+        Err(e) => return unknown_network(e),
+    };
+    if !network.allow_deploy {
+        return deploy_not_allowed(&network);
+    }
+
+    let variant = if req.with_logs.unwrap_or(false) {
+        if network.is_mainnet() {
+            return logs_build_not_allowed(&network);
+        }
+        if let Err(e) = ensure_logs_build(&project, req.contract.as_deref(), &build).await {
+            return logs_build_failed(e);
+        }
+        WasmVariant::WithLogs
+    } else {
+        WasmVariant::Release
+    };
+
+    match wallet::prepare_deploy(
+        &project,
+        req.contract.as_deref(),
+        &req.public_key,
+        &network,
+        variant,
+        req.constructor_args.as_ref(),
+    )
+    .await
+    {
+        Ok(prepared) => HttpResponse::Ok().json(ApiResponse::success(prepared)),
+        Err(e) => {
+            error!("Deploy prepare error for project {}: {}", req.project_id, e);
+            HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
+                "PREPARE_ERROR",
+                "Failed to prepare deploy transaction",
+                Some(e.to_string()),
+            ))
+        }
+    }
+}
+
+pub async fn prepare_invoke_handler(
+    _user: AuthenticatedUser,
+    req: web::Json<PrepareInvokeRequest>,
+    networks: web::Data<Networks>,
+    spec_cache: web::Data<SpecCache>,
+) -> impl Responder {
+    info!(
+        "Received invoke prepare request for contract {} method {}",
+        req.contract_id, req.method_name
+    );
+
+    let network = match networks.resolve(req.network.as_deref()) {
+        Ok(network) => network,
+        Err(e) => return unknown_network(e),
+    };
+    let test_signers = match authorization::requested_signers(
+        &network,
+        req.test_signers.as_deref().unwrap_or_default(),
+    ) {
+        Ok(signers) => signers,
+        Err(e) => return test_signers_not_allowed(e),
+    };
+
+    match wallet::prepare_invoke(
+        &req.contract_id,
+        &req.method_name,
+        &req.args,
+        &req.public_key,
+        CallEnv {
+            network: &network,
+            spec_cache: &spec_cache,
+        },
+        req.auth.as_deref().unwrap_or_default(),
+        &test_signers,
+    )
+    .await
+    {
+        Ok(prepared) => HttpResponse::Ok().json(ApiResponse::success(prepared)),
+        Err(e) => {
+            error!(
+                "Invoke prepare error for contract {} method {}: {}",
+                req.contract_id, req.method_name, e
+            );
+            HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
+                "PREPARE_ERROR",
+                "Failed to prepare contract call",
+                Some(e.to_string()),
+            ))
+        }
+    }
+}
+
+/// Addresses the server signs authorization entries for, to use as
+/// arguments of methods that `require_auth`
+pub async fn test_signers_handler(_user: AuthenticatedUser) -> impl Responder {
+    match authorization::test_signers() {
+        Ok(signers) => HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+            "signers": signers.iter().map(|signer| signer.address()).collect::<Vec<_>>(),
+        }))),
+        Err(e) => {
+            error!("Failed to load test signers: {}", e);
+            HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
+                "SIGNER_CONFIG_ERROR",
+                "Test signers are misconfigured",
+                Some(e.to_string()),
+            ))
+        }
+    }
+}
+
+pub async fn submit_transaction_handler(
+    _user: AuthenticatedUser,
+    req: web::Json<SubmitTransactionRequest>,
+    networks: web::Data<Networks>,
+    spec_cache: web::Data<SpecCache>,
+) -> impl Responder {
+    let network = match networks.resolve(req.network.as_deref()) {
+        Ok(network) => network,
+        Err(e) => return unknown_network(e),
+    };
+
+    // Only relay the kind of transaction the prepare endpoints produce
+    let (envelope, host_function) = match transaction::decode_envelope(&req.xdr) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            error!("Invalid signed transaction: {}", e);
+            return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+                "INVALID_TRANSACTION",
+                "Invalid signed transaction",
+                Some(e.to_string()),
+            ));
+        }
+    };
+    if wallet::is_deploy(&host_function) && !network.allow_deploy {
+        return deploy_not_allowed(&network);
+    }
+
+    match wallet::submit_signed(&envelope, &host_function, &network, &spec_cache).await {
+        Ok(result) => {
+            info!(
+                "Submitted signed transaction {} on {}: success={}",
+                result.transaction_hash, network.name, result.success
+            );
+            HttpResponse::Ok().json(ApiResponse::success(result))
+        }
+        Err(e) => {
+            error!("Submit error on {}: {}", network.name, e);
+            HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
+                "SUBMIT_ERROR",
+                "Failed to submit transaction",
+                Some(e.to_string()),
+            ))
+        }
+    }
+}
+
+pub async fn validate_args_handler(
+    _user: AuthenticatedUser,
+    path: web::Path<String>,
+    req: web::Json<ValidateArgsRequest>,
+    networks: web::Data<Networks>,
+    spec_cache: web::Data<SpecCache>,
+) -> impl Responder {
+    let contract_id = path.into_inner();
+
+    let network = match networks.resolve(req.network.as_deref()) {
+        Ok(network) => network,
+        Err(e) => return unknown_network(e),
+    };
+    let contract = match contract_id.parse::<ScAddress>() {
+        Ok(contract @ ScAddress::Contract(_)) => contract,
+        _ => {
+            return HttpResponse::BadRequest().json(ApiResponse::<()>::error(
+                "INVALID_CONTRACT_ID",
+                "Invalid contract id",
+                Some(format!("{} is not a C... contract address", contract_id)),
+            ))
+        }
+    };
+
+    match spec_cache.get(&network, &contract).await {
+        Ok(interface) => {
+            let result =
+                invocation::validate_args(&interface, &contract_id, &req.method_name, &req.args);
+            HttpResponse::Ok().json(ApiResponse::success(result))
+        }
+        Err(e) => {
+            error!(
+                "Failed to load interface of {} on {}: {}",
+                contract_id, network.name, e
+            );
+            HttpResponse::InternalServerError().json(ApiResponse::<()>::error(
+                "SPEC_ERROR",
+                "Failed to load the contract interface",
+                Some(e.to_string()),
+            ))
+        }
+    }
+}
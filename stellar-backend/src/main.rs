@@ -1,20 +1,38 @@
 use actix_cors::Cors;
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::{middleware::{from_fn, Logger}, web, App, HttpServer};
 use env_logger::Env;
-use log::{info, error};
-use std::path::Path;
+use log::{info, error, warn};
 use std::process::Command;
 use std::env;
 
+mod auth;
+mod config;
 mod handlers;
 mod models;
+mod rate_limit;
 mod services;
-mod utils;
-
-use handlers::{compile_handler, deploy_handler, health_handler, invoke_handler};
+mod storage;
+
+use handlers::{
+    compile_events_handler, compile_handler, deploy_handler, health_handler, invoke_handler,
+    cancel_job_handler, job_status_handler, networks_handler, prepare_deploy_handler,
+    prepare_invoke_handler, sdk_versions_handler, submit_transaction_handler, test_handler,
+    test_signers_handler, validate_args_handler, execute_handler,
+};
+use auth::Authenticator;
+use rate_limit::{MemoryStore, RateLimiter};
+use services::build_limits::BuildLimits;
+use services::build_stream::BuildStreams;
+use services::jobs::JobQueue;
+use services::networks::{NetworkProfile, Networks};
+use services::sandbox;
+use services::sdk_versions::SdkVersions;
+use services::soroban_rpc::RpcClient;
+use services::spec_cache::SpecCache;
+use std::sync::Arc;
 
 async fn initialize_base_project() -> std::io::Result<()> {
-    let base_project_path = Path::new("base_project");
+    let base_project_path = storage::base_project_dir();
 
     if !base_project_path.exists() {
         info!("Base project not found, creating it...");
@@ -52,10 +70,10 @@ async fn initialize_base_project() -> std::io::Result<()> {
     // wasm32-unknown-unknown is kept for backward compatibility
     info!("Checking Rust wasm32 targets...");
     let _ = Command::new("rustup")
-        .args(&["target", "add", "wasm32v1-none"])
+        .args(["target", "add", "wasm32v1-none"])
         .output();
     let _ = Command::new("rustup")
-        .args(&["target", "add", "wasm32-unknown-unknown"])
+        .args(["target", "add", "wasm32-unknown-unknown"])
         .output();
 
     info!("Base project initialized and Stellar CLI verified");
@@ -63,6 +81,22 @@ async fn initialize_base_project() -> std::io::Result<()> {
     Ok(())
 }
 
+/// Log whether a network's Soroban RPC endpoint is reachable. Not fatal;
+/// only deploy and invoke need it.
+async fn check_rpc(network: &NetworkProfile) {
+    let rpc_url = &network.rpc_url;
+    match RpcClient::new(rpc_url) {
+        Ok(rpc) => match rpc.get_latest_ledger().await {
+            Ok(ledger) => info!(
+                "Soroban RPC for {} ({}) reachable at ledger {} (protocol {})",
+                network.name, rpc_url, ledger.sequence, ledger.protocol_version
+            ),
+            Err(e) => warn!("Soroban RPC for {} unreachable: {}", network.name, e),
+        },
+        Err(e) => warn!("Soroban RPC for {} unusable: {}", network.name, e),
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Load environment variables from .env file
@@ -82,7 +116,48 @@ async fn main() -> std::io::Result<()> {
 
     info!("Starting Stellar Playground Backend on {}", bind_address);
 
-    HttpServer::new(|| {
+    // Compile jobs are drained by a bounded worker pool; test runs share its limit
+    let concurrency = config::env_or("COMPILE_CONCURRENCY", 2usize);
+    let queue_capacity = config::env_or("COMPILE_QUEUE_CAPACITY", 100usize);
+    let build_limits = BuildLimits::from_env();
+    let sandbox = sandbox::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::NotFound, e.to_string()))?;
+    let build_streams = Arc::new(BuildStreams::new());
+    let job_queue = web::Data::from(JobQueue::start(
+        build_streams.clone(),
+        build_limits,
+        sandbox.clone(),
+        concurrency,
+        queue_capacity,
+    ));
+    let sandbox = web::Data::from(sandbox);
+    let build_limits = web::Data::new(build_limits);
+    let build_streams = web::Data::from(build_streams);
+
+    // Dependency caches for each supported soroban-sdk version are filled in
+    // the background; builds before that finish just download what they need
+    let sdk_versions = Arc::new(SdkVersions::from_env());
+    tokio::spawn({
+        let sdk_versions = sdk_versions.clone();
+        async move { sdk_versions.prewarm().await }
+    });
+    let sdk_versions = web::Data::from(sdk_versions);
+
+    let networks = Networks::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{:#}", e)))?;
+    if let Ok(network) = networks.resolve(None) {
+        tokio::spawn(async move { check_rpc(&network).await });
+    }
+    let networks = web::Data::new(networks);
+    let spec_cache = web::Data::new(SpecCache::from_env());
+
+    let authenticator = web::Data::new(
+        Authenticator::from_env()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?,
+    );
+    let rate_limiter = web::Data::new(RateLimiter::from_env(Arc::new(MemoryStore::default())));
+
+    HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin("https://stellarplay.app")
             .allowed_origin("https://www.stellarplay.app")
@@ -100,12 +175,48 @@ async fn main() -> std::io::Result<()> {
             .supports_credentials();
 
         App::new()
+            .wrap(from_fn(rate_limit::limit_ip))
             .wrap(cors)
-            .wrap(Logger::default())
+            // Like the default format, but without the query string, which
+            // may carry an access token
+            .wrap(
+                Logger::new(r#"%a "%{method}xi %U" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+                    .custom_request_replace("method", |req| req.method().to_string()),
+            )
+            .app_data(build_streams.clone())
+            .app_data(job_queue.clone())
+            .app_data(sdk_versions.clone())
+            .app_data(build_limits.clone())
+            .app_data(sandbox.clone())
+            .app_data(authenticator.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(networks.clone())
+            .app_data(spec_cache.clone())
             .route("/health", web::get().to(health_handler))
-            .route("/compile", web::post().to(compile_handler))
-            .route("/deploy", web::post().to(deploy_handler))
-            .route("/invoke", web::post().to(invoke_handler))
+            .route("/sdk-versions", web::get().to(sdk_versions_handler))
+            .route("/networks", web::get().to(networks_handler))
+            // Everything else requires a Supabase access token
+            .service(
+                web::scope("")
+                    .wrap(from_fn(rate_limit::limit_user))
+                    .wrap(from_fn(auth::require_user))
+                    .route("/compile", web::post().to(compile_handler))
+                    .route("/compile/{job_id}/events", web::get().to(compile_events_handler))
+                    .route("/jobs/{job_id}", web::get().to(job_status_handler))
+                    .route("/jobs/{job_id}", web::delete().to(cancel_job_handler))
+                    .route("/test", web::post().to(test_handler))
+                    .route("/deploy", web::post().to(deploy_handler))
+                    .route("/invoke", web::post().to(invoke_handler))
+                    .route("/execute", web::post().to(execute_handler))
+                    .route("/deploy/prepare", web::post().to(prepare_deploy_handler))
+                    .route("/invoke/prepare", web::post().to(prepare_invoke_handler))
+                    .route("/transactions/submit", web::post().to(submit_transaction_handler))
+                    .route("/auth/test-signers", web::get().to(test_signers_handler))
+                    .route(
+                        "/contracts/{contract_id}/validate-args",
+                        web::post().to(validate_args_handler),
+                    ),
+            )
     })
     .bind(&bind_address)?
     .run()
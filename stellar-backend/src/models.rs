@@ -1,19 +1,48 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 
 // Request Models
 #[derive(Debug, Deserialize)]
 pub struct CompileRequest {
-    pub user_id: String,
+    pub user_id: Option<String>, // Taken from the access token; must match it if sent
     pub project_id: String,
-    pub code: String,
+    pub code: Option<String>, // Shorthand for files["src/lib.rs"]
+    pub files: Option<BTreeMap<String, String>>, // Path -> contents, e.g. "src/test.rs" or "contracts/token/src/lib.rs"
+    pub contract: Option<String>, // Only build this contract; crate-relative files go here
+    pub sdk_version: Option<String>, // soroban-sdk version from the server allowlist; default if omitted
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TestRequest {
+    pub user_id: Option<String>,
+    pub project_id: String,
+    pub code: Option<String>, // Shorthand for files["src/lib.rs"]
+    pub files: Option<BTreeMap<String, String>>,
+    pub contract: Option<String>, // Only test this contract; crate-relative files go here
+    pub filter: Option<String>, // Only run tests whose name contains this
+    pub sdk_version: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DeployRequest {
-    pub user_id: String,
+    pub user_id: Option<String>,
     pub project_id: String,
-    pub account_secret: Option<String>, // Optional - we can use default account
+    pub contract: Option<String>, // Which contract to deploy when the project has several
+    pub network: Option<String>, // Network profile name; the server default if omitted
+    pub constructor_args: Option<serde_json::Value>, // For contracts with a __constructor
+    pub with_logs: Option<bool>, // Deploy the release-with-logs build, so `log!` output shows in calls; not on mainnet
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrepareDeployRequest {
+    pub user_id: Option<String>,
+    pub project_id: String,
+    pub contract: Option<String>,
+    pub network: Option<String>,
+    pub public_key: String, // G... address of the wallet that will sign
+    pub constructor_args: Option<serde_json::Value>, // Used by the create step
+    pub with_logs: Option<bool>, // Must be the same for both steps
 }
 
 #[derive(Debug, Deserialize)]
@@ -22,11 +51,55 @@ pub struct InvokeRequest {
     pub method_name: String,
     pub args: serde_json::Value, // JSON args
     pub method_type: String, // "view" or "call"
-    pub source_account: Option<String>, // Optional - for signing transactions
+    pub network: Option<String>, // Network profile name; the server default if omitted
+    pub auth: Option<Vec<SignedAuthEntry>>, // Entries from an earlier response, signed
+    pub test_signers: Option<Vec<String>>, // G... test signers the server should sign for
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrepareInvokeRequest {
+    pub contract_id: String,
+    pub method_name: String,
+    pub args: serde_json::Value,
+    pub network: Option<String>,
+    pub public_key: String, // G... address of the wallet that will sign
+    pub auth: Option<Vec<SignedAuthEntry>>,
+    pub test_signers: Option<Vec<String>>,
+}
+
+/// An authorization entry a client signed
+#[derive(Debug, Deserialize)]
+pub struct SignedAuthEntry {
+    pub xdr: String, // The entry as returned in `auth`, base64
+    pub signature: Option<String>, // Hex ed25519 signature of its hash; omit if the entry carries one
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitTransactionRequest {
+    pub xdr: String, // Signed TransactionEnvelope, base64
+    pub network: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateArgsRequest {
+    pub method_name: String,
+    pub args: serde_json::Value, // Same shape as for /invoke
+    pub network: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExecuteRequest {
+    pub user_id: Option<String>,
+    pub project_id: String,
+    pub contract: Option<String>, // Which contract to run when the project has several
+    pub method_name: String,
+    pub args: serde_json::Value, // Same shape as for /invoke
+    pub constructor_args: Option<serde_json::Value>, // For contracts with a __constructor
 }
 
 // Response Models
-#[derive(Debug, Serialize)]
+
+#[derive(Debug, Clone, Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub message: String,
@@ -34,49 +107,327 @@ pub struct ApiResponse<T> {
     pub error: Option<ApiError>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ApiError {
     pub code: String,
     pub message: String,
     pub details: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CompileResponse {
     pub success: bool,
     pub exit_code: i32,
     pub stdout: String,
     pub stderr: String,
     pub details: CompileDetails,
-    pub spec: Option<serde_json::Value>, // Contract spec (like ABI)
+    pub spec: Option<ContractSpec>, // Contract spec (like ABI)
+    pub diagnostics: Vec<Diagnostic>,
+    pub artifacts: Vec<ContractArtifact>, // One per successfully built contract
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractArtifact {
+    pub name: String,
+    pub wasm_size: u64,
+    pub wasm_hash: String, // Hex SHA-256 of the wasm, as used on-chain
+    pub spec: Option<ContractSpec>,
+}
+
+// Contract Spec Models
+// Typed view of the contractspecv0, contractmetav0 and contractenvmetav0
+// wasm custom sections
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContractSpec {
+    pub functions: Vec<SpecFunction>,
+    pub structs: Vec<SpecStruct>,
+    pub unions: Vec<SpecUnion>,
+    pub enums: Vec<SpecEnum>,
+    pub error_enums: Vec<SpecEnum>,
+    pub events: Vec<SpecEvent>,
+    pub meta: BTreeMap<String, String>, // contractmetav0 key/value pairs
+    pub env_meta: Option<SpecEnvMeta>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecEnvMeta {
+    pub protocol: u32,
+    pub pre_release: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecFunction {
+    pub name: String,
+    pub doc: String,
+    pub inputs: Vec<SpecField>,
+    pub outputs: Vec<SpecType>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecField {
+    pub name: String,
+    pub doc: String,
+    #[serde(rename = "type")]
+    pub type_def: SpecType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecStruct {
+    pub name: String,
+    pub doc: String,
+    pub lib: String,
+    pub fields: Vec<SpecField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecUnion {
+    pub name: String,
+    pub doc: String,
+    pub lib: String,
+    pub cases: Vec<SpecUnionCase>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecUnionCase {
+    pub name: String,
+    pub doc: String,
+    pub values: Vec<SpecType>, // Empty for unit variants
+}
+
+// Shared by #[contracttype] integer enums and #[contracterror] enums
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecEnum {
+    pub name: String,
+    pub doc: String,
+    pub lib: String,
+    pub cases: Vec<SpecEnumCase>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecEnumCase {
+    pub name: String,
+    pub doc: String,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecEvent {
+    pub name: String,
+    pub doc: String,
+    pub lib: String,
+    pub prefix_topics: Vec<String>,
+    pub params: Vec<SpecEventParam>,
+    pub data_format: SpecEventDataFormat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpecEventParam {
+    pub name: String,
+    pub doc: String,
+    #[serde(rename = "type")]
+    pub type_def: SpecType,
+    pub location: SpecEventParamLocation,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpecEventParamLocation {
+    Data,
+    TopicList,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpecEventDataFormat {
+    SingleValue,
+    Vec,
+    Map,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SpecType {
+    Val,
+    Bool,
+    Void,
+    Error,
+    U32,
+    I32,
+    U64,
+    I64,
+    Timepoint,
+    Duration,
+    U128,
+    I128,
+    U256,
+    I256,
+    Bytes,
+    String,
+    Symbol,
+    Address,
+    MuxedAddress,
+    Option { value: Box<SpecType> },
+    Result { ok: Box<SpecType>, error: Box<SpecType> },
+    Vec { element: Box<SpecType> },
+    Map { key: Box<SpecType>, value: Box<SpecType> },
+    Tuple { elements: Vec<SpecType> },
+    BytesN { n: u32 },
+    Udt { name: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub level: String, // "error", "warning", "note", "help", ...
+    pub code: Option<String>, // e.g. "E0308"
+    pub message: String,
+    pub contract: Option<String>, // Contract directory the primary span is in
+    pub file: Option<String>, // Relative to the contract crate, e.g. "src/lib.rs"
+    pub spans: Vec<DiagnosticSpan>,
+    pub suggestions: Vec<DiagnosticSuggestion>,
+    pub notes: Vec<String>,
+    pub rendered: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticSpan {
+    pub contract: Option<String>,
+    pub file: String,
+    pub line_start: u32,
+    pub line_end: u32,
+    pub column_start: u32, // 1-based, like rustc
+    pub column_end: u32,
+    pub is_primary: bool,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticSuggestion {
+    pub message: String,
+    pub span: DiagnosticSpan,
+    pub replacement: String,
+    pub applicability: Option<String>, // "MachineApplicable", "MaybeIncorrect", ...
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct CompileDetails {
     pub status: String,
     pub compilation_time: f64,
     pub project_path: String,
     pub wasm_size: Option<u64>,
     pub optimized: bool,
+    pub sdk_version: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestResponse {
+    pub success: bool,
+    pub exit_code: i32,
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    pub filtered_out: u32,
+    pub total_time: f64, // Wall clock seconds, including the build
+    pub tests: Vec<TestCaseResult>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestCaseResult {
+    pub name: String,
+    pub status: TestStatus,
+    pub exec_time: Option<f64>, // Seconds
+    pub stdout: Option<String>,
+    pub panic_message: Option<String>,
+}
+
+// Job Models
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobInfo {
+    pub job_id: String,
+    pub status: JobStatus,
+    pub queue_position: Option<usize>, // 1-based, only while queued
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub result: Option<CompileResponse>,
+    pub error: Option<ApiError>,
+}
+
+// Build Event Models
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum BuildEvent {
+    Output { stream: OutputStream, line: String },
+    Result(Box<ApiResponse<CompileResponse>>),
+    Error(ApiResponse<()>),
+}
+
+impl BuildEvent {
+    /// SSE event name for this event
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuildEvent::Output { .. } => "output",
+            BuildEvent::Result(_) => "result",
+            BuildEvent::Error(_) => "error",
+        }
+    }
+
+    /// Result and error events end the stream
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self, BuildEvent::Output { .. })
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct DeployResponse {
     pub success: bool,
-    pub transaction_hash: String,
+    pub transaction_hash: String, // The transaction that created the contract
     pub contract_id: String,
     pub explorer_url: String,
-    pub fee: Option<String>,
-    pub proof_tx_hash: Option<String>,
+    pub fee: Option<String>, // Total of both transactions, in stroops
+    pub wasm_hash: String, // Hex SHA-256 of the uploaded wasm
+    pub upload_transaction_hash: Option<String>, // None when the wasm was already uploaded
     pub details: DeployDetails,
 }
 
 #[derive(Debug, Serialize)]
 pub struct DeployDetails {
     pub network: String,
-    pub ledger_sequence: u32,
+    pub ledger_sequence: u32, // Ledger the contract was created in
     pub timestamp: DateTime<Utc>,
     pub deployer_address: String,
+    pub salt: String, // Hex salt the contract id was derived from
+    pub upload_fee: Option<String>,
+    pub create_fee: Option<String>,
+    pub upload_cost: Option<TransactionCost>,
+    pub create_cost: Option<TransactionCost>,
 }
 
 #[derive(Debug, Serialize)]
@@ -87,7 +438,168 @@ pub struct InvokeResponse {
     pub logs: Vec<String>,
     pub fee: Option<String>,
     pub error: Option<String>,
+    pub failure: Option<ContractFailure>, // Decoded cause, when the call failed with an error
     pub raw_output: Option<String>,
+    pub events: Vec<ContractEventEntry>, // `logs` has one line for each
+    pub cost: Option<TransactionCost>, // Missing if the call failed before it was simulated
+    pub auth: Vec<AuthEntry>, // Authorizations the call needs
+}
+
+/// An authorization a call needs, from its source account or another address
+#[derive(Debug, Serialize)]
+pub struct AuthEntry {
+    pub address: Option<String>, // None when the transaction's own signature covers it
+    pub invocation: String, // The authorized call, e.g. "CA...XYZ.transfer"
+    pub signed: bool,
+    pub signature_expiration_ledger: Option<u32>,
+    pub xdr: String, // SorobanAuthorizationEntry, base64
+    pub preimage: Option<String>, // HashIdPreimage to sign, base64, as wallets' signAuthEntry takes
+    pub hash: Option<String>, // Hex SHA-256 of the preimage, what the signature covers
+}
+
+/// Resources a transaction uses and what they cost
+#[derive(Debug, Serialize)]
+pub struct TransactionCost {
+    pub resources: ResourceUsage,
+    pub fees: FeeBreakdown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResourceUsage {
+    pub cpu_instructions: u64, // Measured in simulation, else as declared
+    pub memory_bytes: Option<u64>, // Only known from simulation
+    pub read_entries: u32, // Ledger entries in the footprint, including written ones
+    pub write_entries: u32,
+    pub read_bytes: u32, // Bytes read from disk
+    pub write_bytes: u32,
+    pub events_bytes: u32, // Contract events plus the return value
+}
+
+/// Fees in stroops. The resource fee is only split into its parts once the
+/// transaction is applied.
+#[derive(Debug, Serialize)]
+pub struct FeeBreakdown {
+    pub inclusion_fee: String, // Bid for inclusion in a ledger
+    pub resource_fee: String, // Maximum charged for resources
+    pub non_refundable_fee: Option<String>, // CPU, read/write, bandwidth
+    pub refundable_fee: Option<String>, // Rent and events; unused parts are refunded
+    pub rent_fee: Option<String>, // Share of the refundable fee paid as rent
+    pub fee_charged: Option<String>, // Total actually charged
+}
+
+/// An event recorded while simulating or applying a call
+#[derive(Debug, Serialize)]
+pub struct ContractEventEntry {
+    pub event_type: String, // "contract", "system" or "diagnostic"
+    pub contract_id: Option<String>,
+    pub topics: Vec<serde_json::Value>,
+    pub data: serde_json::Value,
+    pub in_successful_call: bool, // False if the call that emitted it was rolled back
+}
+
+/// Why a contract call failed
+#[derive(Debug, Serialize)]
+pub struct ContractFailure {
+    pub error_type: String, // "Contract" for the contract's own errors, else a host category such as "Budget" or "Auth"
+    pub code: u32,
+    pub name: Option<String>, // Contract errors: the error enum variant; host errors: the code, e.g. "ExceededLimit"
+    pub doc: Option<String>, // Doc comment of the error enum variant
+    pub explanation: String,
+}
+
+/// Outcome of running a call in a local, in-memory environment
+#[derive(Debug, Serialize)]
+pub struct ExecuteResponse {
+    pub success: bool,
+    pub contract_id: Option<String>, // Address the contract got locally; None if creation failed
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub failure: Option<ContractFailure>,
+    pub logs: Vec<String>,
+    pub events: Vec<ContractEventEntry>,
+    pub budget: BudgetUsage,
+    pub storage: Vec<StorageEntry>, // Contract data after the call
+}
+
+/// CPU and memory the call consumed, out of the host's per-transaction limits
+#[derive(Debug, Serialize)]
+pub struct BudgetUsage {
+    pub cpu_instructions: u64,
+    pub cpu_limit: u64,
+    pub memory_bytes: u64,
+    pub memory_limit: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageEntry {
+    pub contract_id: String,
+    pub durability: String, // "instance", "persistent" or "temporary"
+    pub key: serde_json::Value,
+    pub value: serde_json::Value,
+    pub live_until_ledger: Option<u32>, // Instance entries share the instance's
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidateArgsResponse {
+    pub valid: bool,
+    pub contract_id: String,
+    pub method_name: String,
+    pub parameters: Vec<ParameterInfo>, // Empty if the method does not exist
+    pub errors: Vec<ArgError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParameterInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String, // Written as in Rust, e.g. "Option<Vec<Address>>"
+}
+
+/// An argument that does not fit its spec type
+#[derive(Debug, Clone, Serialize)]
+pub struct ArgError {
+    pub path: String, // e.g. "order.items[2].price"; empty for the call as a whole
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreparedTransaction {
+    pub xdr: String, // Unsigned TransactionEnvelope, base64, ready for the wallet
+    pub hash: String, // Hex hash the wallet's signature covers
+    pub network: String,
+    pub network_passphrase: String,
+    pub fee: String, // Total fee bid, in stroops
+    pub step: Option<String>, // Deploys: "upload", then "create"
+    pub wasm_hash: Option<String>, // Deploys: hex SHA-256 of the contract wasm
+    pub result: Option<serde_json::Value>, // Simulated return value
+    pub logs: Vec<String>,
+    pub events: Vec<ContractEventEntry>,
+    pub cost: Option<TransactionCost>,
+    pub auth: Vec<AuthEntry>, // Submittable once every entry is signed
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubmitResponse {
+    pub success: bool,
+    pub transaction_hash: String,
+    pub ledger_sequence: Option<u32>,
+    pub result: Option<serde_json::Value>, // Return value; the contract id after a create
+    pub fee: Option<String>,
+    pub error: Option<String>,
+    pub failure: Option<ContractFailure>,
+    pub logs: Vec<String>,
+    pub events: Vec<ContractEventEntry>,
+    pub cost: Option<TransactionCost>,
+}
+
+impl std::fmt::Display for ArgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
 }
 
 impl<T> ApiResponse<T> {
@@ -0,0 +1,508 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpResponse};
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::auth::AuthenticatedUser;
+use crate::config::env_or;
+use crate::models::ApiResponse;
+
+const DEFAULT_WINDOW_MS: u64 = 15 * 60 * 1000;
+const DEFAULT_MAX_REQUESTS: u64 = 100;
+// Generous, since the frontend polls job status every second
+const DEFAULT_IP_MAX_REQUESTS: u64 = 1000;
+const DEFAULT_DEPLOY_DAILY_QUOTA: u64 = 20;
+const DEFAULT_INVOKE_DAILY_QUOTA: u64 = 500;
+
+// Quota windows are aligned to the epoch, so daily quotas reset at 00:00 UTC
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Routes with their own per-user limit, and the variable overriding it
+const ENDPOINT_LIMITS: &[(&str, &str)] = &[
+    ("POST /compile", "RATE_LIMIT_COMPILE_MAX_REQUESTS"),
+    ("POST /test", "RATE_LIMIT_TEST_MAX_REQUESTS"),
+    ("POST /deploy", "RATE_LIMIT_DEPLOY_MAX_REQUESTS"),
+    ("POST /invoke", "RATE_LIMIT_INVOKE_MAX_REQUESTS"),
+    ("POST /execute", "RATE_LIMIT_EXECUTE_MAX_REQUESTS"),
+    ("POST /deploy/prepare", "RATE_LIMIT_DEPLOY_MAX_REQUESTS"),
+    ("POST /invoke/prepare", "RATE_LIMIT_INVOKE_MAX_REQUESTS"),
+    ("POST /transactions/submit", "RATE_LIMIT_SUBMIT_MAX_REQUESTS"),
+];
+
+// Routes spending the server's funded accounts, limited per user per day
+const DAILY_QUOTAS: &[(&str, &str, u64)] = &[
+    (
+        DEPLOY_QUOTA,
+        "DEPLOY_DAILY_QUOTA",
+        DEFAULT_DEPLOY_DAILY_QUOTA,
+    ),
+    (
+        INVOKE_QUOTA,
+        "INVOKE_DAILY_QUOTA",
+        DEFAULT_INVOKE_DAILY_QUOTA,
+    ),
+];
+
+// Quotas charged by the handler rather than the middleware, since only some
+// requests to the route spend funds: /deploy only once the request is valid,
+// /invoke only for non-view calls
+pub const DEPLOY_QUOTA: &str = "POST /deploy";
+pub const INVOKE_QUOTA: &str = "POST /invoke";
+const HANDLER_QUOTAS: &[&str] = &[DEPLOY_QUOTA, INVOKE_QUOTA];
+
+// Never limited, so health checks keep working under load
+const EXEMPT_PATHS: &[&str] = &["/health"];
+
+/// Where hit counters live. The in-memory store only limits a single
+/// instance; a shared backend (e.g. Redis) can implement this to limit
+/// across replicas.
+pub trait RateLimitStore: Send + Sync {
+    /// Count a hit against `key` and return the number of hits so far.
+    /// Keys are unique per window; the store may forget them after `ttl`.
+    fn increment<'a>(&'a self, key: &'a str, ttl: Duration) -> BoxFuture<'a, Result<u64>>;
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    counters: Mutex<MemoryCounters>,
+}
+
+#[derive(Default)]
+struct MemoryCounters {
+    hits: HashMap<String, (u64, Instant)>, // Key -> (count, expiry)
+    last_pruned: Option<Instant>,
+}
+
+impl RateLimitStore for MemoryStore {
+    fn increment<'a>(&'a self, key: &'a str, ttl: Duration) -> BoxFuture<'a, Result<u64>> {
+        let mut counters = self.counters.lock().unwrap();
+        let now = Instant::now();
+
+        if counters
+            .last_pruned
+            .is_none_or(|last_pruned| now - last_pruned > Duration::from_secs(60))
+        {
+            counters.hits.retain(|_, (_, expiry)| *expiry > now);
+            counters.last_pruned = Some(now);
+        }
+
+        let (count, _) = counters
+            .hits
+            .entry(key.to_string())
+            .or_insert((0, now + ttl));
+        *count += 1;
+        let count = *count;
+
+        Box::pin(async move { Ok(count) })
+    }
+}
+
+/// A limit of `max` hits per fixed window
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    max: u64,
+    window: Duration,
+}
+
+/// Why a request was refused
+struct Exceeded {
+    code: &'static str,
+    message: String,
+    retry_after: Duration,
+}
+
+/// Request rate limits and daily quotas
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    per_ip: Option<Limit>,
+    per_user: HashMap<&'static str, Limit>,
+    daily: HashMap<&'static str, Limit>,
+    trust_proxy: bool,
+}
+
+impl RateLimiter {
+    /// Read limits from the environment; a limit of 0 disables it
+    pub fn from_env(store: Arc<dyn RateLimitStore>) -> Self {
+        let window = Duration::from_millis(env_or("RATE_LIMIT_WINDOW_MS", DEFAULT_WINDOW_MS));
+        let default_max = env_or("RATE_LIMIT_MAX_REQUESTS", DEFAULT_MAX_REQUESTS);
+        let limit = |max: u64, window: Duration| (max > 0).then_some(Limit { max, window });
+
+        let limiter = Self {
+            store,
+            per_ip: limit(
+                env_or("RATE_LIMIT_IP_MAX_REQUESTS", DEFAULT_IP_MAX_REQUESTS),
+                window,
+            ),
+            per_user: ENDPOINT_LIMITS
+                .iter()
+                .filter_map(|&(route, var)| Some((route, limit(env_or(var, default_max), window)?)))
+                .collect(),
+            daily: DAILY_QUOTAS
+                .iter()
+                .filter_map(|&(route, var, default)| {
+                    Some((route, limit(env_or(var, default), DAY)?))
+                })
+                .collect(),
+            trust_proxy: env_or("RATE_LIMIT_TRUST_PROXY", false),
+        };
+
+        info!(
+            "Rate limits: {:?} per IP, per user {:?}, daily quotas {:?}",
+            limiter.per_ip, limiter.per_user, limiter.daily
+        );
+        limiter
+    }
+
+    fn client_ip(&self, req: &ServiceRequest) -> String {
+        let info = req.connection_info();
+        // Behind a reverse proxy every peer is the proxy itself
+        let ip = if self.trust_proxy {
+            info.realip_remote_addr()
+        } else {
+            info.peer_addr()
+        };
+        ip.unwrap_or("unknown").to_string()
+    }
+
+    async fn check_ip(&self, req: &ServiceRequest) -> Result<(), Exceeded> {
+        let Some(limit) = self.per_ip else {
+            return Ok(());
+        };
+        let ip = self.client_ip(req);
+
+        self.hit(&format!("ip:{}", ip), limit)
+            .await
+            .map_err(|retry_after| Exceeded {
+                code: "RATE_LIMITED",
+                message: format!(
+                    "More than {} requests from {} in {}s",
+                    limit.max,
+                    ip,
+                    limit.window.as_secs()
+                ),
+                retry_after,
+            })
+    }
+
+    async fn check_user(&self, route: &str, user_id: &str) -> Result<(), Exceeded> {
+        if let Some(&limit) = self.per_user.get(route) {
+            self.hit(&format!("user:{}:{}", user_id, route), limit)
+                .await
+                .map_err(|retry_after| Exceeded {
+                    code: "RATE_LIMITED",
+                    message: format!(
+                        "More than {} {} requests in {}s",
+                        limit.max,
+                        route,
+                        limit.window.as_secs()
+                    ),
+                    retry_after,
+                })?;
+        }
+
+        if !HANDLER_QUOTAS.contains(&route) {
+            self.check_quota(route, user_id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn check_quota(&self, route: &str, user_id: &str) -> Result<(), Exceeded> {
+        let Some(&limit) = self.daily.get(route) else {
+            return Ok(());
+        };
+        self.hit(&format!("quota:{}:{}", user_id, route), limit)
+            .await
+            .map_err(|retry_after| Exceeded {
+                code: "QUOTA_EXCEEDED",
+                message: format!("Daily quota of {} {} requests used up", limit.max, route),
+                retry_after,
+            })
+    }
+
+    /// Spend one of a user's daily quota for a route whose handler decides
+    /// when a request costs funds. Returns the response refusing it when
+    /// the quota is used up.
+    pub async fn charge_quota(&self, route: &str, user_id: &str) -> Result<(), HttpResponse> {
+        self.check_quota(route, user_id).await.map_err(|exceeded| {
+            warn!("Refused {} for {}: {}", route, user_id, exceeded.message);
+            exceeded_response(&exceeded)
+        })
+    }
+
+    /// Count a hit in the current window of `limit`, returning how long
+    /// until that window ends if it is over the limit
+    async fn hit(&self, key: &str, limit: Limit) -> Result<(), Duration> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let window_ms = (limit.window.as_millis() as u64).max(1);
+        let window = now / window_ms;
+        let window_end = (window + 1) * window_ms;
+
+        match self
+            .store
+            .increment(&format!("{}:{}", key, window), limit.window)
+            .await
+        {
+            Ok(count) if count > limit.max => Err(Duration::from_millis(window_end - now)),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                // Fail open; an unavailable store should not take the API down
+                warn!("Rate limit store error for {}: {}", key, e);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// "METHOD /path"; every limited route is a fixed path
+fn route(req: &ServiceRequest) -> String {
+    format!("{} {}", req.method(), req.path())
+}
+
+fn too_many_requests<B>(req: ServiceRequest, exceeded: Exceeded) -> ServiceResponse<EitherBody<B>> {
+    warn!(
+        "Refused {} {}: {}",
+        req.method(),
+        req.path(),
+        exceeded.message
+    );
+    let response = exceeded_response(&exceeded);
+    req.into_response(response).map_into_right_body()
+}
+
+fn exceeded_response(exceeded: &Exceeded) -> HttpResponse {
+    // Round up, so clients retrying exactly on time are let through
+    let retry_after = exceeded.retry_after.as_millis().div_ceil(1000);
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .json(ApiResponse::<()>::error(
+            exceeded.code,
+            match exceeded.code {
+                "QUOTA_EXCEEDED" => "Daily quota exceeded",
+                _ => "Too many requests",
+            },
+            Some(format!("{}; retry in {}s", exceeded.message, retry_after)),
+        ))
+}
+
+/// Middleware limiting requests per client IP, for every route
+pub async fn limit_ip(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if let Some(limiter) = req.app_data::<web::Data<RateLimiter>>().cloned() {
+        if !EXEMPT_PATHS.contains(&req.path()) {
+            if let Err(exceeded) = limiter.check_ip(&req).await {
+                return Ok(too_many_requests(req, exceeded));
+            }
+        }
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// Middleware applying per-user endpoint limits and daily quotas. Must run
+/// after `auth::require_user`.
+pub async fn limit_user(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let limiter = req.app_data::<web::Data<RateLimiter>>().cloned();
+    let user = req.extensions().get::<AuthenticatedUser>().cloned();
+
+    if let (Some(limiter), Some(user)) = (limiter, user) {
+        if let Err(exceeded) = limiter.check_user(&route(&req), &user.user_id).await {
+            return Ok(too_many_requests(req, exceeded));
+        }
+    }
+
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Answers with queued results and records what it was asked to count
+    #[derive(Default)]
+    struct StubStore {
+        results: Mutex<VecDeque<Result<u64>>>,
+        calls: Mutex<Vec<(String, Duration)>>,
+    }
+
+    impl StubStore {
+        fn answering(results: Vec<Result<u64>>) -> Arc<Self> {
+            Arc::new(Self {
+                results: Mutex::new(results.into()),
+                calls: Mutex::default(),
+            })
+        }
+
+        fn calls(&self) -> Vec<(String, Duration)> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl RateLimitStore for StubStore {
+        fn increment<'a>(&'a self, key: &'a str, ttl: Duration) -> BoxFuture<'a, Result<u64>> {
+            self.calls.lock().unwrap().push((key.to_string(), ttl));
+            let result = self
+                .results
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("unexpected increment");
+            Box::pin(async move { result })
+        }
+    }
+
+    fn limiter(store: Arc<dyn RateLimitStore>, daily: &[(&'static str, u64)]) -> RateLimiter {
+        RateLimiter {
+            store,
+            per_ip: None,
+            per_user: HashMap::new(),
+            daily: daily
+                .iter()
+                .map(|&(route, max)| (route, Limit { max, window: DAY }))
+                .collect(),
+            trust_proxy: false,
+        }
+    }
+
+    const HOUR: Limit = Limit {
+        max: 2,
+        window: Duration::from_secs(60 * 60),
+    };
+
+    fn epoch_ms() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    }
+
+    #[tokio::test]
+    async fn memory_store_counts_each_key() {
+        let store = MemoryStore::default();
+        let ttl = Duration::from_secs(60);
+
+        assert_eq!(store.increment("a", ttl).await.unwrap(), 1);
+        assert_eq!(store.increment("a", ttl).await.unwrap(), 2);
+        assert_eq!(store.increment("b", ttl).await.unwrap(), 1);
+        assert_eq!(store.increment("a", ttl).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn memory_store_prunes_expired_keys() {
+        let store = MemoryStore::default();
+        assert_eq!(store.increment("a", Duration::ZERO).await.unwrap(), 1);
+        assert_eq!(store.increment("a", Duration::ZERO).await.unwrap(), 2);
+
+        // Expired keys are only dropped once a minute
+        store.counters.lock().unwrap().last_pruned =
+            Instant::now().checked_sub(Duration::from_secs(61));
+        assert_eq!(store.increment("a", Duration::ZERO).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn hit_allows_up_to_the_limit() {
+        let store = StubStore::answering(vec![Ok(1), Ok(2), Ok(3)]);
+        let limiter = limiter(store.clone(), &[]);
+
+        assert!(limiter.hit("user:u", HOUR).await.is_ok());
+        assert!(limiter.hit("user:u", HOUR).await.is_ok());
+        let retry_after = limiter.hit("user:u", HOUR).await.unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= HOUR.window);
+    }
+
+    #[tokio::test]
+    async fn hit_counts_in_windows_aligned_to_the_epoch() {
+        let store = StubStore::answering(vec![Ok(3)]);
+        let limiter = limiter(store.clone(), &[]);
+        let window_ms = HOUR.window.as_millis() as u64;
+
+        let before = epoch_ms();
+        let retry_after = limiter.hit("user:u", HOUR).await.unwrap_err();
+        let after = epoch_ms();
+
+        let (key, ttl) = store.calls().remove(0);
+        assert_eq!(ttl, HOUR.window);
+        let window: u64 = key.strip_prefix("user:u:").unwrap().parse().unwrap();
+        assert!((before / window_ms..=after / window_ms).contains(&window));
+
+        // Retry once the window ends
+        let window_end = (window + 1) * window_ms;
+        let retry_at = before + retry_after.as_millis() as u64;
+        assert!(retry_at <= window_end && retry_at + (after - before) >= window_end);
+    }
+
+    #[tokio::test]
+    async fn hit_fails_open_when_the_store_errors() {
+        let store = StubStore::answering(vec![Err(anyhow::anyhow!("store down"))]);
+        let limiter = limiter(store.clone(), &[]);
+
+        assert!(limiter.hit("user:u", HOUR).await.is_ok());
+        assert_eq!(store.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn invoke_quota_is_only_charged_by_the_handler() {
+        let store = StubStore::answering(vec![Ok(1), Ok(2), Ok(1)]);
+        let limiter = limiter(store.clone(), &[(INVOKE_QUOTA, 1), ("POST /other", 5)]);
+
+        // The middleware lets views and calls alike through
+        assert!(limiter.check_user(INVOKE_QUOTA, "u").await.is_ok());
+        assert!(store.calls().is_empty());
+
+        assert!(limiter.charge_quota(INVOKE_QUOTA, "u").await.is_ok());
+        let refused = limiter.charge_quota(INVOKE_QUOTA, "u").await.unwrap_err();
+        assert_eq!(
+            refused.status(),
+            actix_web::http::StatusCode::TOO_MANY_REQUESTS
+        );
+        assert!(refused.headers().contains_key(header::RETRY_AFTER));
+
+        // Other quotas are still charged by the middleware
+        assert!(limiter.check_user("POST /other", "u").await.is_ok());
+        let keys: Vec<String> = store.calls().into_iter().map(|(key, _)| key).collect();
+        assert!(keys[0].starts_with("quota:u:POST /invoke:"));
+        assert!(keys[2].starts_with("quota:u:POST /other:"));
+    }
+
+    #[tokio::test]
+    async fn deploy_quota_is_only_charged_by_the_handler() {
+        let store = StubStore::answering(vec![Ok(1), Ok(2)]);
+        let limiter = limiter(store.clone(), &[(DEPLOY_QUOTA, 1)]);
+
+        // Requests the handler refuses before deploying cost nothing
+        assert!(limiter.check_user(DEPLOY_QUOTA, "u").await.is_ok());
+        assert!(limiter.check_user(DEPLOY_QUOTA, "u").await.is_ok());
+        assert!(store.calls().is_empty());
+
+        assert!(limiter.charge_quota(DEPLOY_QUOTA, "u").await.is_ok());
+        let refused = limiter.charge_quota(DEPLOY_QUOTA, "u").await.unwrap_err();
+        assert_eq!(
+            refused.status(),
+            actix_web::http::StatusCode::TOO_MANY_REQUESTS
+        );
+        let calls = store.calls();
+        assert_eq!(calls.len(), 2);
+        assert!(calls[0].0.starts_with("quota:u:POST /deploy:"));
+        assert_eq!(calls[0].1, DAY);
+    }
+}
@@ -0,0 +1,526 @@
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use log::info;
+use sha2::{Digest, Sha256};
+use std::env;
+use stellar_xdr::curr::{
+    AccountId, Hash, HashIdPreimage, HashIdPreimageSorobanAuthorization, HostFunction, Limits,
+    PublicKey, ReadXdr, ScAddress, ScBytes, ScMap, ScMapEntry, ScSymbol, ScVal, ScVec,
+    SorobanAuthorizationEntry, SorobanAuthorizedFunction, SorobanCredentials, Transaction, Uint256,
+    WriteXdr,
+};
+
+use crate::models::{AuthEntry, SignedAuthEntry};
+use crate::services::networks::NetworkProfile;
+use crate::services::soroban_rpc::{RpcClient, Simulation};
+use crate::services::transaction::{self, Signer};
+
+// Signatures on authorization entries expire after this many ledgers,
+// about eight minutes
+const AUTH_VALIDITY_LEDGERS: u32 = 100;
+
+/// A simulated host function with the authorizations it needs
+pub struct Authorized {
+    pub tx: Transaction, // Carries the entries, signed or not
+    pub simulation: Simulation,
+    pub auth: Vec<AuthEntry>,
+}
+
+impl Authorized {
+    /// Addresses that still have to sign before the transaction can succeed
+    pub fn missing(&self) -> Vec<String> {
+        missing(&self.auth)
+    }
+}
+
+/// Accounts the server signs authorization entries for on request, so
+/// methods that `require_auth` another address can be tried without a
+/// wallet for it. Set with `STELLAR_TEST_SIGNERS`, comma separated secrets.
+pub fn test_signers() -> Result<Vec<Signer>> {
+    let secrets = env::var("STELLAR_TEST_SIGNERS").unwrap_or_default();
+    secrets
+        .split(',')
+        .map(str::trim)
+        .filter(|secret| !secret.is_empty())
+        .map(|secret| Signer::from_secret(secret).context("Invalid secret in STELLAR_TEST_SIGNERS"))
+        .collect()
+}
+
+/// The test signers a request names by address. Signing for them is only
+/// allowed on networks whose profile opts in, never on mainnet.
+pub fn requested_signers(network: &NetworkProfile, requested: &[String]) -> Result<Vec<Signer>> {
+    if requested.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !network.test_signers_allowed() {
+        return Err(anyhow::anyhow!(
+            "Network {} does not allow test signers",
+            network.name
+        ));
+    }
+
+    let mut signers = test_signers()?;
+    requested
+        .iter()
+        .map(|address| {
+            let position = signers
+                .iter()
+                .position(|signer| signer.address() == address.trim())
+                .with_context(|| format!("{} is not a test signer", address))?;
+            Ok(signers.swap_remove(position))
+        })
+        .collect()
+}
+
+/// Simulate a host function and sign the authorizations it needs.
+///
+/// Without `supplied` entries the call is simulated to record which
+/// addresses must authorize it, and the entries get an expiration. Given
+/// entries, e.g. the recorded ones signed by a client, are used instead.
+/// Entries of the given test signers are signed by the server. Once every
+/// entry is signed the call is simulated again with them, which checks the
+/// signatures and accounts for their cost; otherwise the recording
+/// simulation is returned, listing what is missing.
+pub async fn simulate(
+    rpc: &RpcClient,
+    network: &NetworkProfile,
+    source: &AccountId,
+    host_function: HostFunction,
+    supplied: &[SignedAuthEntry],
+    signers: &[Signer],
+) -> Result<Authorized> {
+    let network_id = Hash(transaction::network_id(&network.passphrase));
+
+    let (recorded, mut entries) = if supplied.is_empty() {
+        let (tx, simulation) = transaction::simulate(rpc, source, host_function.clone()).await?;
+        let needs_signatures = simulation
+            .auth
+            .iter()
+            .any(|entry| matches!(entry.credentials, SorobanCredentials::Address(_)));
+        if simulation.error.is_some() || simulation.restore_preamble.is_some() || !needs_signatures
+        {
+            let auth = describe(&simulation.auth, &network_id)?;
+            return Ok(Authorized {
+                tx,
+                simulation,
+                auth,
+            });
+        }
+
+        let expiration = rpc.get_latest_ledger().await?.sequence + AUTH_VALIDITY_LEDGERS;
+        let mut entries = simulation.auth.clone();
+        for entry in &mut entries {
+            if let SorobanCredentials::Address(credentials) = &mut entry.credentials {
+                credentials.signature_expiration_ledger = expiration;
+            }
+        }
+        (Some((tx, simulation)), entries)
+    } else {
+        let entries = supplied
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                parse_signed(entry, &network_id)
+                    .with_context(|| format!("Invalid authorization entry {}", index))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        (None, entries)
+    };
+
+    for entry in &mut entries {
+        let Some(signer) = signature_needed(entry)
+            .and_then(|address| signers.iter().find(|signer| signer.account_id() == address))
+        else {
+            continue;
+        };
+        let preimage = preimage(entry, &network_id).context("Entry has no signer")?;
+        let signature = signer.signature(&hash(&preimage)?);
+        info!(
+            "Signing authorization of {} with a test signer",
+            signer.address()
+        );
+        attach_signature(entry, signer.public_key(), signature)?;
+    }
+
+    let auth = describe(&entries, &network_id)?;
+    let missing = missing(&auth);
+
+    match recorded {
+        Some((tx, simulation)) if !missing.is_empty() => Ok(Authorized {
+            tx: transaction::with_auth(tx, entries)?,
+            simulation,
+            auth,
+        }),
+        None if !missing.is_empty() => Err(anyhow::anyhow!(
+            "Authorization entries of {} are not signed",
+            missing.join(", ")
+        )),
+        _ => {
+            let (tx, simulation) =
+                transaction::simulate_authorized(rpc, source, host_function, entries).await?;
+            Ok(Authorized {
+                tx,
+                simulation,
+                auth,
+            })
+        }
+    }
+}
+
+fn missing(auth: &[AuthEntry]) -> Vec<String> {
+    auth.iter()
+        .filter(|entry| !entry.signed)
+        .filter_map(|entry| entry.address.clone())
+        .collect()
+}
+
+/// Describe entries for a client, with what to sign for those that need it
+fn describe(entries: &[SorobanAuthorizationEntry], network_id: &Hash) -> Result<Vec<AuthEntry>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let preimage = preimage(entry, network_id);
+            let (address, signed, expiration) = match &entry.credentials {
+                SorobanCredentials::SourceAccount => (None, true, None),
+                SorobanCredentials::Address(credentials) => (
+                    Some(credentials.address.to_string()),
+                    credentials.signature != ScVal::Void,
+                    Some(credentials.signature_expiration_ledger),
+                ),
+            };
+            Ok(AuthEntry {
+                address,
+                invocation: invocation_name(&entry.root_invocation.function),
+                signed,
+                signature_expiration_ledger: expiration,
+                xdr: entry
+                    .to_xdr_base64(Limits::none())
+                    .context("Failed to encode authorization entry")?,
+                preimage: preimage
+                    .as_ref()
+                    .map(|preimage| preimage.to_xdr_base64(Limits::none()))
+                    .transpose()
+                    .context("Failed to encode authorization preimage")?,
+                hash: preimage
+                    .as_ref()
+                    .map(|preimage| hash(preimage).map(hex::encode))
+                    .transpose()?,
+            })
+        })
+        .collect()
+}
+
+/// Decode an entry a client returned, attaching its signature if given
+fn parse_signed(entry: &SignedAuthEntry, network_id: &Hash) -> Result<SorobanAuthorizationEntry> {
+    let xdr = entry.xdr.trim();
+    let mut parsed =
+        SorobanAuthorizationEntry::from_xdr_base64(xdr, transaction::client_xdr_limits(xdr))
+            .map_err(|_| anyhow::anyhow!("Invalid authorization entry XDR"))?;
+    let Some(signature) = &entry.signature else {
+        return Ok(parsed);
+    };
+
+    let public_key = match &parsed.credentials {
+        SorobanCredentials::SourceAccount => {
+            return Err(anyhow::anyhow!(
+                "Entry is authorized by the transaction source and takes no signature"
+            ))
+        }
+        SorobanCredentials::Address(credentials) => match &credentials.address {
+            ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(key)))) => *key,
+            address => {
+                return Err(anyhow::anyhow!(
+                    "{} is not an account; attach its signature to the entry instead",
+                    address
+                ))
+            }
+        },
+    };
+    let preimage = preimage(&parsed, network_id).context("Entry has no signer")?;
+
+    let signature: [u8; 64] = hex::decode(signature.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .context("Signature must be 64 hex encoded bytes")?;
+    VerifyingKey::from_bytes(&public_key)
+        .context("Invalid account key")?
+        .verify(&hash(&preimage)?, &Signature::from_bytes(&signature))
+        .map_err(|_| anyhow::anyhow!("Signature does not match the entry's address"))?;
+
+    attach_signature(&mut parsed, public_key, signature)?;
+    Ok(parsed)
+}
+
+/// The account whose signature an entry still lacks
+fn signature_needed(entry: &SorobanAuthorizationEntry) -> Option<AccountId> {
+    match &entry.credentials {
+        SorobanCredentials::Address(credentials) if credentials.signature == ScVal::Void => {
+            match &credentials.address {
+                ScAddress::Account(account) => Some(account.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// What an address signs to authorize an entry
+fn preimage(entry: &SorobanAuthorizationEntry, network_id: &Hash) -> Option<HashIdPreimage> {
+    match &entry.credentials {
+        SorobanCredentials::SourceAccount => None,
+        SorobanCredentials::Address(credentials) => Some(HashIdPreimage::SorobanAuthorization(
+            HashIdPreimageSorobanAuthorization {
+                network_id: network_id.clone(),
+                nonce: credentials.nonce,
+                signature_expiration_ledger: credentials.signature_expiration_ledger,
+                invocation: entry.root_invocation.clone(),
+            },
+        )),
+    }
+}
+
+fn hash(preimage: &HashIdPreimage) -> Result<[u8; 32]> {
+    let bytes = preimage
+        .to_xdr(Limits::none())
+        .context("Failed to encode authorization preimage")?;
+    Ok(Sha256::digest(bytes).into())
+}
+
+/// Set an account's signature the way its built-in account contract
+/// expects it: a vector of `{public_key, signature}` maps
+fn attach_signature(
+    entry: &mut SorobanAuthorizationEntry,
+    public_key: [u8; 32],
+    signature: [u8; 64],
+) -> Result<()> {
+    let SorobanCredentials::Address(credentials) = &mut entry.credentials else {
+        return Ok(());
+    };
+    let field = |name: &str, bytes: Vec<u8>| -> Result<ScMapEntry> {
+        Ok(ScMapEntry {
+            key: ScVal::Symbol(ScSymbol(name.try_into()?)),
+            val: ScVal::Bytes(ScBytes(bytes.try_into()?)),
+        })
+    };
+    let fields = vec![
+        field("public_key", public_key.to_vec())?,
+        field("signature", signature.to_vec())?,
+    ];
+    credentials.signature = ScVal::Vec(Some(ScVec(
+        vec![ScVal::Map(Some(ScMap(fields.try_into()?)))].try_into()?,
+    )));
+    Ok(())
+}
+
+fn invocation_name(function: &SorobanAuthorizedFunction) -> String {
+    match function {
+        SorobanAuthorizedFunction::ContractFn(call) => format!(
+            "{}.{}",
+            call.contract_address,
+            call.function_name.0.to_utf8_string_lossy()
+        ),
+        SorobanAuthorizedFunction::CreateContractHostFn(_)
+        | SorobanAuthorizedFunction::CreateContractV2HostFn(_) => "create contract".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stellar_strkey::ed25519::PrivateKey;
+    use stellar_xdr::curr::{
+        ContractId, InvokeContractArgs, SorobanAddressCredentials, SorobanAuthorizedInvocation,
+    };
+
+    const PASSPHRASE: &str = "Test SDF Network ; September 2015";
+
+    fn signer(seed: u8) -> Signer {
+        Signer::from_secret(&PrivateKey([seed; 32]).to_string()).unwrap()
+    }
+
+    fn entry(credentials: SorobanCredentials) -> SorobanAuthorizationEntry {
+        SorobanAuthorizationEntry {
+            credentials,
+            root_invocation: SorobanAuthorizedInvocation {
+                function: SorobanAuthorizedFunction::ContractFn(InvokeContractArgs {
+                    contract_address: ScAddress::Contract(ContractId(Hash([1; 32]))),
+                    function_name: "transfer".try_into().unwrap(),
+                    args: Default::default(),
+                }),
+                sub_invocations: Default::default(),
+            },
+        }
+    }
+
+    fn address_entry(address: ScAddress) -> SorobanAuthorizationEntry {
+        entry(SorobanCredentials::Address(SorobanAddressCredentials {
+            address,
+            nonce: 42,
+            signature_expiration_ledger: 1000,
+            signature: ScVal::Void,
+        }))
+    }
+
+    fn signed(entry: &SorobanAuthorizationEntry, signature: [u8; 64]) -> SignedAuthEntry {
+        SignedAuthEntry {
+            xdr: entry.to_xdr_base64(Limits::none()).unwrap(),
+            signature: Some(hex::encode(signature)),
+        }
+    }
+
+    fn network_id() -> Hash {
+        Hash(transaction::network_id(PASSPHRASE))
+    }
+
+    fn signature_of(signer: &Signer, entry: &SorobanAuthorizationEntry) -> [u8; 64] {
+        let preimage = preimage(entry, &network_id()).unwrap();
+        signer.signature(&hash(&preimage).unwrap())
+    }
+
+    #[test]
+    fn valid_signatures_are_attached() {
+        let signer = signer(7);
+        let unsigned = address_entry(ScAddress::Account(signer.account_id()));
+        let signature = signature_of(&signer, &unsigned);
+
+        let parsed = parse_signed(&signed(&unsigned, signature), &network_id()).unwrap();
+        let mut expected = unsigned.clone();
+        attach_signature(&mut expected, signer.public_key(), signature).unwrap();
+        assert_eq!(parsed, expected);
+        assert_eq!(signature_needed(&parsed), None);
+    }
+
+    #[test]
+    fn wrong_signatures_are_rejected() {
+        let unsigned = address_entry(ScAddress::Account(signer(7).account_id()));
+
+        let by_other = signature_of(&signer(8), &unsigned);
+        let err = parse_signed(&signed(&unsigned, by_other), &network_id()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Signature does not match the entry's address"
+        );
+
+        // Altered after signing
+        let mut altered = signature_of(&signer(7), &unsigned);
+        altered[0] ^= 1;
+        let err = parse_signed(&signed(&unsigned, altered), &network_id()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Signature does not match the entry's address"
+        );
+
+        let short = SignedAuthEntry {
+            signature: Some("abcd".to_string()),
+            ..signed(&unsigned, [0; 64])
+        };
+        let err = parse_signed(&short, &network_id()).unwrap_err();
+        assert_eq!(err.to_string(), "Signature must be 64 hex encoded bytes");
+    }
+
+    #[test]
+    fn source_account_entries_take_no_signature() {
+        let unsigned = entry(SorobanCredentials::SourceAccount);
+        let err = parse_signed(&signed(&unsigned, [0; 64]), &network_id()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Entry is authorized by the transaction source and takes no signature"
+        );
+    }
+
+    #[test]
+    fn contract_addresses_take_no_signature() {
+        let unsigned = address_entry(ScAddress::Contract(ContractId(Hash([2; 32]))));
+        let err = parse_signed(&signed(&unsigned, [0; 64]), &network_id()).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("is not an account; attach its signature to the entry instead"));
+    }
+
+    #[test]
+    fn entries_without_a_signature_are_kept_as_given() {
+        let unsigned = address_entry(ScAddress::Account(signer(7).account_id()));
+        let supplied = SignedAuthEntry {
+            xdr: format!(" {}\n", unsigned.to_xdr_base64(Limits::none()).unwrap()),
+            signature: None,
+        };
+        assert_eq!(parse_signed(&supplied, &network_id()).unwrap(), unsigned);
+
+        let garbage = SignedAuthEntry {
+            xdr: "not xdr".to_string(),
+            signature: None,
+        };
+        let err = parse_signed(&garbage, &network_id()).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid authorization entry XDR");
+    }
+
+    #[test]
+    fn signatures_are_a_vec_of_public_key_and_signature_maps() {
+        let mut entry = address_entry(ScAddress::Account(signer(7).account_id()));
+        attach_signature(&mut entry, [3; 32], [4; 64]).unwrap();
+
+        let SorobanCredentials::Address(credentials) = &entry.credentials else {
+            panic!("credentials changed kind");
+        };
+        let expected = ScVal::Vec(Some(ScVec(
+            vec![ScVal::Map(Some(ScMap(
+                vec![
+                    ScMapEntry {
+                        key: ScVal::Symbol(ScSymbol("public_key".try_into().unwrap())),
+                        val: ScVal::Bytes(ScBytes(vec![3; 32].try_into().unwrap())),
+                    },
+                    ScMapEntry {
+                        key: ScVal::Symbol(ScSymbol("signature".try_into().unwrap())),
+                        val: ScVal::Bytes(ScBytes(vec![4; 64].try_into().unwrap())),
+                    },
+                ]
+                .try_into()
+                .unwrap(),
+            )))]
+            .try_into()
+            .unwrap(),
+        )));
+        assert_eq!(credentials.signature, expected);
+        assert_eq!(credentials.nonce, 42);
+        assert_eq!(credentials.signature_expiration_ledger, 1000);
+    }
+
+    #[test]
+    fn missing_lists_unsigned_addresses() {
+        let first = signer(7);
+        let second = signer(8);
+        let mut signed_entry = address_entry(ScAddress::Account(first.account_id()));
+        attach_signature(&mut signed_entry, first.public_key(), [0; 64]).unwrap();
+        let entries = vec![
+            entry(SorobanCredentials::SourceAccount),
+            signed_entry,
+            address_entry(ScAddress::Account(second.account_id())),
+        ];
+
+        let tx = transaction::host_function_transaction(
+            &first.account_id(),
+            1,
+            HostFunction::InvokeContract(InvokeContractArgs {
+                contract_address: ScAddress::Contract(ContractId(Hash([1; 32]))),
+                function_name: "transfer".try_into().unwrap(),
+                args: Default::default(),
+            }),
+        )
+        .unwrap();
+        let authorized = Authorized {
+            tx,
+            simulation: Simulation {
+                min_resource_fee: 0,
+                transaction_data: None,
+                auth: Vec::new(),
+                return_value: None,
+                events: Vec::new(),
+                error: None,
+                restore_preamble: None,
+                cost: None,
+            },
+            auth: describe(&entries, &network_id()).unwrap(),
+        };
+        assert_eq!(authorized.missing(), vec![second.address()]);
+    }
+}
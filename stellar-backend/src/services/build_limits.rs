@@ -0,0 +1,299 @@
+use log::info;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::env_or;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_CPU_LIMIT_SECS: u64 = 600;
+const DEFAULT_MEMORY_LIMIT_MB: u64 = 4096;
+const DEFAULT_MAX_OUTPUT_MB: usize = 8;
+
+pub const STATUS_TIMEOUT: &str = "TIMEOUT";
+pub const STATUS_CANCELLED: &str = "CANCELLED";
+pub const STATUS_RESOURCE_LIMIT: &str = "RESOURCE_LIMIT";
+
+/// Limits applied to every cargo process spawned for a user build
+#[derive(Debug, Clone, Copy)]
+pub struct BuildLimits {
+    pub timeout: Duration,       // Wall clock, for the whole job
+    pub cpu_seconds: u64,        // RLIMIT_CPU, per process
+    pub memory_bytes: u64,       // RLIMIT_DATA, per process
+    pub max_output_bytes: usize, // Combined stdout and stderr
+}
+
+impl BuildLimits {
+    pub fn from_env() -> Self {
+        let limits = Self {
+            timeout: Duration::from_secs(env_or("BUILD_TIMEOUT_SECS", DEFAULT_TIMEOUT_SECS)),
+            cpu_seconds: env_or("BUILD_CPU_LIMIT_SECS", DEFAULT_CPU_LIMIT_SECS),
+            memory_bytes: env_or("BUILD_MEMORY_LIMIT_MB", DEFAULT_MEMORY_LIMIT_MB) * 1024 * 1024,
+            max_output_bytes: env_or("BUILD_MAX_OUTPUT_MB", DEFAULT_MAX_OUTPUT_MB) * 1024 * 1024,
+        };
+        info!("Build limits: {:?}", limits);
+        limits
+    }
+
+    /// Run the command in its own process group with resource limits that
+    /// every compiler process it spawns inherits
+    pub fn apply(&self, command: &mut Command) {
+        let cpu_seconds = self.cpu_seconds as libc::rlim_t;
+        let memory_bytes = self.memory_bytes as libc::rlim_t;
+
+        command.process_group(0);
+        // SAFETY: only async-signal-safe libc calls between fork and exec
+        unsafe {
+            command.pre_exec(move || {
+                // SIGXCPU comes at the soft limit, which is what cargo
+                // reports; an equal hard limit would SIGKILL instead
+                set_rlimit(libc::RLIMIT_CPU, cpu_seconds, cpu_seconds + 1)?;
+                set_rlimit(libc::RLIMIT_DATA, memory_bytes, memory_bytes)?;
+                Ok(())
+            });
+        }
+    }
+}
+
+// glibc declares setrlimit with its own resource type; other libcs take an int
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+type RlimitResource = libc::c_int;
+
+fn set_rlimit(
+    resource: RlimitResource,
+    soft: libc::rlim_t,
+    hard: libc::rlim_t,
+) -> std::io::Result<()> {
+    let rlimit = libc::rlimit {
+        rlim_cur: soft,
+        rlim_max: hard,
+    };
+    if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Why a build was stopped before cargo finished on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildTermination {
+    Timeout,
+    Cancelled,
+    ResourceLimit,
+}
+
+impl BuildTermination {
+    /// Value reported in `CompileDetails.status`
+    pub fn status(self) -> &'static str {
+        match self {
+            BuildTermination::Timeout => STATUS_TIMEOUT,
+            BuildTermination::Cancelled => STATUS_CANCELLED,
+            BuildTermination::ResourceLimit => STATUS_RESOURCE_LIMIT,
+        }
+    }
+
+    pub fn message(self, limits: &BuildLimits) -> String {
+        match self {
+            BuildTermination::Timeout => format!(
+                "Build exceeded the time limit of {}s",
+                limits.timeout.as_secs()
+            ),
+            BuildTermination::Cancelled => "Build was cancelled".to_string(),
+            BuildTermination::ResourceLimit => {
+                "Build exceeded its CPU, memory or output limit".to_string()
+            }
+        }
+    }
+}
+
+/// Limits and cancellation for one job, shared by all cargo runs in it
+#[derive(Clone)]
+pub struct BuildControl {
+    pub limits: BuildLimits,
+    pub deadline: Instant,
+    pub cancel: CancellationToken,
+}
+
+impl BuildControl {
+    /// Start the wall clock for a job now
+    pub fn new(limits: BuildLimits, cancel: CancellationToken) -> Self {
+        Self {
+            deadline: Instant::now() + limits.timeout,
+            limits,
+            cancel,
+        }
+    }
+}
+
+/// Kill the build and everything it spawned (rustc, build scripts, linkers)
+pub fn kill_process_group(child: &Child) {
+    if let Some(pid) = child.id() {
+        // The child leads its own group, see BuildLimits::apply
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+}
+
+/// Whether the build was stopped by an rlimit, either cargo itself or a
+/// compiler process that cargo reported on
+pub fn hit_resource_limit(status: &ExitStatus, stderr: &str) -> bool {
+    status.signal() == Some(libc::SIGXCPU)
+        || stderr.contains("SIGXCPU")
+        || stderr.contains("memory allocation of")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::compilation::{run_cargo, BuildOutput};
+
+    fn limits(timeout: Duration) -> BuildLimits {
+        BuildLimits {
+            timeout,
+            cpu_seconds: 1,
+            memory_bytes: 256 * 1024 * 1024,
+            max_output_bytes: 64 * 1024,
+        }
+    }
+
+    // Run a shell script the way builds run cargo
+    async fn run(script: &str, limits: BuildLimits, cancel: CancellationToken) -> BuildOutput {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        let control = BuildControl::new(limits, cancel);
+        run_cargo(&mut command, &control, &|_, _| {}).await.unwrap()
+    }
+
+    // Whether the process is gone, allowing a moment for SIGKILL to land. A
+    // zombie counts as gone since nothing in the test reaps it.
+    async fn exited(pid: &str) -> bool {
+        for _ in 0..100 {
+            let output = std::process::Command::new("ps")
+                .args(["-o", "stat=", "-p", pid])
+                .output()
+                .unwrap();
+            let state = String::from_utf8_lossy(&output.stdout);
+            if state.trim().is_empty() || state.trim().starts_with('Z') {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        false
+    }
+
+    // Leaves a grandchild in the build's process group and prints its pid
+    const SPAWNS_A_GRANDCHILD: &str = "sleep 60 & echo $!; sleep 60";
+
+    #[tokio::test]
+    async fn timeouts_kill_the_process_group() {
+        let started = Instant::now();
+        let output = run(
+            SPAWNS_A_GRANDCHILD,
+            limits(Duration::from_millis(500)),
+            CancellationToken::new(),
+        )
+        .await;
+
+        assert_eq!(output.terminated, Some(BuildTermination::Timeout));
+        assert_eq!(output.terminated.unwrap().status(), STATUS_TIMEOUT);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(!output.status.success());
+        assert!(
+            exited(output.stdout.trim()).await,
+            "{} still running",
+            output.stdout
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelling_kills_the_process_group() {
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            canceller.cancel();
+        });
+
+        let output = run(SPAWNS_A_GRANDCHILD, limits(Duration::from_secs(60)), cancel).await;
+
+        assert_eq!(output.terminated, Some(BuildTermination::Cancelled));
+        assert_eq!(output.terminated.unwrap().status(), STATUS_CANCELLED);
+        assert!(
+            exited(output.stdout.trim()).await,
+            "{} still running",
+            output.stdout
+        );
+    }
+
+    #[tokio::test]
+    async fn rlimits_are_inherited() {
+        let output = run(
+            "ulimit -t; ulimit -d",
+            limits(Duration::from_secs(60)),
+            CancellationToken::new(),
+        )
+        .await;
+
+        // ulimit -d reports KiB
+        assert_eq!(output.stdout, format!("1\n{}\n", 256 * 1024));
+        assert_eq!(output.terminated, None);
+    }
+
+    #[tokio::test]
+    async fn cpu_limit_is_a_resource_limit() {
+        let output = run(
+            "while :; do :; done",
+            limits(Duration::from_secs(60)),
+            CancellationToken::new(),
+        )
+        .await;
+
+        assert_eq!(output.status.signal(), Some(libc::SIGXCPU));
+        assert_eq!(output.terminated, Some(BuildTermination::ResourceLimit));
+        assert_eq!(output.terminated.unwrap().status(), STATUS_RESOURCE_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn output_limit_is_a_resource_limit() {
+        let output = run(
+            "while :; do echo spam; done",
+            limits(Duration::from_secs(60)),
+            CancellationToken::new(),
+        )
+        .await;
+
+        assert_eq!(output.terminated, Some(BuildTermination::ResourceLimit));
+        assert_eq!(output.status.signal(), Some(libc::SIGKILL));
+    }
+
+    #[tokio::test]
+    async fn compiler_out_of_memory_is_a_resource_limit() {
+        let output = run(
+            "echo 'memory allocation of 4096 bytes failed' >&2; exit 101",
+            limits(Duration::from_secs(60)),
+            CancellationToken::new(),
+        )
+        .await;
+
+        assert_eq!(output.terminated, Some(BuildTermination::ResourceLimit));
+    }
+
+    #[tokio::test]
+    async fn ordinary_failures_are_not_terminations() {
+        let output = run(
+            "echo 'error[E0425]: cannot find value' >&2; exit 101",
+            limits(Duration::from_secs(60)),
+            CancellationToken::new(),
+        )
+        .await;
+
+        assert_eq!(output.status.code(), Some(101));
+        assert_eq!(output.terminated, None);
+    }
+}
@@ -0,0 +1,108 @@
+use log::debug;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::models::{BuildEvent, OutputStream};
+
+// Finished (or never used) streams are kept around this long so late
+// subscribers can still replay the full build log
+const STREAM_RETENTION: Duration = Duration::from_secs(10 * 60);
+const CHANNEL_CAPACITY: usize = 1024;
+
+struct BuildChannel {
+    history: Vec<BuildEvent>,
+    sender: broadcast::Sender<BuildEvent>,
+    finished: bool,
+    updated_at: Instant,
+}
+
+impl BuildChannel {
+    fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            history: Vec::new(),
+            sender,
+            finished: false,
+            updated_at: Instant::now(),
+        }
+    }
+}
+
+/// Registry of live build output streams, keyed by job id
+pub struct BuildStreams {
+    channels: Mutex<HashMap<String, BuildChannel>>,
+}
+
+impl BuildStreams {
+    pub fn new() -> Self {
+        Self {
+            channels: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Publish an event to every subscriber of a job and record it for replay
+    pub fn publish(&self, job_id: &str, event: BuildEvent) {
+        let mut channels = self.channels.lock().unwrap();
+        Self::prune(&mut channels);
+
+        let channel = channels
+            .entry(job_id.to_string())
+            .or_insert_with(BuildChannel::new);
+
+        if channel.finished {
+            debug!("Dropping event for finished build stream {}", job_id);
+            return;
+        }
+
+        channel.finished = event.is_terminal();
+        channel.updated_at = Instant::now();
+        channel.history.push(event.clone());
+        // No receivers is fine, the event is still kept in history
+        let _ = channel.sender.send(event);
+    }
+
+    /// Publish a single line of build output
+    pub fn output(&self, job_id: &str, stream: OutputStream, line: &str) {
+        self.publish(
+            job_id,
+            BuildEvent::Output {
+                stream,
+                line: line.to_string(),
+            },
+        );
+    }
+
+    /// Subscribe to a job's events.
+    ///
+    /// Returns the events published so far and, unless the build has already
+    /// finished, a receiver for the ones still to come. Subscribing before the
+    /// build starts is allowed.
+    pub fn subscribe(
+        &self,
+        job_id: &str,
+    ) -> (Vec<BuildEvent>, Option<broadcast::Receiver<BuildEvent>>) {
+        let mut channels = self.channels.lock().unwrap();
+        Self::prune(&mut channels);
+
+        let channel = channels
+            .entry(job_id.to_string())
+            .or_insert_with(BuildChannel::new);
+
+        let receiver = if channel.finished {
+            None
+        } else {
+            Some(channel.sender.subscribe())
+        };
+
+        (channel.history.clone(), receiver)
+    }
+
+    fn prune(channels: &mut HashMap<String, BuildChannel>) {
+        channels.retain(|_, channel| {
+            channel.updated_at.elapsed() < STREAM_RETENTION
+                || (!channel.finished && channel.sender.receiver_count() > 0)
+        });
+    }
+}
@@ -1,70 +1,505 @@
 use anyhow::{Context, Result};
 use log::{debug, info, warn};
-use serde_json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Component, Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
 use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+use crate::models::{CompileDetails, CompileResponse, ContractArtifact, Diagnostic, OutputStream};
+use crate::services::build_limits::{self, BuildControl, BuildTermination};
+use crate::services::contract_spec::parse_contract_spec;
+use crate::services::diagnostics::{parse_cargo_line, CargoLine};
+use crate::services::sandbox::{Network, Sandbox};
+use crate::services::sdk_versions::SdkVersion;
+use crate::services::target_cache::{self, BuildProfile};
+use crate::storage::{self, copy_dir_all, ProjectRef};
+
+// Contract crates live in contracts/<name>, matching the workspace members glob
+pub const CONTRACTS_DIR: &str = "contracts";
+// The contract crate shipped with the base project; crate-relative sources go here by default
+pub const DEFAULT_CONTRACT: &str = "hello-world";
+// Stellar CLI 23.x uses wasm32v1-none target instead of wasm32-unknown-unknown
+pub const WASM_TARGET: &str = "wasm32v1-none";
+
+/// A contract crate in a project workspace
+#[derive(Debug, Clone)]
+pub struct ContractCrate {
+    pub name: String, // Package name, also the wasm file stem
+    pub dir: PathBuf, // Relative to the project root, e.g. contracts/hello-world
+}
+
+impl ContractCrate {
+    pub fn manifest_path(&self) -> PathBuf {
+        self.dir.join("Cargo.toml")
+    }
+
+    /// Path of the built wasm inside the project
+    pub fn wasm_path(&self, project_path: &Path, variant: WasmVariant) -> PathBuf {
+        profile_dir(project_path, variant).join(format!("{}.wasm", self.name.replace('-', "_")))
+    }
+}
+
+/// Which of a contract's builds to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmVariant {
+    /// The `release` profile, as `stellar contract build` produces
+    Release,
+    /// The base project's `release-with-logs` profile. Debug assertions are
+    /// on, so the contract's `log!` calls are compiled in. Only built when
+    /// asked for, by `build_with_logs`.
+    WithLogs,
+}
+
+impl WasmVariant {
+    /// Cargo profile, also the name of its output directory
+    pub fn profile(self) -> &'static str {
+        match self {
+            WasmVariant::Release => "release",
+            WasmVariant::WithLogs => "release-with-logs",
+        }
+    }
+}
+
+/// Callback receiving each line of build output as soon as it is produced
+pub type OutputSink<'a> = &'a (dyn Fn(OutputStream, &str) + Send + Sync);
+
+/// Source files to write into the project's contract crates before building
+#[derive(Debug, Clone)]
+pub struct ContractSources {
+    // Contract name -> crate-relative path (e.g. "src/lib.rs") -> file contents
+    contracts: BTreeMap<String, BTreeMap<String, String>>,
+    // When the full file set was sent, files not in it are deleted
+    replace_all: bool,
+}
+
+impl ContractSources {
+    /// Validate the sources of a compile request.
+    ///
+    /// `code` is shorthand for `src/lib.rs`; `files` maps paths to contents
+    /// and, when present, is the complete set of sources for every contract
+    /// it mentions. Paths are either crate-relative (`src/test.rs`, written to
+    /// `contract` or the default contract) or workspace-relative
+    /// (`contracts/token/src/lib.rs`).
+    pub fn from_request(
+        code: Option<&str>,
+        files: Option<&BTreeMap<String, String>>,
+        contract: Option<&str>,
+    ) -> Result<Self> {
+        let default_contract = contract.unwrap_or(DEFAULT_CONTRACT);
+        validate_contract_name(default_contract)?;
+
+        if code.is_none() && files.is_none() {
+            return Err(anyhow::anyhow!("Provide either `code` or `files`"));
+        }
+
+        let mut contracts: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+
+        if let Some(files) = files {
+            for (path, contents) in files {
+                let (contract, path) = validate_source_path(path, default_contract)?;
+                contracts
+                    .entry(contract)
+                    .or_default()
+                    .insert(path, contents.clone());
+            }
+        }
+
+        if let Some(code) = code {
+            let sources = contracts.entry(default_contract.to_string()).or_default();
+            if sources.contains_key("src/lib.rs") {
+                return Err(anyhow::anyhow!(
+                    "Both `code` and `files[\"src/lib.rs\"]` were provided; send only one"
+                ));
+            }
+            sources.insert("src/lib.rs".to_string(), code.to_string());
+        }
+
+        for (contract, sources) in &contracts {
+            if !sources.contains_key("src/lib.rs") {
+                return Err(anyhow::anyhow!(
+                    "Missing entry point for contract {}: provide its src/lib.rs",
+                    contract
+                ));
+            }
+        }
+
+        Ok(Self {
+            contracts,
+            replace_all: files.is_some(),
+        })
+    }
+}
+
+/// Contract names double as directory and package names
+fn validate_contract_name(name: &str) -> Result<()> {
+    let valid = name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Invalid contract name {:?}: use lowercase letters, digits, '-' and '_'",
+            name
+        ))
+    }
+}
+
+/// Normalise a user-supplied source path and make sure it stays inside a
+/// contract crate's `src/` directory. Returns the contract name and the
+/// crate-relative path.
+fn validate_source_path(path: &str, default_contract: &str) -> Result<(String, String)> {
+    let mut parts = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(
+                part.to_str()
+                    .context("Source file paths must be valid UTF-8")?
+                    .to_string(),
+            ),
+            Component::CurDir => {}
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Invalid source file path {:?}: must be relative and must not contain '..'",
+                    path
+                ))
+            }
+        }
+    }
+
+    let (contract, crate_parts) = if parts.first().map(String::as_str) == Some(CONTRACTS_DIR) {
+        let contract = parts.get(1).with_context(|| {
+            format!("Invalid source file path {:?}: missing contract name", path)
+        })?;
+        validate_contract_name(contract)?;
+        (contract.clone(), &parts[2..])
+    } else {
+        (default_contract.to_string(), &parts[..])
+    };
+
+    if crate_parts.len() < 2 || crate_parts[0] != "src" {
+        return Err(anyhow::anyhow!(
+            "Invalid source file path {:?}: files must live under a contract's src/",
+            path
+        ));
+    }
+
+    Ok((contract, crate_parts.join("/")))
+}
+
+/// How cargo runs over user code: against which SDK version's caches, in
+/// which sandbox and under which limits
+#[derive(Clone, Copy)]
+pub struct BuildEnv<'a> {
+    pub sdk: &'a SdkVersion,
+    pub sandbox: &'a dyn Sandbox,
+    pub control: &'a BuildControl,
+}
 
-use crate::models::{CompileDetails, CompileResponse};
-use crate::utils::copy_dir_all;
+/// Output of a cargo run with `--message-format=json`
+pub struct BuildOutput {
+    pub status: ExitStatus,
+    pub stdout: String, // Non-JSON stdout lines only
+    pub stderr: String, // Cargo progress plus rendered diagnostics
+    pub diagnostics: Vec<Diagnostic>,
+    pub terminated: Option<BuildTermination>, // Set when the build was stopped early
+}
 
+/// Build every contract crate in the project, or only `contract` when given
 pub async fn compile_contract(
-    code: &str,
-    user_id: &str,
-    project_id: &str,
+    sources: &ContractSources,
+    project: &ProjectRef,
+    contract: Option<&str>,
+    build: BuildEnv<'_>,
+    on_output: OutputSink<'_>,
 ) -> Result<CompileResponse> {
     let start_time = Instant::now();
 
-    // Use persistent project directory structure
-    let base_project_path = PathBuf::from("base_project");
-    let user_projects_dir = PathBuf::from("projects").join(user_id);
-    let project_path = user_projects_dir.join(project_id);
-
-    info!(
-        "Starting compilation for project {} in persistent directory: {:?}",
-        project_id, project_path
-    );
+    let project_path = prepare_project(project, sources, build.sdk)?;
+    target_cache::seed(build.sdk, &project_path, BuildProfile::Release)?;
+
+    let contracts = select_contracts(&project_path, contract)?;
+
+    let mut exit_code = 0;
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut diagnostics = Vec::new();
+    let mut artifacts = Vec::new();
+    let mut terminated = None;
+
+    // Dependencies are fetched first; the builds themselves get no network
+    let fetch_result = fetch_dependencies(&project_path, build, on_output).await?;
+    stderr.push_str(&fetch_result.stderr);
+    let fetched = fetch_result.status.success() && fetch_result.terminated.is_none();
+    if let Some(reason) = fetch_result.terminated {
+        let message = reason.message(&build.control.limits);
+        on_output(OutputStream::Stderr, &message);
+        stderr.push_str(&message);
+        stderr.push('\n');
+        terminated = Some(reason);
+    }
+    if !fetched {
+        exit_code = fetch_result.status.code().unwrap_or(-1);
+    }
 
-    // Ensure the project exists (copy from base if needed)
-    setup_user_project(&base_project_path, &project_path, code)?;
+    for contract_crate in contracts.iter().filter(|_| fetched) {
+        info!("Building contract {} for project {}", contract_crate.name, project);
+
+        // Build the contract to wasm
+        let compile_result = run_contract_build(
+            &project_path,
+            contract_crate,
+            WasmVariant::Release,
+            build,
+            on_output,
+        )
+        .await?;
+
+        stdout.push_str(&compile_result.stdout);
+        stderr.push_str(&compile_result.stderr);
+        diagnostics.extend(compile_result.diagnostics);
+
+        if let Some(reason) = compile_result.terminated {
+            let message = reason.message(&build.control.limits);
+            on_output(OutputStream::Stderr, &message);
+            stderr.push_str(&message);
+            stderr.push('\n');
+            exit_code = compile_result.status.code().unwrap_or(-1);
+            terminated = Some(reason);
+            break;
+        }
 
-    // Run stellar contract build
-    let compile_result = run_stellar_build(&project_path)?;
+        if compile_result.status.success() {
+            artifacts.push(collect_artifact(&project_path, contract_crate)?);
+        } else if exit_code == 0 {
+            exit_code = compile_result.status.code().unwrap_or(-1);
+        }
+    }
 
     let compilation_time = start_time.elapsed().as_secs_f64();
+    let success = exit_code == 0 && terminated.is_none();
 
-    // Extract WASM file size and contract spec
-    let (wasm_size, spec) = extract_compilation_artifacts(&project_path)?;
+    // Top-level wasm size and spec describe the primary contract, as before
+    let primary = contract.unwrap_or(DEFAULT_CONTRACT);
+    let primary_artifact = artifacts
+        .iter()
+        .find(|artifact| artifact.name == primary)
+        .or(artifacts.first());
 
     let response = CompileResponse {
-        success: compile_result.status.success(),
-        exit_code: compile_result.status.code().unwrap_or(-1),
-        stdout: String::from_utf8_lossy(&compile_result.stdout).to_string(),
-        stderr: String::from_utf8_lossy(&compile_result.stderr).to_string(),
+        success,
+        exit_code,
+        stdout,
+        stderr,
         details: CompileDetails {
-            status: if compile_result.status.success() {
-                "success".to_string()
-            } else {
-                "failed".to_string()
+            status: match terminated {
+                Some(reason) => reason.status().to_string(),
+                None if success => "success".to_string(),
+                None => "failed".to_string(),
             },
             compilation_time,
             project_path: project_path.to_string_lossy().to_string(),
-            wasm_size,
-            optimized: true, // stellar contract build optimizes by default
+            wasm_size: primary_artifact.map(|artifact| artifact.wasm_size),
+            optimized: false, // The release build is not run through wasm-opt
+            sdk_version: build.sdk.version.clone(),
         },
-        spec,
+        spec: primary_artifact.and_then(|artifact| artifact.spec.clone()),
+        diagnostics,
+        artifacts,
     };
 
     info!(
         "Compilation completed for project {} in {:.2}s",
-        project_id, compilation_time
+        project, compilation_time
     );
 
     Ok(response)
 }
 
-fn setup_user_project(base_project_path: &PathBuf, project_path: &PathBuf, code: &str) -> Result<()> {
+/// List the contract crates in a project workspace, sorted by name
+pub fn discover_contracts(project_path: &Path) -> Result<Vec<ContractCrate>> {
+    let contracts_path = project_path.join(CONTRACTS_DIR);
+    let mut contracts = Vec::new();
+
+    for entry in fs::read_dir(&contracts_path).context("Failed to read contracts directory")? {
+        let dir = entry.context("Failed to read directory entry")?.path();
+        let manifest = dir.join("Cargo.toml");
+        if !manifest.is_file() {
+            continue;
+        }
+
+        let document = fs::read_to_string(&manifest)
+            .with_context(|| format!("Failed to read {:?}", manifest))?
+            .parse::<toml_edit::DocumentMut>()
+            .with_context(|| format!("Failed to parse {:?}", manifest))?;
+        let name = document
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(|name| name.as_str())
+            .with_context(|| format!("Missing package name in {:?}", manifest))?
+            .to_string();
+
+        let dir_name = dir.file_name().context("Invalid contract directory")?;
+        contracts.push(ContractCrate {
+            name,
+            dir: Path::new(CONTRACTS_DIR).join(dir_name),
+        });
+    }
+
+    contracts.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(contracts)
+}
+
+/// All contracts in the project, or just the named one
+pub fn select_contracts(project_path: &Path, contract: Option<&str>) -> Result<Vec<ContractCrate>> {
+    let contracts = discover_contracts(project_path)?;
+
+    match contract {
+        Some(name) => {
+            let selected: Vec<_> = contracts.into_iter().filter(|c| c.name == name).collect();
+            if selected.is_empty() {
+                return Err(anyhow::anyhow!("Contract {} not found in project", name));
+            }
+            Ok(selected)
+        }
+        None if contracts.is_empty() => Err(anyhow::anyhow!("Project has no contract crates")),
+        None => Ok(contracts),
+    }
+}
+
+/// The contract to deploy or run: the named contract, else the default
+/// contract, else the only contract in the project
+fn deployable_contract(project_path: &Path, contract: Option<&str>) -> Result<ContractCrate> {
+    let mut contracts = select_contracts(project_path, contract)?;
+
+    if contracts.len() == 1 {
+        return Ok(contracts.remove(0));
+    }
+    contracts
+        .into_iter()
+        .find(|c| c.name == DEFAULT_CONTRACT)
+        .context("Project has several contracts; specify which one to use")
+}
+
+/// Locate the built wasm to deploy
+pub fn find_contract_wasm(
+    project_path: &Path,
+    contract: Option<&str>,
+    variant: WasmVariant,
+) -> Result<PathBuf> {
+    let contract_crate = deployable_contract(project_path, contract)?;
+
+    let wasm_path = contract_crate.wasm_path(project_path, variant);
+    if !wasm_path.exists() {
+        return Err(anyhow::anyhow!(
+            "No WASM file found for contract {}. Please compile the project first.",
+            contract_crate.name
+        ));
+    }
+
+    Ok(wasm_path)
+}
+
+/// Whether a contract's build with logs is missing or older than its release
+/// build, i.e. than the last successful compile
+pub fn logs_build_stale(project: &ProjectRef, contract: Option<&str>) -> Result<bool> {
+    let project_path = storage::project_dir(project)?;
+    if !project_path.exists() {
+        return Err(anyhow::anyhow!("Project directory not found: {:?}", project_path));
+    }
+    let contract_crate = deployable_contract(&project_path, contract)?;
+    let built_at = |variant| {
+        fs::metadata(contract_crate.wasm_path(&project_path, variant))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+
+    let release_built = built_at(WasmVariant::Release).with_context(|| {
+        format!(
+            "No WASM file found for contract {}. Please compile the project first.",
+            contract_crate.name
+        )
+    })?;
+    Ok(built_at(WasmVariant::WithLogs).is_none_or(|logs_built| logs_built < release_built))
+}
+
+/// Build a contract with logs from the sources of its last compile, unless
+/// that build is up to date. Its output is not streamed anywhere, and a
+/// failure leaves the release build as it is.
+pub async fn build_with_logs(
+    project: &ProjectRef,
+    contract: Option<&str>,
+    build: BuildEnv<'_>,
+) -> Result<()> {
+    // Another request may have built it while this one waited for the project
+    if !logs_build_stale(project, contract)? {
+        return Ok(());
+    }
+
+    let project_path = storage::project_dir(project)?;
+    let contract_crate = deployable_contract(&project_path, contract)?;
+    info!(
+        "Building contract {} of project {} with logs",
+        contract_crate.name, project
+    );
+    target_cache::seed(build.sdk, &project_path, BuildProfile::ReleaseWithLogs)?;
+
+    let output = run_contract_build(
+        &project_path,
+        &contract_crate,
+        WasmVariant::WithLogs,
+        build,
+        &|_, _| {},
+    )
+    .await?;
+    if let Some(reason) = output.terminated {
+        return Err(anyhow::anyhow!("{}", reason.message(&build.control.limits)));
+    }
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to build contract {} with logs: {}",
+            contract_crate.name,
+            output.stderr
+        ));
+    }
+    Ok(())
+}
+
+/// Make sure the user's project workspace exists, holds the given sources
+/// and depends on the requested soroban-sdk version
+pub fn prepare_project(
+    project: &ProjectRef,
+    sources: &ContractSources,
+    sdk: &SdkVersion,
+) -> Result<PathBuf> {
+    // Use persistent project directory structure
+    let base_project_path = storage::base_project_dir();
+    let project_path = storage::project_dir(project)?;
+
+    info!(
+        "Preparing project {} in persistent directory: {:?}",
+        project, project_path
+    );
+
+    // Ensure the project exists (copy from base if needed)
+    setup_user_project(&base_project_path, &project_path, sources)?;
+    sdk.apply_to_workspace(&project_path)?;
+
+    Ok(project_path)
+}
+
+fn setup_user_project(
+    base_project_path: &Path,
+    project_path: &Path,
+    sources: &ContractSources,
+) -> Result<()> {
     // Check if user project already exists
     if !project_path.exists() {
         info!("Creating new project by copying base project workspace template");
@@ -81,23 +516,109 @@ fn setup_user_project(base_project_path: &PathBuf, project_path: &PathBuf, code:
         debug!("Base project workspace copied to: {:?}", project_path);
     }
 
-    // Always update lib.rs with user's code in the workspace contract
-    // New Stellar CLI structure uses workspace with contracts/hello-world/src/lib.rs
-    let lib_rs_path = project_path
-        .join("contracts")
-        .join("hello-world")
-        .join("src")
-        .join("lib.rs");
+    // Write the user's sources into the workspace contracts
+    // New Stellar CLI structure uses workspace with contracts/<name>/src/lib.rs
+    for (contract, files) in &sources.contracts {
+        let crate_path = project_path.join(CONTRACTS_DIR).join(contract);
+        if !crate_path.join("Cargo.toml").exists() {
+            scaffold_contract_crate(base_project_path, &crate_path, contract)?;
+        }
+
+        for (relative_path, contents) in files {
+            let file_path = crate_path.join(relative_path);
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory for {}", relative_path))?;
+            }
+            fs::write(&file_path, contents)
+                .with_context(|| format!("Failed to write user source file {}", relative_path))?;
+        }
+
+        if sources.replace_all {
+            remove_stale_sources(&crate_path.join("src"), &crate_path, files)?;
+        }
+
+        debug!(
+            "Updated {} source files in contract {} with user code",
+            files.len(),
+            contract
+        );
+    }
+
+    Ok(())
+}
+
+/// Create a new contract crate using the default contract's manifest as a template
+fn scaffold_contract_crate(base_project_path: &Path, crate_path: &Path, name: &str) -> Result<()> {
+    info!("Creating new contract crate {}", name);
+
+    let template_path = base_project_path
+        .join(CONTRACTS_DIR)
+        .join(DEFAULT_CONTRACT)
+        .join("Cargo.toml");
+    let mut manifest = fs::read_to_string(&template_path)
+        .context("Failed to read contract manifest template")?
+        .parse::<toml_edit::DocumentMut>()
+        .context("Failed to parse contract manifest template")?;
+    manifest["package"]["name"] = toml_edit::value(name);
+
+    fs::create_dir_all(crate_path.join("src")).context("Failed to create contract crate")?;
+    fs::write(crate_path.join("Cargo.toml"), manifest.to_string())
+        .context("Failed to write contract manifest")?;
+
+    Ok(())
+}
+
+/// Delete files under `dir` that are not part of the submitted sources,
+/// removing directories left empty
+fn remove_stale_sources(
+    dir: &Path,
+    crate_path: &Path,
+    files: &BTreeMap<String, String>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir).context("Failed to read contract source directory")? {
+        let path = entry.context("Failed to read directory entry")?.path();
+
+        if path.is_dir() {
+            remove_stale_sources(&path, crate_path, files)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)
+                    .with_context(|| format!("Failed to remove directory {:?}", path))?;
+            }
+            continue;
+        }
 
-    fs::write(&lib_rs_path, code)
-        .context("Failed to write user contract code")?;
+        let relative_path = path
+            .strip_prefix(crate_path)
+            .context("Source file outside of contract crate")?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if !files.contains_key(&relative_path) {
+            debug!("Removing source file no longer in project: {}", relative_path);
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove source file {}", relative_path))?;
+        }
+    }
 
-    debug!("Updated contracts/hello-world/src/lib.rs with user code");
     Ok(())
 }
 
-fn run_stellar_build(project_path: &PathBuf) -> Result<std::process::Output> {
-    debug!("Running stellar contract build in workspace directory: {:?}", project_path);
+async fn run_contract_build(
+    project_path: &Path,
+    contract: &ContractCrate,
+    variant: WasmVariant,
+    build: BuildEnv<'_>,
+    on_output: OutputSink<'_>,
+) -> Result<BuildOutput> {
+    debug!(
+        "Building contract {} ({}) in workspace directory: {:?}",
+        contract.name,
+        variant.profile(),
+        project_path
+    );
 
     // Ensure both wasm32 targets are installed
     // wasm32v1-none is required by Stellar CLI 23.x+
@@ -105,7 +626,8 @@ fn run_stellar_build(project_path: &PathBuf) -> Result<std::process::Output> {
         .arg("target")
         .arg("add")
         .arg("wasm32v1-none")
-        .output();
+        .output()
+        .await;
 
     if let Ok(output) = rustup_result {
         if !output.status.success() {
@@ -118,119 +640,274 @@ fn run_stellar_build(project_path: &PathBuf) -> Result<std::process::Output> {
         .arg("target")
         .arg("add")
         .arg("wasm32-unknown-unknown")
-        .output();
-
-    // Run stellar contract build
-    let output = Command::new("stellar")
-        .arg("contract")
-        .arg("build")
-        .current_dir(project_path)
         .output()
-        .context("Failed to execute stellar contract build. Make sure 'stellar' CLI is installed.")?;
+        .await;
+
+    // JSON messages so diagnostics can be returned in structured form
+    let mut command = build.sandbox.cargo(project_path, build.sdk, Network::Denied)?;
+    add_wasm_build_args(&mut command, contract, variant);
+    command.arg("--message-format=json");
+
+    let output = run_cargo(&mut command, build.control, on_output).await?;
 
     debug!(
-        "stellar contract build completed with exit code: {:?}",
-        output.status.code()
+        "Contract build completed with exit code: {:?}, {} diagnostics",
+        output.status.code(),
+        output.diagnostics.len()
     );
 
     Ok(output)
 }
 
-fn extract_compilation_artifacts(project_path: &PathBuf) -> Result<(Option<u64>, Option<serde_json::Value>)> {
-    // Look for WASM file in target/wasm32v1-none/release directory
-    // Stellar CLI 23.x uses wasm32v1-none target instead of wasm32-unknown-unknown
-    let release_dir = project_path
-        .join("target")
-        .join("wasm32v1-none")
-        .join("release");
-
-    let wasm_size = if release_dir.exists() {
-        // Find .wasm files
-        let wasm_files: Vec<_> = fs::read_dir(&release_dir)
-            .context("Failed to read release directory")?
-            .filter_map(|entry| {
-                let entry = entry.ok()?;
-                let path = entry.path();
-                if path.extension()? == "wasm" {
-                    Some(path)
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        if let Some(wasm_file) = wasm_files.first() {
-            info!("Found WASM file: {:?}", wasm_file);
-            fs::metadata(wasm_file)
-                .map(|metadata| metadata.len())
-                .ok()
-        } else {
-            warn!("No WASM file found in release directory");
-            None
+/// The cargo invocation `stellar contract build` uses for one contract crate.
+/// It produces a release wasm but, like that command without `--optimize`,
+/// does not run the optimizer over it. The logs variant is what
+/// `stellar contract build --profile release-with-logs` runs.
+pub fn add_wasm_build_args(command: &mut Command, contract: &ContractCrate, variant: WasmVariant) {
+    command
+        .arg("rustc")
+        .arg("--manifest-path")
+        .arg(contract.manifest_path())
+        .arg("--crate-type=cdylib")
+        .arg(format!("--target={}", WASM_TARGET))
+        .arg(format!("--profile={}", variant.profile()));
+}
+
+/// Download the project's dependencies into the SDK version's cache. This
+/// is the only sandboxed step with network access and runs no user code.
+pub async fn fetch_dependencies(
+    project_path: &Path,
+    build: BuildEnv<'_>,
+    on_output: OutputSink<'_>,
+) -> Result<BuildOutput> {
+    let mut command = build.sandbox.cargo(project_path, build.sdk, Network::Allowed)?;
+    command.arg("fetch");
+    run_cargo(&mut command, build.control, on_output).await
+}
+
+/// Run a cargo command that was given `--message-format=json`, forwarding
+/// output lines to `on_output` as they arrive and collecting diagnostics.
+///
+/// The whole process group is killed when the job's deadline passes, the job
+/// is cancelled or the output grows past the limit.
+pub async fn run_cargo(
+    command: &mut Command,
+    control: &BuildControl,
+    on_output: OutputSink<'_>,
+) -> Result<BuildOutput> {
+    control.limits.apply(command);
+
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to execute cargo. Make sure the Rust toolchain is installed.")?;
+
+    let mut stdout_lines = BufReader::new(child.stdout.take().context("Missing build stdout")?).lines();
+    let mut stderr_lines = BufReader::new(child.stderr.take().context("Missing build stderr")?).lines();
+
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut diagnostics = Vec::new();
+    let mut stdout_done = false;
+    let mut stderr_done = false;
+    let mut output_bytes = 0;
+    let mut terminated = None;
+
+    while !(stdout_done && stderr_done) {
+        if output_bytes > control.limits.max_output_bytes {
+            terminated = Some(BuildTermination::ResourceLimit);
+        }
+        if let Some(reason) = terminated {
+            warn!("Stopping build: {:?}", reason);
+            build_limits::kill_process_group(&child);
+            break;
         }
-    } else {
-        warn!("Release directory not found");
-        None
-    };
 
-    // Extract contract spec using stellar contract inspect
-    let spec = if let Some(wasm_path) = find_wasm_file(&release_dir) {
-        match extract_contract_spec(&wasm_path) {
-            Ok(spec_json) => Some(spec_json),
-            Err(e) => {
-                warn!("Failed to extract contract spec: {}", e);
-                None
+        tokio::select! {
+            _ = tokio::time::sleep_until(control.deadline) => {
+                terminated = Some(BuildTermination::Timeout);
+            }
+            _ = control.cancel.cancelled() => {
+                terminated = Some(BuildTermination::Cancelled);
+            }
+            line = stdout_lines.next_line(), if !stdout_done => {
+                match line.context("Failed to read build stdout")? {
+                    Some(line) => {
+                        output_bytes += line.len() + 1;
+                        match parse_cargo_line(&line) {
+                            CargoLine::Diagnostic(diagnostic) => {
+                                // Keep stderr looking like a plain `cargo build` run
+                                if let Some(rendered) = &diagnostic.rendered {
+                                    for rendered_line in rendered.lines() {
+                                        on_output(OutputStream::Stderr, rendered_line);
+                                    }
+                                    stderr.push_str(rendered);
+                                }
+                                diagnostics.push(*diagnostic);
+                            }
+                            CargoLine::Message => {}
+                            CargoLine::Text => {
+                                on_output(OutputStream::Stdout, &line);
+                                stdout.push_str(&line);
+                                stdout.push('\n');
+                            }
+                        }
+                    }
+                    None => stdout_done = true,
+                }
+            }
+            line = stderr_lines.next_line(), if !stderr_done => {
+                match line.context("Failed to read build stderr")? {
+                    Some(line) => {
+                        output_bytes += line.len() + 1;
+                        on_output(OutputStream::Stderr, &line);
+                        stderr.push_str(&line);
+                        stderr.push('\n');
+                    }
+                    None => stderr_done = true,
+                }
             }
         }
-    } else {
-        None
+    }
+
+    let status = child
+        .wait()
+        .await
+        .context("Failed to wait for cargo")?;
+
+    if terminated.is_none() && !status.success() && build_limits::hit_resource_limit(&status, &stderr) {
+        terminated = Some(BuildTermination::ResourceLimit);
+    }
+
+    Ok(BuildOutput {
+        status,
+        stdout,
+        stderr,
+        diagnostics,
+        terminated,
+    })
+}
+
+fn profile_dir(project_path: &Path, variant: WasmVariant) -> PathBuf {
+    project_path.join("target").join(WASM_TARGET).join(variant.profile())
+}
+
+fn collect_artifact(project_path: &Path, contract: &ContractCrate) -> Result<ContractArtifact> {
+    let wasm_path = contract.wasm_path(project_path, WasmVariant::Release);
+    info!("Found WASM file: {:?}", wasm_path);
+
+    let wasm = fs::read(&wasm_path)
+        .with_context(|| format!("Failed to read built wasm for contract {}", contract.name))?;
+
+    let spec = match parse_contract_spec(&wasm) {
+        Ok(spec) => Some(spec),
+        Err(e) => {
+            warn!("Failed to parse contract spec for {}: {}", contract.name, e);
+            None
+        }
     };
 
-    Ok((wasm_size, spec))
+    Ok(ContractArtifact {
+        name: contract.name.clone(),
+        wasm_size: wasm.len() as u64,
+        wasm_hash: hex::encode(Sha256::digest(&wasm)),
+        spec,
+    })
 }
 
-fn find_wasm_file(dir: &PathBuf) -> Option<PathBuf> {
-    if !dir.exists() {
-        return None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected(path: &str) -> String {
+        match validate_source_path(path, "hello-world") {
+            Ok(resolved) => panic!("{:?} was accepted as {:?}", path, resolved),
+            Err(e) => e.to_string(),
+        }
     }
 
-    fs::read_dir(dir).ok()?
-        .filter_map(|entry| {
-            let entry = entry.ok()?;
-            let path = entry.path();
-            if path.extension()? == "wasm" {
-                Some(path)
-            } else {
-                None
-            }
-        })
-        .next()
-}
+    #[test]
+    fn paths_resolve_to_a_contract() {
+        let valid = [
+            ("src/lib.rs", ("hello-world", "src/lib.rs")),
+            ("./src/test.rs", ("hello-world", "src/test.rs")),
+            ("src/storage/mod.rs", ("hello-world", "src/storage/mod.rs")),
+            ("contracts/token/src/lib.rs", ("token", "src/lib.rs")),
+            ("contracts/token/./src/admin.rs", ("token", "src/admin.rs")),
+        ];
+        for (path, (contract, file)) in valid {
+            let resolved = validate_source_path(path, "hello-world").unwrap();
+            assert_eq!(
+                resolved,
+                (contract.to_string(), file.to_string()),
+                "{}",
+                path
+            );
+        }
+    }
 
-fn extract_contract_spec(wasm_path: &PathBuf) -> Result<serde_json::Value> {
-    info!("Extracting contract spec from: {:?}", wasm_path);
+    #[test]
+    fn parent_directories_are_rejected() {
+        for path in [
+            "../src/lib.rs",
+            "src/../../lib.rs",
+            "contracts/token/../hello-world/src/lib.rs",
+            "contracts/../../src/lib.rs",
+        ] {
+            assert!(rejected(path).contains("must not contain '..'"), "{}", path);
+        }
+    }
 
-    // Use stellar contract inspect to get the spec
-    let output = Command::new("stellar")
-        .arg("contract")
-        .arg("bindings")
-        .arg("json")
-        .arg("--wasm")
-        .arg(wasm_path)
-        .output()
-        .context("Failed to execute stellar contract bindings")?;
+    #[test]
+    fn absolute_paths_are_rejected() {
+        for path in [
+            "/src/lib.rs",
+            "/etc/passwd",
+            "/root/contracts/token/src/lib.rs",
+        ] {
+            assert!(rejected(path).contains("must be relative"), "{}", path);
+        }
+    }
 
-    if !output.status.success() {
-        return Err(anyhow::anyhow!(
-            "stellar contract bindings failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
+    #[test]
+    fn paths_outside_src_are_rejected() {
+        for path in [
+            "Cargo.toml",
+            "build.rs",
+            "tests/test.rs",
+            "contracts/token/Cargo.toml",
+            "contracts/token/tests/test.rs",
+            "lib.rs",
+        ] {
+            assert!(
+                rejected(path).contains("under a contract's src/"),
+                "{}",
+                path
+            );
+        }
     }
 
-    let spec_str = String::from_utf8_lossy(&output.stdout);
-    let spec: serde_json::Value = serde_json::from_str(&spec_str)
-        .context("Failed to parse contract spec JSON")?;
+    #[test]
+    fn empty_names_are_rejected() {
+        // No path, no file under src/, and no contract name
+        for path in ["", ".", "src", "src/", "contracts/token/src"] {
+            assert!(
+                rejected(path).contains("under a contract's src/"),
+                "{}",
+                path
+            );
+        }
+        for path in ["contracts", "contracts/"] {
+            assert!(rejected(path).contains("missing contract name"), "{}", path);
+        }
+    }
 
-    info!("Successfully extracted contract spec");
-    Ok(spec)
+    #[test]
+    fn contract_names_are_validated() {
+        for path in ["contracts/Token/src/lib.rs", "contracts/-token/src/lib.rs"] {
+            assert!(rejected(path).contains("Invalid contract name"), "{}", path);
+        }
+    }
 }
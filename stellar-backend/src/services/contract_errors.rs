@@ -0,0 +1,328 @@
+use stellar_xdr::curr::{ContractEventBody, DiagnosticEvent, ScError, ScErrorCode, ScVal};
+
+use crate::models::ContractFailure;
+use crate::services::scval::ContractInterface;
+
+/// Find why a call failed and describe it. `message` is the error text RPC
+/// returned, if any, which names the error the call ended with; otherwise
+/// the first error the host recorded in the diagnostic events is used.
+pub fn contract_failure(
+    message: Option<&str>,
+    events: &[DiagnosticEvent],
+    interface: Option<&ContractInterface>,
+) -> Option<ContractFailure> {
+    let error = message
+        .and_then(parse_error)
+        .or_else(|| event_error(events))?;
+    Some(describe(&error, interface))
+}
+
+/// Describe an error, naming contract errors after the spec's error enum
+pub fn describe(error: &ScError, interface: Option<&ContractInterface>) -> ContractFailure {
+    match error {
+        ScError::Contract(code) => {
+            let case = interface.and_then(|interface| interface.error_case(*code));
+            let explanation = match &case {
+                Some((name, doc)) if !doc.is_empty() => {
+                    format!("The contract returned error #{} ({}): {}", code, name, doc)
+                }
+                Some((name, _)) => format!("The contract returned error #{} ({})", code, name),
+                None => format!(
+                    "The contract returned error #{}, which its spec does not name",
+                    code
+                ),
+            };
+            let (name, doc) = case.unzip();
+            ContractFailure {
+                error_type: error.name().to_string(),
+                code: *code,
+                name,
+                doc: doc.filter(|doc| !doc.is_empty()),
+                explanation,
+            }
+        }
+        ScError::WasmVm(code)
+        | ScError::Context(code)
+        | ScError::Storage(code)
+        | ScError::Object(code)
+        | ScError::Crypto(code)
+        | ScError::Events(code)
+        | ScError::Budget(code)
+        | ScError::Value(code)
+        | ScError::Auth(code) => ContractFailure {
+            error_type: error.name().to_string(),
+            code: *code as u32,
+            name: Some(code.name().to_string()),
+            doc: None,
+            explanation: format!("{} ({})", host_explanation(error), code_explanation(code)),
+        },
+    }
+}
+
+/// Parse the first `Error(Type, Code)` in an RPC error message, e.g.
+/// `HostError: Error(Contract, #3)` or `Error(Budget, ExceededLimit)`
+fn parse_error(message: &str) -> Option<ScError> {
+    message.match_indices("Error(").find_map(|(start, _)| {
+        let rest = &message[start + "Error(".len()..];
+        let (error_type, code) = rest[..rest.find(')')?].split_once(',')?;
+        let code = code.trim();
+
+        if error_type.trim() == "Contract" {
+            return code
+                .trim_start_matches('#')
+                .parse()
+                .ok()
+                .map(ScError::Contract);
+        }
+        let code = *ScErrorCode::VARIANTS
+            .iter()
+            .find(|variant| variant.name() == code)?;
+        Some(match error_type.trim() {
+            "WasmVm" => ScError::WasmVm(code),
+            "Context" => ScError::Context(code),
+            "Storage" => ScError::Storage(code),
+            "Object" => ScError::Object(code),
+            "Crypto" => ScError::Crypto(code),
+            "Events" => ScError::Events(code),
+            "Budget" => ScError::Budget(code),
+            "Value" => ScError::Value(code),
+            "Auth" => ScError::Auth(code),
+            _ => return None,
+        })
+    })
+}
+
+/// The host logs each error as a diagnostic event with an `error` topic;
+/// the first one is where the failure started
+fn event_error(events: &[DiagnosticEvent]) -> Option<ScError> {
+    events.iter().find_map(|event| {
+        let ContractEventBody::V0(body) = &event.event.body;
+        match body.topics.as_slice() {
+            [ScVal::Symbol(topic), ScVal::Error(error), ..] if topic.0.as_slice() == b"error" => {
+                Some(error.clone())
+            }
+            _ => None,
+        }
+    })
+}
+
+fn host_explanation(error: &ScError) -> &'static str {
+    match error {
+        ScError::Contract(_) => "The contract returned an error",
+        ScError::WasmVm(_) => "The contract's wasm trapped, e.g. it panicked or hit unreachable code",
+        ScError::Context(_) => "The call was not valid in its context, e.g. a cross-contract call failed",
+        ScError::Storage(_) => {
+            "A storage access failed, e.g. a missing or archived entry, or one outside the footprint"
+        }
+        ScError::Object(_) => "A host object was used incorrectly, e.g. an index out of bounds",
+        ScError::Crypto(_) => "A cryptographic operation failed, e.g. an invalid signature",
+        ScError::Events(_) => "Emitting an event failed",
+        ScError::Budget(_) => "The call ran out of its CPU or memory budget",
+        ScError::Value(_) => "A value could not be converted, e.g. an argument of the wrong type",
+        ScError::Auth(_) => "Authorization failed: a required signature is missing or invalid",
+    }
+}
+
+fn code_explanation(code: &ScErrorCode) -> &'static str {
+    match code {
+        ScErrorCode::ArithDomain => "arithmetic overflow or division by zero",
+        ScErrorCode::IndexBounds => "index out of bounds",
+        ScErrorCode::InvalidInput => "invalid input",
+        ScErrorCode::MissingValue => "missing value",
+        ScErrorCode::ExistingValue => "value already exists",
+        ScErrorCode::ExceededLimit => "limit exceeded",
+        ScErrorCode::InvalidAction => "invalid action",
+        ScErrorCode::InternalError => "internal host error",
+        ScErrorCode::UnexpectedType => "unexpected type",
+        ScErrorCode::UnexpectedSize => "unexpected size",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stellar_xdr::curr::{
+        ContractEvent, ContractEventType, ContractEventV0, ExtensionPoint, ScSpecEntry,
+        ScSpecUdtErrorEnumCaseV0, ScSpecUdtErrorEnumV0, ScSymbol,
+    };
+
+    fn interface() -> ContractInterface {
+        let case = |name: &str, doc: &str, value| ScSpecUdtErrorEnumCaseV0 {
+            doc: doc.try_into().unwrap(),
+            name: name.try_into().unwrap(),
+            value,
+        };
+        ContractInterface::new(vec![ScSpecEntry::UdtErrorEnumV0(ScSpecUdtErrorEnumV0 {
+            doc: Default::default(),
+            lib: Default::default(),
+            name: "Error".try_into().unwrap(),
+            cases: vec![
+                case(
+                    "AlreadyInitialized",
+                    "The contract was already initialized",
+                    1,
+                ),
+                case("NotFound", "", 3),
+            ]
+            .try_into()
+            .unwrap(),
+        })])
+    }
+
+    fn error_event(error: ScError) -> DiagnosticEvent {
+        DiagnosticEvent {
+            in_successful_contract_call: false,
+            event: ContractEvent {
+                ext: ExtensionPoint::V0,
+                contract_id: None,
+                type_: ContractEventType::Diagnostic,
+                body: ContractEventBody::V0(ContractEventV0 {
+                    topics: vec![
+                        ScVal::Symbol(ScSymbol("error".try_into().unwrap())),
+                        ScVal::Error(error),
+                    ]
+                    .try_into()
+                    .unwrap(),
+                    data: ScVal::Void,
+                }),
+            },
+        }
+    }
+
+    #[test]
+    fn contract_errors_are_named_by_the_spec() {
+        let message = "HostError: Error(Contract, #1)\n\nEvent log (newest first):\n   \
+                       0: [Diagnostic Event] topics:[error, Error(Contract, #1)]";
+        assert_eq!(parse_error(message), Some(ScError::Contract(1)));
+
+        let failure = contract_failure(Some(message), &[], Some(&interface())).unwrap();
+        assert_eq!(failure.error_type, "Contract");
+        assert_eq!(failure.code, 1);
+        assert_eq!(failure.name.as_deref(), Some("AlreadyInitialized"));
+        assert_eq!(
+            failure.doc.as_deref(),
+            Some("The contract was already initialized")
+        );
+        assert_eq!(
+            failure.explanation,
+            "The contract returned error #1 (AlreadyInitialized): The contract was already initialized"
+        );
+    }
+
+    #[test]
+    fn contract_errors_without_a_spec_case() {
+        let failure = describe(&ScError::Contract(3), Some(&interface()));
+        assert_eq!(failure.name.as_deref(), Some("NotFound"));
+        assert_eq!(failure.doc, None);
+        assert_eq!(
+            failure.explanation,
+            "The contract returned error #3 (NotFound)"
+        );
+
+        let failure = describe(&ScError::Contract(7), Some(&interface()));
+        assert_eq!(failure.name, None);
+        assert_eq!(
+            failure.explanation,
+            "The contract returned error #7, which its spec does not name"
+        );
+        assert_eq!(describe(&ScError::Contract(1), None).name, None);
+    }
+
+    #[test]
+    fn host_error_categories() {
+        let cases = [
+            (
+                "WasmVm",
+                "InvalidAction",
+                ScError::WasmVm(ScErrorCode::InvalidAction),
+            ),
+            (
+                "Context",
+                "InternalError",
+                ScError::Context(ScErrorCode::InternalError),
+            ),
+            (
+                "Storage",
+                "MissingValue",
+                ScError::Storage(ScErrorCode::MissingValue),
+            ),
+            (
+                "Object",
+                "IndexBounds",
+                ScError::Object(ScErrorCode::IndexBounds),
+            ),
+            (
+                "Crypto",
+                "InvalidInput",
+                ScError::Crypto(ScErrorCode::InvalidInput),
+            ),
+            (
+                "Events",
+                "UnexpectedSize",
+                ScError::Events(ScErrorCode::UnexpectedSize),
+            ),
+            (
+                "Budget",
+                "ExceededLimit",
+                ScError::Budget(ScErrorCode::ExceededLimit),
+            ),
+            (
+                "Value",
+                "UnexpectedType",
+                ScError::Value(ScErrorCode::UnexpectedType),
+            ),
+            (
+                "Auth",
+                "InvalidAction",
+                ScError::Auth(ScErrorCode::InvalidAction),
+            ),
+        ];
+        for (error_type, code, expected) in cases {
+            let message = format!("HostError: Error({}, {})", error_type, code);
+            let error = parse_error(&message).unwrap();
+            assert_eq!(error, expected, "{}", message);
+
+            let failure = describe(&error, None);
+            assert_eq!(failure.error_type, error_type);
+            assert_eq!(failure.name.as_deref(), Some(code));
+            assert_eq!(failure.doc, None);
+        }
+
+        let failure = describe(&ScError::Budget(ScErrorCode::ExceededLimit), None);
+        assert_eq!(failure.code, ScErrorCode::ExceededLimit as u32);
+        assert_eq!(
+            failure.explanation,
+            "The call ran out of its CPU or memory budget (limit exceeded)"
+        );
+    }
+
+    #[test]
+    fn unknown_errors_are_not_parsed() {
+        assert_eq!(parse_error("transaction simulation failed"), None);
+        assert_eq!(parse_error("HostError: Error(Budget, NoSuchCode)"), None);
+        assert_eq!(parse_error("HostError: Error(Unknown, InvalidInput)"), None);
+        assert_eq!(parse_error("HostError: Error(Contract, #x)"), None);
+        assert_eq!(parse_error("HostError: Error(Contract"), None);
+        // A later well-formed error is still found
+        assert_eq!(
+            parse_error("Error(Storage) then Error(Contract, #2)"),
+            Some(ScError::Contract(2))
+        );
+    }
+
+    #[test]
+    fn events_are_used_without_a_message() {
+        let events = [
+            error_event(ScError::Auth(ScErrorCode::InvalidAction)),
+            error_event(ScError::Contract(1)),
+        ];
+        let failure = contract_failure(None, &events, None).unwrap();
+        assert_eq!(failure.error_type, "Auth");
+
+        // The message, when it names an error, wins over the events
+        let failure = contract_failure(Some("Error(Contract, #3)"), &events, None).unwrap();
+        assert_eq!((failure.error_type.as_str(), failure.code), ("Contract", 3));
+
+        assert!(contract_failure(Some("timeout"), &[], None).is_none());
+    }
+}
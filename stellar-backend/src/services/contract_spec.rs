@@ -0,0 +1,267 @@
+use anyhow::{Context, Result};
+use std::io::Cursor;
+use stellar_xdr::curr::{
+    Limited, Limits, ReadXdr, ScEnvMetaEntry, ScMetaEntry, ScSpecEntry, ScSpecEventDataFormat,
+    ScSpecEventParamLocationV0, ScSpecTypeDef, ScSpecUdtEnumV0, ScSpecUdtErrorEnumV0,
+    ScSpecUdtUnionCaseV0, StringM,
+};
+use wasmparser::{Parser, Payload};
+
+use crate::models::{
+    ContractSpec, SpecEnum, SpecEnumCase, SpecEnvMeta, SpecEvent, SpecEventDataFormat,
+    SpecEventParam, SpecEventParamLocation, SpecField, SpecFunction, SpecStruct, SpecType,
+    SpecUnion, SpecUnionCase,
+};
+
+const SPEC_SECTION: &str = "contractspecv0";
+const META_SECTION: &str = "contractmetav0";
+const ENV_META_SECTION: &str = "contractenvmetav0";
+
+// Type definitions nest (Option<Vec<Map<..>>>); cap the depth so a hostile
+// wasm cannot blow the stack while decoding
+const XDR_DEPTH_LIMIT: u32 = 100;
+
+/// Read the contract interface straight from a wasm's custom sections
+pub fn parse_contract_spec(wasm: &[u8]) -> Result<ContractSpec> {
+    let sections = CustomSections::read(wasm)?;
+    let mut spec = ContractSpec::default();
+
+    for entry in
+        read_entries::<ScSpecEntry>(&sections.spec).context("Invalid contract spec section")?
+    {
+        match entry {
+            ScSpecEntry::FunctionV0(function) => spec.functions.push(SpecFunction {
+                name: function.name.to_utf8_string_lossy(),
+                doc: text(&function.doc),
+                inputs: function
+                    .inputs
+                    .iter()
+                    .map(|input| SpecField {
+                        name: text(&input.name),
+                        doc: text(&input.doc),
+                        type_def: spec_type(&input.type_),
+                    })
+                    .collect(),
+                outputs: function.outputs.iter().map(spec_type).collect(),
+            }),
+            ScSpecEntry::UdtStructV0(udt) => spec.structs.push(SpecStruct {
+                name: text(&udt.name),
+                doc: text(&udt.doc),
+                lib: text(&udt.lib),
+                fields: udt
+                    .fields
+                    .iter()
+                    .map(|field| SpecField {
+                        name: text(&field.name),
+                        doc: text(&field.doc),
+                        type_def: spec_type(&field.type_),
+                    })
+                    .collect(),
+            }),
+            ScSpecEntry::UdtUnionV0(udt) => spec.unions.push(SpecUnion {
+                name: text(&udt.name),
+                doc: text(&udt.doc),
+                lib: text(&udt.lib),
+                cases: udt
+                    .cases
+                    .iter()
+                    .map(|case| match case {
+                        ScSpecUdtUnionCaseV0::VoidV0(case) => SpecUnionCase {
+                            name: text(&case.name),
+                            doc: text(&case.doc),
+                            values: Vec::new(),
+                        },
+                        ScSpecUdtUnionCaseV0::TupleV0(case) => SpecUnionCase {
+                            name: text(&case.name),
+                            doc: text(&case.doc),
+                            values: case.type_.iter().map(spec_type).collect(),
+                        },
+                    })
+                    .collect(),
+            }),
+            ScSpecEntry::UdtEnumV0(udt) => spec.enums.push(spec_enum(&udt)),
+            ScSpecEntry::UdtErrorEnumV0(udt) => spec.error_enums.push(spec_error_enum(&udt)),
+            ScSpecEntry::EventV0(event) => spec.events.push(SpecEvent {
+                name: event.name.to_utf8_string_lossy(),
+                doc: text(&event.doc),
+                lib: text(&event.lib),
+                prefix_topics: event
+                    .prefix_topics
+                    .iter()
+                    .map(|topic| topic.to_utf8_string_lossy())
+                    .collect(),
+                params: event
+                    .params
+                    .iter()
+                    .map(|param| SpecEventParam {
+                        name: text(&param.name),
+                        doc: text(&param.doc),
+                        type_def: spec_type(&param.type_),
+                        location: match param.location {
+                            ScSpecEventParamLocationV0::Data => SpecEventParamLocation::Data,
+                            ScSpecEventParamLocationV0::TopicList => {
+                                SpecEventParamLocation::TopicList
+                            }
+                        },
+                    })
+                    .collect(),
+                data_format: match event.data_format {
+                    ScSpecEventDataFormat::SingleValue => SpecEventDataFormat::SingleValue,
+                    ScSpecEventDataFormat::Vec => SpecEventDataFormat::Vec,
+                    ScSpecEventDataFormat::Map => SpecEventDataFormat::Map,
+                },
+            }),
+        }
+    }
+
+    for entry in
+        read_entries::<ScMetaEntry>(&sections.meta).context("Invalid contract meta section")?
+    {
+        let ScMetaEntry::ScMetaV0(meta) = entry;
+        spec.meta.insert(text(&meta.key), text(&meta.val));
+    }
+
+    if let Some(entry) = read_entries::<ScEnvMetaEntry>(&sections.env_meta)
+        .context("Invalid contract env meta section")?
+        .into_iter()
+        .next()
+    {
+        let ScEnvMetaEntry::ScEnvMetaKindInterfaceVersion(version) = entry;
+        spec.env_meta = Some(SpecEnvMeta {
+            protocol: version.protocol,
+            pre_release: version.pre_release,
+        });
+    }
+
+    Ok(spec)
+}
+
+/// Raw spec entries, for encoding and decoding values of the contract's types
+pub fn spec_entries(wasm: &[u8]) -> Result<Vec<ScSpecEntry>> {
+    let sections = CustomSections::read(wasm)?;
+    read_entries(&sections.spec).context("Invalid contract spec section")
+}
+
+#[derive(Default)]
+struct CustomSections {
+    spec: Vec<u8>,
+    meta: Vec<u8>,
+    env_meta: Vec<u8>,
+}
+
+impl CustomSections {
+    fn read(wasm: &[u8]) -> Result<Self> {
+        let mut sections = Self::default();
+
+        for payload in Parser::new(0).parse_all(wasm) {
+            if let Payload::CustomSection(section) = payload.context("Invalid wasm module")? {
+                match section.name() {
+                    SPEC_SECTION => sections.spec.extend_from_slice(section.data()),
+                    META_SECTION => sections.meta.extend_from_slice(section.data()),
+                    ENV_META_SECTION => sections.env_meta.extend_from_slice(section.data()),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(sections)
+    }
+}
+
+/// Decode a custom section, which is a plain concatenation of XDR values
+fn read_entries<T: ReadXdr>(bytes: &[u8]) -> Result<Vec<T>> {
+    let mut reader = Limited::new(
+        Cursor::new(bytes),
+        Limits {
+            depth: XDR_DEPTH_LIMIT,
+            len: bytes.len(),
+        },
+    );
+
+    T::read_xdr_iter(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("XDR decode error: {}", e))
+}
+
+fn text<const MAX: u32>(value: &StringM<MAX>) -> String {
+    value.to_utf8_string_lossy()
+}
+
+fn spec_enum(udt: &ScSpecUdtEnumV0) -> SpecEnum {
+    SpecEnum {
+        name: text(&udt.name),
+        doc: text(&udt.doc),
+        lib: text(&udt.lib),
+        cases: udt
+            .cases
+            .iter()
+            .map(|case| SpecEnumCase {
+                name: text(&case.name),
+                doc: text(&case.doc),
+                value: case.value,
+            })
+            .collect(),
+    }
+}
+
+fn spec_error_enum(udt: &ScSpecUdtErrorEnumV0) -> SpecEnum {
+    SpecEnum {
+        name: text(&udt.name),
+        doc: text(&udt.doc),
+        lib: text(&udt.lib),
+        cases: udt
+            .cases
+            .iter()
+            .map(|case| SpecEnumCase {
+                name: text(&case.name),
+                doc: text(&case.doc),
+                value: case.value,
+            })
+            .collect(),
+    }
+}
+
+fn spec_type(type_def: &ScSpecTypeDef) -> SpecType {
+    match type_def {
+        ScSpecTypeDef::Val => SpecType::Val,
+        ScSpecTypeDef::Bool => SpecType::Bool,
+        ScSpecTypeDef::Void => SpecType::Void,
+        ScSpecTypeDef::Error => SpecType::Error,
+        ScSpecTypeDef::U32 => SpecType::U32,
+        ScSpecTypeDef::I32 => SpecType::I32,
+        ScSpecTypeDef::U64 => SpecType::U64,
+        ScSpecTypeDef::I64 => SpecType::I64,
+        ScSpecTypeDef::Timepoint => SpecType::Timepoint,
+        ScSpecTypeDef::Duration => SpecType::Duration,
+        ScSpecTypeDef::U128 => SpecType::U128,
+        ScSpecTypeDef::I128 => SpecType::I128,
+        ScSpecTypeDef::U256 => SpecType::U256,
+        ScSpecTypeDef::I256 => SpecType::I256,
+        ScSpecTypeDef::Bytes => SpecType::Bytes,
+        ScSpecTypeDef::String => SpecType::String,
+        ScSpecTypeDef::Symbol => SpecType::Symbol,
+        ScSpecTypeDef::Address => SpecType::Address,
+        ScSpecTypeDef::MuxedAddress => SpecType::MuxedAddress,
+        ScSpecTypeDef::Option(option) => SpecType::Option {
+            value: Box::new(spec_type(&option.value_type)),
+        },
+        ScSpecTypeDef::Result(result) => SpecType::Result {
+            ok: Box::new(spec_type(&result.ok_type)),
+            error: Box::new(spec_type(&result.error_type)),
+        },
+        ScSpecTypeDef::Vec(vec) => SpecType::Vec {
+            element: Box::new(spec_type(&vec.element_type)),
+        },
+        ScSpecTypeDef::Map(map) => SpecType::Map {
+            key: Box::new(spec_type(&map.key_type)),
+            value: Box::new(spec_type(&map.value_type)),
+        },
+        ScSpecTypeDef::Tuple(tuple) => SpecType::Tuple {
+            elements: tuple.value_types.iter().map(spec_type).collect(),
+        },
+        ScSpecTypeDef::BytesN(bytes) => SpecType::BytesN { n: bytes.n },
+        ScSpecTypeDef::Udt(udt) => SpecType::Udt {
+            name: text(&udt.name),
+        },
+    }
+}
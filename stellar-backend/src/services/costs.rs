@@ -0,0 +1,68 @@
+use stellar_xdr::curr::{
+    ContractEventType, DiagnosticEvent, Limits, ScVal, Transaction, TransactionExt, WriteXdr,
+};
+
+use crate::models::{FeeBreakdown, ResourceUsage, TransactionCost};
+use crate::services::soroban_rpc::{SimulationCost, TransactionInfo};
+
+/// Resources a Soroban transaction declares and the fees they cost. `tx`
+/// must carry its resource footprint, i.e. be assembled from a simulation.
+/// `simulated` adds the measured CPU and memory use; `applied` adds the
+/// fees actually charged, which split the resource fee into its
+/// non-refundable, refundable and rent parts.
+pub fn transaction_cost(
+    tx: &Transaction,
+    simulated: Option<&SimulationCost>,
+    events: &[DiagnosticEvent],
+    return_value: Option<&ScVal>,
+    applied: Option<&TransactionInfo>,
+) -> Option<TransactionCost> {
+    let TransactionExt::V1(data) = &tx.ext else {
+        return None;
+    };
+    let resources = &data.resources;
+    let footprint = &resources.footprint;
+
+    let charged = applied.and_then(TransactionInfo::resource_fees_charged);
+
+    Some(TransactionCost {
+        resources: ResourceUsage {
+            cpu_instructions: simulated
+                .map(|cost| cost.cpu_instructions)
+                .unwrap_or(u64::from(resources.instructions)),
+            memory_bytes: simulated.map(|cost| cost.memory_bytes),
+            read_entries: (footprint.read_only.len() + footprint.read_write.len()) as u32,
+            write_entries: footprint.read_write.len() as u32,
+            read_bytes: resources.disk_read_bytes,
+            write_bytes: resources.write_bytes,
+            events_bytes: events_size(events, return_value),
+        },
+        fees: FeeBreakdown {
+            inclusion_fee: (i64::from(tx.fee) - data.resource_fee).to_string(),
+            resource_fee: data.resource_fee.to_string(),
+            non_refundable_fee: charged.map(|(fee, _, _)| fee.to_string()),
+            refundable_fee: charged.map(|(_, fee, _)| fee.to_string()),
+            rent_fee: charged.map(|(_, _, fee)| fee.to_string()),
+            fee_charged: applied
+                .and_then(TransactionInfo::fee_charged)
+                .map(|fee| fee.to_string()),
+        },
+    })
+}
+
+/// Encoded size of the contract events and return value, which the network
+/// charges for together
+fn events_size(events: &[DiagnosticEvent], return_value: Option<&ScVal>) -> u32 {
+    let events: usize = events
+        .iter()
+        .filter(|event| {
+            event.in_successful_contract_call && event.event.type_ == ContractEventType::Contract
+        })
+        .filter_map(|event| event.event.to_xdr(Limits::none()).ok())
+        .map(|bytes| bytes.len())
+        .sum();
+    let return_value = return_value
+        .and_then(|value| value.to_xdr(Limits::none()).ok())
+        .map_or(0, |bytes| bytes.len());
+    (events + return_value) as u32
+}
@@ -1,224 +1,247 @@
 use anyhow::{Context, Result};
-use log::{info, warn, error};
-use std::fs;
-use std::path::PathBuf;
+use log::{info, error};
+use rand::RngCore;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::env;
-use std::process::Command;
-use crate::models::{DeployDetails, DeployResponse};
+use stellar_xdr::curr::{
+    AccountId, ContractExecutable, ContractIdPreimage, ContractIdPreimageFromAddress, CreateContractArgsV2,
+    Hash, HostFunction, ScAddress, ScVal, Uint256,
+};
+use crate::models::{DeployDetails, DeployResponse, TransactionCost};
+use crate::services::{compilation, contract_spec, costs};
+use crate::services::compilation::WasmVariant;
+use crate::services::networks::NetworkProfile;
+use crate::services::scval::ContractInterface;
+use crate::services::soroban_rpc::{RpcClient, TransactionInfo};
+use crate::services::transaction::{self, Signer};
+use crate::storage::{self, ProjectRef};
 use chrono::Utc;
 
 pub async fn deploy_contract(
-    user_id: &str,
-    project_id: &str,
-    _account_secret: Option<&str>,
+    project: &ProjectRef,
+    contract: Option<&str>,
+    network: &NetworkProfile,
+    variant: WasmVariant,
+    constructor_args: Option<&Value>,
 ) -> Result<DeployResponse> {
     info!(
-        "Starting Stellar deployment for project {} by user {}",
-        project_id, user_id
+        "Starting Stellar deployment for project {} on {}",
+        project, network.name
     );
 
     // Load Stellar account credentials from environment
     let deployer_secret = env::var("STELLAR_SECRET_KEY")
         .context("STELLAR_SECRET_KEY not found in environment")?;
-    let rpc_url = env::var("STELLAR_RPC_URL")
-        .context("STELLAR_RPC_URL not found in environment")?;
-    let network_passphrase = env::var("STELLAR_NETWORK_PASSPHRASE")
-        .context("STELLAR_NETWORK_PASSPHRASE not found in environment")?;
-    let network = env::var("STELLAR_NETWORK").unwrap_or_else(|_| "testnet".to_string());
+    let signer = Signer::from_secret(&deployer_secret)?;
 
-    // Find the project directory
-    let project_path = PathBuf::from("projects").join(user_id).join(project_id);
-    if !project_path.exists() {
-        return Err(anyhow::anyhow!("Project directory not found: {:?}", project_path));
-    }
+    let wasm = load_wasm(project, contract, variant).await?;
+    let wasm_hash: [u8; 32] = Sha256::digest(&wasm).into();
 
-    // Find the compiled WASM file
-    // Stellar CLI 23.x uses wasm32v1-none target
-    let release_dir = project_path
-        .join("target")
-        .join("wasm32v1-none")
-        .join("release");
-
-    let wasm_files: Vec<_> = fs::read_dir(&release_dir)
-        .context("Failed to read release directory")?
-        .filter_map(|entry| {
-            let entry = entry.ok()?;
-            let path = entry.path();
-            if path.extension()? == "wasm" {
-                Some(path)
-            } else {
-                None
-            }
-        })
-        .collect();
+    // Check the constructor arguments before spending anything
+    let interface = ContractInterface::new(contract_spec::spec_entries(&wasm)?);
+    let constructor_args =
+        encode_constructor_args(&interface, constructor_args).map_err(|e| anyhow::anyhow!(e))?;
 
-    if wasm_files.is_empty() {
-        return Err(anyhow::anyhow!("No WASM file found. Please compile the project first."));
-    }
+    let rpc = RpcClient::new(&network.rpc_url)?;
 
-    let wasm_path = &wasm_files[0];
-    info!("Deploying WASM file: {:?}", wasm_path);
-
-    // Deploy using stellar CLI with explicit RPC URL and network passphrase
-    // Use --verbose to get transaction hash in stderr output
-    let mut cmd = Command::new("stellar");
-    cmd.arg("--verbose")
-        .arg("contract")
-        .arg("deploy")
-        .arg("--wasm")
-        .arg(wasm_path)
-        .arg("--source")
-        .arg(&deployer_secret)
-        .arg("--rpc-url")
-        .arg(&rpc_url)
-        .arg("--network-passphrase")
-        .arg(&network_passphrase);
-
-    let deploy_output = cmd
-        .output()
-        .context("Failed to execute stellar contract deploy")?;
-
-    // Log full output for debugging
-    let stdout_str = String::from_utf8_lossy(&deploy_output.stdout);
-    let stderr_str = String::from_utf8_lossy(&deploy_output.stderr);
-    info!("Deploy stdout: {}", stdout_str);
-    info!("Deploy stderr: {}", stderr_str);
-
-    if !deploy_output.status.success() {
-        error!("Deployment failed: {}", stderr_str);
-        return Err(anyhow::anyhow!("Deployment failed: {}", stderr_str));
-    }
-
-    // Helper function to extract transaction hash from Stellar CLI verbose output
-    fn extract_transaction_hash(stderr: &str) -> Option<String> {
-        // Stellar CLI outputs transaction hash in various formats:
-        // - "transaction hash: <hash>"
-        // - "Transaction hash is <hash>"
-        // - May include emoji characters before/after
-        for line in stderr.lines() {
-            let line_lower = line.to_lowercase();
-            if line_lower.contains("transaction hash") {
-                // Find the position after "transaction hash" phrase
-                if let Some(pos) = line_lower.find("transaction hash") {
-                    let after_phrase = &line[pos + 16..]; // Skip "transaction hash"
-                    // Extract the hash - it's a 64-character hex string
-                    for word in after_phrase.split_whitespace() {
-                        let cleaned = word.trim_matches(|c: char| !c.is_ascii_hexdigit());
-                        if cleaned.len() == 64 && cleaned.chars().all(|c| c.is_ascii_hexdigit()) {
-                            return Some(cleaned.to_string());
-                        }
-                    }
-                }
-            }
-        }
+    // Step 1: upload the code, unless it is already on-chain
+    let upload = if rpc.wasm_installed(&Hash(wasm_hash)).await? {
+        info!("Wasm {} is already uploaded", hex::encode(wasm_hash));
         None
-    }
-
-    // Parse the contract ID from output
-    // Stellar CLI outputs the contract ID as the last line of stdout
-    let contract_id = stdout_str
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .last()
-        .context("No contract ID in deployment output")?
-        .trim()
-        .to_string();
-
-    info!("Parsed contract ID: {}", contract_id);
-
-    // Validate contract ID format (should start with 'C' and be 56 chars)
-    if !contract_id.starts_with('C') || contract_id.len() != 56 {
-        warn!("Contract ID format looks suspicious: {}", contract_id);
-        warn!("Expected format: C followed by 55 characters (StrKey contract encoding)");
-    }
-
-    info!("Contract deployed successfully: {}", contract_id);
-
-    // Get deployer address (public key from secret key)
-    let deployer_address = get_public_key_from_secret(&deployer_secret)?;
-
-    // Extract real transaction hash from Stellar CLI verbose output
-    let transaction_hash = extract_transaction_hash(&stderr_str)
-        .context("Failed to extract transaction hash from deployment output. This suggests the deployment may not have succeeded on-chain.")?;
+    } else {
+        let (upload_hash, upload_info, upload_cost) = run_host_function(
+            &rpc,
+            &signer,
+            &network.passphrase,
+            upload_function(wasm)?,
+            "Wasm upload",
+        )
+        .await?;
+        info!("Uploaded wasm {} in transaction {}", hex::encode(wasm_hash), upload_hash);
+        Some((upload_hash, upload_info, upload_cost))
+    };
 
-    info!("Transaction hash: {}", transaction_hash);
+    // Step 2: create an instance of it
+    let salt = random_salt();
+    let (create_hash, create_info, create_cost) = run_host_function(
+        &rpc,
+        &signer,
+        &network.passphrase,
+        create_function(&signer.account_id(), wasm_hash, salt, constructor_args)?,
+        "Contract creation",
+    )
+    .await?;
+
+    let contract_id = match create_info.return_value() {
+        Some(ScVal::Address(address @ ScAddress::Contract(_))) => address.to_string(),
+        other => {
+            return Err(anyhow::anyhow!(
+                "Contract creation returned {:?} instead of a contract address",
+                other
+            ))
+        }
+    };
+    info!(
+        "Contract deployed successfully: {}, transaction hash: {}",
+        contract_id, create_hash
+    );
 
     let timestamp = Utc::now();
-    let explorer_url = format!(
-        "https://testnet.stellarchain.io/contracts/{}",
-        contract_id
-    );
+    let explorer_url = network
+        .contract_explorer_url(&contract_id)
+        .unwrap_or_default();
 
-    // Create proof-of-deployment transaction (optional, small payment)
-    let proof_tx_hash = send_proof_transfer(&deployer_secret, &network).await.ok();
+    let (upload_hash, upload_fee, upload_cost) = match upload {
+        Some((hash, info, cost)) => (Some(hash), info.fee_charged(), cost),
+        None => (None, None, None),
+    };
+    let create_fee = create_info.fee_charged();
+    let total_fee = match upload_hash {
+        Some(_) => upload_fee.zip(create_fee).map(|(upload, create)| upload + create),
+        None => create_fee,
+    };
 
     let response = DeployResponse {
         success: true,
-        transaction_hash: transaction_hash.clone(),
+        transaction_hash: create_hash,
         contract_id: contract_id.clone(),
         explorer_url,
-        fee: Some("100000".to_string()), // Approximate fee in stroops
-        proof_tx_hash,
+        fee: total_fee.map(|fee| fee.to_string()),
+        wasm_hash: hex::encode(wasm_hash),
+        upload_transaction_hash: upload_hash,
         details: DeployDetails {
-            network: network.clone(),
-            ledger_sequence: 0, // Would need to query the network for this
+            network: network.name.clone(),
+            ledger_sequence: create_info.ledger.unwrap_or_default(),
             timestamp,
-            deployer_address,
+            deployer_address: signer.address(),
+            salt: hex::encode(salt),
+            upload_fee: upload_fee.map(|fee| fee.to_string()),
+            create_fee: create_fee.map(|fee| fee.to_string()),
+            upload_cost,
+            create_cost,
         },
     };
 
     info!(
         "Successfully deployed contract {} for project {}",
-        contract_id, project_id
+        contract_id, project
     );
 
     Ok(response)
 }
 
-fn get_public_key_from_secret(secret_key: &str) -> Result<String> {
-    // Use stellar CLI to derive public key from secret
-    let output = Command::new("stellar")
-        .arg("keys")
-        .arg("address")
-        .arg(secret_key)
-        .output()
-        .context("Failed to get public key from secret")?;
-
-    if !output.status.success() {
-        return Err(anyhow::anyhow!("Failed to derive public key"));
+/// The compiled wasm of one of a project's contracts
+pub async fn load_wasm(
+    project: &ProjectRef,
+    contract: Option<&str>,
+    variant: WasmVariant,
+) -> Result<Vec<u8>> {
+    // Find the project directory
+    let project_path = storage::project_dir(project)?;
+    if !project_path.exists() {
+        return Err(anyhow::anyhow!("Project directory not found: {:?}", project_path));
     }
 
-    let address = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    Ok(address)
+    // Find the compiled WASM file
+    let wasm_path = compilation::find_contract_wasm(&project_path, contract, variant)?;
+    info!("Deploying WASM file: {:?}", wasm_path);
+    tokio::fs::read(&wasm_path)
+        .await
+        .with_context(|| format!("Failed to read {:?}", wasm_path))
+}
+
+pub fn upload_function(wasm: Vec<u8>) -> Result<HostFunction> {
+    Ok(HostFunction::UploadContractWasm(
+        wasm.try_into()
+            .map_err(|_| anyhow::anyhow!("Contract wasm is too large"))?,
+    ))
 }
 
-async fn send_proof_transfer(source_secret: &str, network: &str) -> Result<String> {
-    // Send a small proof-of-deployment transaction
-    // This is optional and helps track deployments
-    info!("Sending proof-of-deployment transaction");
-
-    let destination = env::var("STELLAR_PROOF_DESTINATION")
-        .unwrap_or_else(|_| source_secret.to_string()); // Send to self if no destination
-
-    let output = Command::new("stellar")
-        .arg("contract")
-        .arg("invoke")
-        .arg("--source")
-        .arg(source_secret)
-        .arg("--network")
-        .arg(network)
-        .arg("--id")
-        .arg(&destination)
-        .output();
-
-    match output {
-        Ok(result) if result.status.success() => {
-            let tx_hash = String::from_utf8_lossy(&result.stdout).trim().to_string();
-            Ok(tx_hash)
-        }
-        _ => {
-            warn!("Proof transfer failed, but continuing...");
-            Err(anyhow::anyhow!("Proof transfer failed"))
-        }
+/// Create a contract from uploaded wasm, passing its constructor the given
+/// arguments. Its id is derived from the deployer address and salt.
+pub fn create_function(
+    deployer: &AccountId,
+    wasm_hash: [u8; 32],
+    salt: [u8; 32],
+    constructor_args: Vec<ScVal>,
+) -> Result<HostFunction> {
+    Ok(HostFunction::CreateContractV2(CreateContractArgsV2 {
+        contract_id_preimage: ContractIdPreimage::Address(ContractIdPreimageFromAddress {
+            address: ScAddress::Account(deployer.clone()),
+            salt: Uint256(salt),
+        }),
+        executable: ContractExecutable::Wasm(Hash(wasm_hash)),
+        constructor_args: constructor_args
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Too many constructor arguments"))?,
+    }))
+}
+
+/// Encode the arguments of a contract's `__constructor`. Contracts without
+/// one take no arguments. Errors read like argument errors of a call.
+pub fn encode_constructor_args(
+    interface: &ContractInterface,
+    args: Option<&Value>,
+) -> std::result::Result<Vec<ScVal>, String> {
+    let Some(constructor) = interface.function("__constructor") else {
+        return Ok(Vec::new());
+    };
+    interface
+        .encode_args(constructor, args.unwrap_or(&Value::Null))
+        .map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            format!("Invalid constructor arguments: {}", errors.join("; "))
+        })
+}
+
+pub fn random_salt() -> [u8; 32] {
+    let mut salt = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt
+}
+
+/// Simulate, sign and submit a single host function transaction, returning
+/// its hash, the applied result and what it cost
+async fn run_host_function(
+    rpc: &RpcClient,
+    signer: &Signer,
+    network_passphrase: &str,
+    host_function: HostFunction,
+    step: &str,
+) -> Result<(String, TransactionInfo, Option<TransactionCost>)> {
+    let (tx, simulation) = transaction::simulate(rpc, &signer.account_id(), host_function).await?;
+
+    if let Some(error_msg) = &simulation.error {
+        error!("{} failed in simulation: {}", step, error_msg);
+        return Err(anyhow::anyhow!("{} failed in simulation: {}", step, error_msg));
     }
+    if simulation.restore_preamble.is_some() {
+        return Err(anyhow::anyhow!(
+            "{} needs archived ledger entries restored first",
+            step
+        ));
+    }
+
+    let tx = transaction::assemble(tx, &simulation)?;
+    let envelope = transaction::sign(tx.clone(), network_passphrase, signer)?;
+    let submission = transaction::submit(rpc, &envelope).await?;
+
+    if let Some(error_msg) = submission.failure() {
+        error!("{} failed: {}", step, error_msg);
+        return Err(anyhow::anyhow!("{} failed: {}", step, error_msg));
+    }
+    let info = submission
+        .info
+        .with_context(|| format!("{} transaction was not applied", step))?;
+
+    let cost = costs::transaction_cost(
+        &tx,
+        simulation.cost.as_ref(),
+        &info.events(),
+        info.return_value().as_ref(),
+        Some(&info),
+    );
+
+    Ok((submission.hash, info, cost))
 }
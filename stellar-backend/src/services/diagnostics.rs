@@ -0,0 +1,320 @@
+use serde::Deserialize;
+
+use crate::models::{Diagnostic, DiagnosticSpan, DiagnosticSuggestion};
+use crate::services::compilation::CONTRACTS_DIR;
+
+// Subset of cargo's `--message-format=json` output we care about
+#[derive(Debug, Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<RustcDiagnostic>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RustcDiagnostic {
+    message: String,
+    code: Option<RustcCode>,
+    level: String,
+    spans: Vec<RustcSpan>,
+    children: Vec<RustcDiagnostic>,
+    rendered: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RustcCode {
+    code: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    line_start: u32,
+    line_end: u32,
+    column_start: u32,
+    column_end: u32,
+    is_primary: bool,
+    label: Option<String>,
+    suggested_replacement: Option<String>,
+    suggestion_applicability: Option<String>,
+    expansion: Option<Box<RustcExpansion>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RustcExpansion {
+    span: RustcSpan,
+}
+
+/// A line of cargo stdout when building with JSON messages
+pub enum CargoLine {
+    /// A compiler diagnostic, with the human readable text rustc would have printed
+    Diagnostic(Box<Diagnostic>),
+    /// Any other cargo JSON message (artifacts, build scripts, build-finished)
+    Message,
+    /// Plain text that is not a cargo message
+    Text,
+}
+
+/// Parse one line of cargo's JSON output.
+///
+/// Spans inside a contract crate (`contracts/<name>/...`) are reported
+/// relative to that crate so they line up with the files the user edits.
+pub fn parse_cargo_line(line: &str) -> CargoLine {
+    let message: CargoMessage = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(_) => return CargoLine::Text,
+    };
+
+    match (message.reason.as_str(), message.message) {
+        ("compiler-message", Some(diagnostic)) => {
+            CargoLine::Diagnostic(Box::new(convert_diagnostic(diagnostic)))
+        }
+        _ => CargoLine::Message,
+    }
+}
+
+fn convert_diagnostic(diagnostic: RustcDiagnostic) -> Diagnostic {
+    let spans: Vec<DiagnosticSpan> = diagnostic.spans.iter().map(convert_span).collect();
+
+    let primary = spans.iter().find(|span| span.is_primary).or(spans.first());
+
+    let mut suggestions = Vec::new();
+    let mut notes = Vec::new();
+    collect_children(&diagnostic.children, &mut suggestions, &mut notes);
+    // Some lints attach the replacement to the diagnostic's own spans
+    collect_suggestions(&diagnostic.message, &diagnostic.spans, &mut suggestions);
+
+    Diagnostic {
+        level: diagnostic.level,
+        code: diagnostic.code.map(|code| code.code),
+        message: diagnostic.message,
+        contract: primary.and_then(|span| span.contract.clone()),
+        file: primary.map(|span| span.file.clone()),
+        spans,
+        suggestions,
+        notes,
+        rendered: diagnostic.rendered,
+    }
+}
+
+fn collect_children(
+    children: &[RustcDiagnostic],
+    suggestions: &mut Vec<DiagnosticSuggestion>,
+    notes: &mut Vec<String>,
+) {
+    for child in children {
+        let before = suggestions.len();
+        collect_suggestions(&child.message, &child.spans, suggestions);
+
+        if suggestions.len() == before {
+            notes.push(format!("{}: {}", child.level, child.message));
+        }
+
+        collect_children(&child.children, suggestions, notes);
+    }
+}
+
+fn collect_suggestions(
+    message: &str,
+    spans: &[RustcSpan],
+    suggestions: &mut Vec<DiagnosticSuggestion>,
+) {
+    for span in spans {
+        if let Some(replacement) = &span.suggested_replacement {
+            suggestions.push(DiagnosticSuggestion {
+                message: message.to_string(),
+                span: convert_span(span),
+                replacement: replacement.clone(),
+                applicability: span.suggestion_applicability.clone(),
+            });
+        }
+    }
+}
+
+fn convert_span(span: &RustcSpan) -> DiagnosticSpan {
+    // Errors inside macros (e.g. #[contractimpl]) point into the macro's
+    // source; walk the expansion chain back to the call site in user code
+    let mut user_span = span;
+    while split_contract_path(&user_span.file_name).is_none() {
+        match &user_span.expansion {
+            Some(expansion) => user_span = &expansion.span,
+            None => {
+                user_span = span;
+                break;
+            }
+        }
+    }
+
+    let (contract, file) = match split_contract_path(&user_span.file_name) {
+        Some((contract, file)) => (Some(contract.to_string()), file.to_string()),
+        None => (None, user_span.file_name.clone()),
+    };
+
+    DiagnosticSpan {
+        contract,
+        file,
+        line_start: user_span.line_start,
+        line_end: user_span.line_end,
+        column_start: user_span.column_start,
+        column_end: user_span.column_end,
+        is_primary: span.is_primary,
+        label: span.label.clone().or_else(|| user_span.label.clone()),
+    }
+}
+
+/// Split a workspace-relative path like `contracts/token/src/lib.rs` into
+/// the contract directory and the crate-relative path
+fn split_contract_path(file_name: &str) -> Option<(&str, &str)> {
+    let rest = file_name.strip_prefix(CONTRACTS_DIR)?.strip_prefix('/')?;
+    rest.split_once('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Recorded `cargo build --message-format=json` output for a contract
+    // with a type error, an unused variable and a bad panic_with_error! call
+    const RECORDED: &str = include_str!("testdata/cargo_messages.jsonl");
+
+    fn recorded(index: usize) -> &'static str {
+        RECORDED.lines().nth(index).expect("recorded line")
+    }
+
+    fn diagnostic(index: usize) -> Diagnostic {
+        match parse_cargo_line(recorded(index)) {
+            CargoLine::Diagnostic(diagnostic) => *diagnostic,
+            _ => panic!("line {} is not a diagnostic", index),
+        }
+    }
+
+    #[test]
+    fn spans_are_relative_to_the_contract() {
+        let diagnostic = diagnostic(0);
+        assert_eq!(diagnostic.level, "error");
+        assert_eq!(diagnostic.code.as_deref(), Some("E0308"));
+        assert_eq!(diagnostic.message, "mismatched types");
+        assert_eq!(diagnostic.contract.as_deref(), Some("hello-world"));
+        assert_eq!(diagnostic.file.as_deref(), Some("src/lib.rs"));
+        assert!(diagnostic.rendered.unwrap().starts_with("error[E0308]"));
+
+        let spans: Vec<_> = diagnostic
+            .spans
+            .iter()
+            .map(|span| {
+                (
+                    span.contract.as_deref(),
+                    span.file.as_str(),
+                    span.line_start,
+                    span.column_start,
+                    span.column_end,
+                    span.is_primary,
+                    span.label.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (
+                    Some("hello-world"),
+                    "src/lib.rs",
+                    12,
+                    26,
+                    32,
+                    true,
+                    Some("expected `u32`, found `&str`")
+                ),
+                (
+                    Some("hello-world"),
+                    "src/lib.rs",
+                    12,
+                    20,
+                    23,
+                    false,
+                    Some("expected due to this")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn child_replacements_become_suggestions() {
+        let diagnostic = diagnostic(1);
+        assert_eq!(diagnostic.level, "warning");
+        assert_eq!(diagnostic.code.as_deref(), Some("unused_variables"));
+
+        assert_eq!(diagnostic.suggestions.len(), 1);
+        let suggestion = &diagnostic.suggestions[0];
+        assert_eq!(
+            suggestion.message,
+            "if this is intentional, prefix it with an underscore"
+        );
+        assert_eq!(suggestion.replacement, "_env");
+        assert_eq!(
+            suggestion.applicability.as_deref(),
+            Some("MachineApplicable")
+        );
+        assert_eq!(suggestion.span.file, "src/lib.rs");
+        assert_eq!(
+            (suggestion.span.line_start, suggestion.span.column_start),
+            (16, 18)
+        );
+
+        // Children without a replacement are kept as notes
+        assert_eq!(
+            diagnostic.notes,
+            vec!["note: `#[warn(unused_variables)]` (part of `#[warn(unused)]`) on by default"]
+        );
+    }
+
+    #[test]
+    fn macro_spans_walk_back_to_the_call_site() {
+        let diagnostic = diagnostic(2);
+        assert_eq!(diagnostic.code.as_deref(), Some("E0277"));
+        assert_eq!(diagnostic.file.as_deref(), Some("src/lib.rs"));
+
+        // The second span is in soroban-sdk's panic_with_error! definition,
+        // expanded from the contract's call of it
+        let span = &diagnostic.spans[1];
+        assert_eq!(span.contract.as_deref(), Some("hello-world"));
+        assert_eq!(span.file, "src/lib.rs");
+        assert_eq!((span.line_start, span.line_end), (10, 10));
+        assert_eq!((span.column_start, span.column_end), (9, 38));
+        assert!(!span.is_primary);
+        assert_eq!(
+            span.label.as_deref(),
+            Some("required by a bound introduced by this call")
+        );
+
+        assert_eq!(diagnostic.notes.len(), 3);
+        assert!(diagnostic.notes[0].starts_with("help: the following other types implement"));
+        assert_eq!(
+            diagnostic.notes[2],
+            "note: required by a bound in `Env::panic_with_error`"
+        );
+    }
+
+    #[test]
+    fn other_lines_are_not_diagnostics() {
+        assert!(matches!(parse_cargo_line(recorded(3)), CargoLine::Message));
+        assert!(matches!(parse_cargo_line(recorded(4)), CargoLine::Message));
+        assert!(matches!(
+            parse_cargo_line("   Compiling hello-world v0.0.0"),
+            CargoLine::Text
+        ));
+    }
+
+    #[test]
+    fn spans_outside_contracts_keep_their_path() {
+        assert_eq!(
+            split_contract_path("contracts/token/src/lib.rs"),
+            Some(("token", "src/lib.rs"))
+        );
+        assert_eq!(split_contract_path("src/lib.rs"), None);
+        assert_eq!(split_contract_path("contracts/token"), None);
+        assert_eq!(
+            split_contract_path("/root/.cargo/registry/src/lib.rs"),
+            None
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use serde_json::Value;
+use stellar_xdr::curr::{ContractEventBody, ContractEventType, DiagnosticEvent, ScAddress};
+
+use crate::models::ContractEventEntry;
+use crate::services::scval::scval_to_json;
+
+/// Decode events recorded while simulating or applying a transaction
+pub fn decode(events: &[DiagnosticEvent]) -> Vec<ContractEventEntry> {
+    events
+        .iter()
+        .map(|event| {
+            let ContractEventBody::V0(body) = &event.event.body;
+            ContractEventEntry {
+                event_type: match event.event.type_ {
+                    ContractEventType::Contract => "contract",
+                    ContractEventType::System => "system",
+                    ContractEventType::Diagnostic => "diagnostic",
+                }
+                .to_string(),
+                contract_id: event
+                    .event
+                    .contract_id
+                    .as_ref()
+                    .map(|id| ScAddress::Contract(id.clone()).to_string()),
+                topics: body.topics.iter().map(scval_to_json).collect(),
+                data: scval_to_json(&body.data),
+                in_successful_call: event.in_successful_contract_call,
+            }
+        })
+        .collect()
+}
+
+/// One readable line per event. `log!` output is shown as its message
+/// followed by its arguments.
+pub fn logs(events: &[ContractEventEntry]) -> Vec<String> {
+    events.iter().map(format_event).collect()
+}
+
+fn format_event(event: &ContractEventEntry) -> String {
+    let source = event.contract_id.as_deref().unwrap_or("host");
+    match (event.event_type.as_str(), event.topics.first()) {
+        ("diagnostic", Some(Value::String(topic))) if topic == "log" => {
+            let parts: Vec<String> = match &event.data {
+                Value::Array(values) => values.iter().map(text).collect(),
+                value => vec![text(value)],
+            };
+            format!("log {}: {}", source, parts.join(" "))
+        }
+        ("diagnostic", _) => format!("{} {}", Value::Array(event.topics.clone()), event.data),
+        (event_type, _) => format!(
+            "{} {}: {} {}",
+            event_type,
+            source,
+            Value::Array(event.topics.clone()),
+            event.data
+        ),
+    }
+}
+
+/// Strings unquoted, everything else as JSON
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
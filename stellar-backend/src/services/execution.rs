@@ -0,0 +1,289 @@
+use anyhow::{Context, Result};
+use log::{error, info};
+use rand::RngCore;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use soroban_env_host::budget::Budget;
+use soroban_env_host::storage::{EntryWithLiveUntil, SnapshotSource, Storage};
+use soroban_env_host::{meta, DiagnosticLevel, Host, HostError, LedgerInfo};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use stellar_xdr::curr::{
+    AccountId, ContractDataDurability, DiagnosticEvent, LedgerEntryData, LedgerKey,
+    PublicKey, ScAddress, ScError, ScVal, Uint256,
+};
+
+use crate::models::{BudgetUsage, ContractFailure, ExecuteResponse, StorageEntry};
+use crate::services::compilation::WasmVariant;
+use crate::services::scval::{scval_to_json, ContractInterface};
+use crate::services::{
+    contract_errors, contract_spec, deployment, events, invocation, transaction,
+};
+use crate::storage::ProjectRef;
+
+// The local environment identifies as a standalone network
+const LOCAL_PASSPHRASE: &str = "Standalone Network ; February 2017";
+const LEDGER_SEQUENCE: u32 = 1000;
+
+/// Run a method of a project's compiled contract in an in-process host,
+/// without any network. Each execution starts from an empty ledger: the
+/// contract is deployed into it, constructed, and then called. The build
+/// with logs is used, so `log!` output is part of the result.
+pub async fn execute(
+    project: &ProjectRef,
+    contract: Option<&str>,
+    method_name: &str,
+    args: &Value,
+    constructor_args: Option<&Value>,
+) -> Result<ExecuteResponse> {
+    info!(
+        "Executing method {} of project {} locally",
+        method_name, project
+    );
+
+    let wasm = deployment::load_wasm(project, contract, WasmVariant::WithLogs).await?;
+    let interface = Arc::new(ContractInterface::new(contract_spec::spec_entries(&wasm)?));
+
+    // The host is neither Send nor cheap, so it runs on a blocking thread
+    let method_name = method_name.to_string();
+    let args = args.clone();
+    let constructor_args = constructor_args.cloned();
+    tokio::task::spawn_blocking(move || {
+        run(
+            wasm,
+            interface,
+            &method_name,
+            &args,
+            constructor_args.as_ref(),
+        )
+    })
+    .await
+    .context("Local execution panicked")?
+}
+
+fn run(
+    wasm: Vec<u8>,
+    interface: Arc<ContractInterface>,
+    method_name: &str,
+    args: &Value,
+    constructor_args: Option<&Value>,
+) -> Result<ExecuteResponse> {
+    let deployer = AccountId(PublicKey::PublicKeyTypeEd25519(Uint256([0; 32])));
+    let host = local_host(&deployer)?;
+
+    // Deploy the contract as the network would
+    let wasm_hash: [u8; 32] = Sha256::digest(&wasm).into();
+    host.invoke_function(deployment::upload_function(wasm)?)
+        .map_err(|e| anyhow::anyhow!("Failed to upload the contract wasm: {:?}", e.error))?;
+
+    let constructor_args = match deployment::encode_constructor_args(&interface, constructor_args) {
+        Ok(args) => args,
+        Err(error_msg) => return finish(host, None, 0, Err(error_msg)),
+    };
+    let create = deployment::create_function(&deployer, wasm_hash, [0; 32], constructor_args)?;
+    let contract = match host.invoke_function(create) {
+        Ok(ScVal::Address(address @ ScAddress::Contract(_))) => address,
+        Ok(other) => {
+            return Err(anyhow::anyhow!(
+                "Contract creation returned {:?} instead of a contract address",
+                other
+            ))
+        }
+        Err(e) => {
+            let failure = failure(&e, &interface);
+            let message = format!(
+                "Contract creation failed: {}",
+                failure
+                    .as_ref()
+                    .map(|failure| failure.explanation.clone())
+                    .unwrap_or_else(|| format!("{:?}", e.error))
+            );
+            return finish(host, None, 0, Err(message));
+        }
+    };
+
+    // Report the call alone, not the deployment
+    host.budget_cloned()
+        .reset_default()
+        .map_err(|e| anyhow::anyhow!("Failed to reset the budget: {:?}", e.error))?;
+    let setup_events = host
+        .get_events()
+        .map_err(|e| anyhow::anyhow!("Failed to read events: {:?}", e.error))?
+        .0
+        .len();
+
+    let call = match invocation::build_call(interface.clone(), contract.clone(), method_name, args)?
+    {
+        Ok(call) => call,
+        Err(error_msg) => return finish(host, Some(&contract), setup_events, Err(error_msg)),
+    };
+
+    match host.invoke_function(call.host_function.clone()) {
+        Ok(value) => {
+            info!("Local execution of {} succeeded", method_name);
+            let result = call.decode_result(&value);
+            let mut response = finish(host, Some(&contract), setup_events, Ok(()))?;
+            response.result = Some(result);
+            Ok(response)
+        }
+        Err(e) => {
+            error!("Local execution of {} failed: {:?}", method_name, e.error);
+            let failure = failure(&e, &interface);
+            let error_msg = failure
+                .as_ref()
+                .map(|failure| failure.explanation.clone())
+                .unwrap_or_else(|| format!("{:?}", e.error));
+            let mut response = finish(host, Some(&contract), setup_events, Err(error_msg))?;
+            response.failure = failure;
+            Ok(response)
+        }
+    }
+}
+
+/// A host over an empty ledger, recording the footprint and authorizations
+/// instead of enforcing them, so every `require_auth` passes
+fn local_host(source: &AccountId) -> Result<Host> {
+    let host = Host::with_storage_and_budget(
+        Storage::with_recording_footprint(Rc::new(EmptyLedger)),
+        Budget::default(),
+    );
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut seed = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut seed);
+
+    (|| -> std::result::Result<(), HostError> {
+        host.set_ledger_info(LedgerInfo {
+            protocol_version: meta::INTERFACE_VERSION.protocol,
+            sequence_number: LEDGER_SEQUENCE,
+            timestamp,
+            network_id: transaction::network_id(LOCAL_PASSPHRASE),
+            base_reserve: 5_000_000,
+            min_temp_entry_ttl: 17_280,
+            min_persistent_entry_ttl: 2_073_600,
+            max_entry_ttl: 3_110_400,
+        })?;
+        host.set_source_account(source.clone())?;
+        host.set_base_prng_seed(seed)?;
+        host.switch_to_recording_auth(false)?;
+        host.set_diagnostic_level(DiagnosticLevel::Debug)
+    })()
+    .map_err(|e| anyhow::anyhow!("Failed to set up the local host: {:?}", e.error))?;
+
+    Ok(host)
+}
+
+struct EmptyLedger;
+
+impl SnapshotSource for EmptyLedger {
+    fn get(
+        &self,
+        _key: &Rc<LedgerKey>,
+    ) -> std::result::Result<Option<EntryWithLiveUntil>, HostError> {
+        Ok(None)
+    }
+}
+
+fn failure(error: &HostError, interface: &ContractInterface) -> Option<ContractFailure> {
+    ScError::try_from(error.error)
+        .ok()
+        .map(|error| contract_errors::describe(&error, Some(interface)))
+}
+
+/// Collect what the host recorded into a response, leaving out the first
+/// `skip_events` events
+fn finish(
+    host: Host,
+    contract: Option<&ScAddress>,
+    skip_events: usize,
+    outcome: std::result::Result<(), String>,
+) -> Result<ExecuteResponse> {
+    let budget = host.budget_cloned();
+    let budget_usage = (|| -> std::result::Result<BudgetUsage, HostError> {
+        let cpu_instructions = budget.get_cpu_insns_consumed()?;
+        let memory_bytes = budget.get_mem_bytes_consumed()?;
+        Ok(BudgetUsage {
+            cpu_instructions,
+            cpu_limit: cpu_instructions + budget.get_cpu_insns_remaining()?,
+            memory_bytes,
+            memory_limit: memory_bytes + budget.get_mem_bytes_remaining()?,
+        })
+    })()
+    .map_err(|e| anyhow::anyhow!("Failed to read the budget: {:?}", e.error))?;
+
+    let (storage, recorded) = host
+        .try_finish()
+        .map_err(|e| anyhow::anyhow!("Failed to finish local execution: {:?}", e.error))?;
+
+    let recorded: Vec<DiagnosticEvent> = recorded
+        .0
+        .into_iter()
+        .skip(skip_events)
+        .map(|event| DiagnosticEvent {
+            in_successful_contract_call: !event.failed_call,
+            event: event.event,
+        })
+        .collect();
+    let recorded = events::decode(&recorded);
+
+    Ok(ExecuteResponse {
+        success: outcome.is_ok(),
+        contract_id: contract.map(ToString::to_string),
+        result: None,
+        error: outcome.err(),
+        failure: None,
+        logs: events::logs(&recorded),
+        events: recorded,
+        budget: budget_usage,
+        storage: contract_data(&storage)?,
+    })
+}
+
+/// Every contract data entry left in storage, with instance storage
+/// flattened into its entries
+fn contract_data(storage: &Storage) -> Result<Vec<StorageEntry>> {
+    let entries = storage
+        .map
+        .iter(&Budget::default())
+        .map_err(|e| anyhow::anyhow!("Failed to read storage: {:?}", e.error))?;
+
+    let mut data = Vec::new();
+    for (_, entry) in entries {
+        let Some((entry, live_until)) = entry else {
+            continue;
+        };
+        let LedgerEntryData::ContractData(entry) = &entry.data else {
+            continue;
+        };
+        let contract_id = entry.contract.to_string();
+
+        match (&entry.key, &entry.val) {
+            (ScVal::LedgerKeyContractInstance, ScVal::ContractInstance(instance)) => {
+                for item in instance.storage.iter().flat_map(|map| map.iter()) {
+                    data.push(StorageEntry {
+                        contract_id: contract_id.clone(),
+                        durability: "instance".to_string(),
+                        key: scval_to_json(&item.key),
+                        value: scval_to_json(&item.val),
+                        live_until_ledger: *live_until,
+                    });
+                }
+            }
+            (key, value) => data.push(StorageEntry {
+                contract_id,
+                durability: match entry.durability {
+                    ContractDataDurability::Persistent => "persistent",
+                    ContractDataDurability::Temporary => "temporary",
+                }
+                .to_string(),
+                key: scval_to_json(key),
+                value: scval_to_json(value),
+                live_until_ledger: *live_until,
+            }),
+        }
+    }
+    Ok(data)
+}
@@ -1,196 +1,344 @@
 use anyhow::{Context, Result};
-use log::{info, error, warn};
-use std::process::Command;
+use log::{info, error};
 use std::env;
-use crate::models::InvokeResponse;
+use std::sync::Arc;
+use stellar_xdr::curr::{
+    DiagnosticEvent, HostFunction, InvokeContractArgs, Limits, ScAddress, ScSpecTypeDef, ScSymbol,
+    ScVal, WriteXdr,
+};
+use crate::models::{
+    ArgError, ContractEventEntry, ContractFailure, InvokeResponse, ParameterInfo, SignedAuthEntry,
+    ValidateArgsResponse,
+};
+use crate::services::networks::NetworkProfile;
+use crate::services::soroban_rpc::RpcClient;
+use crate::services::spec_cache::SpecCache;
+use crate::services::transaction::{self, Signer};
+use crate::services::{authorization, contract_errors, contract_spec, costs, events};
+use crate::services::scval::{self, ContractInterface};
+
+/// Where a call runs: a network profile, and the cached interfaces of the
+/// contracts deployed on it
+#[derive(Clone, Copy)]
+pub struct CallEnv<'a> {
+    pub network: &'a NetworkProfile,
+    pub spec_cache: &'a SpecCache,
+}
 
 pub async fn invoke_contract(
     contract_id: &str,
     method_name: &str,
     args: &serde_json::Value,
     method_type: &str,
-    source_account: Option<&str>,
+    env: CallEnv<'_>,
+    auth: &[SignedAuthEntry],
+    test_signers: &[Signer],
 ) -> Result<InvokeResponse> {
+    let network = env.network;
     info!(
-        "Invoking contract {} method {} with type {}",
-        contract_id, method_name, method_type
+        "Invoking contract {} method {} with type {} on {}",
+        contract_id, method_name, method_type, network.name
     );
 
-    // Load Stellar RPC configuration from environment
-    let rpc_url = env::var("STELLAR_RPC_URL")
-        .context("STELLAR_RPC_URL not found in environment")?;
-    let network_passphrase = env::var("STELLAR_NETWORK_PASSPHRASE")
-        .context("STELLAR_NETWORK_PASSPHRASE not found in environment")?;
-
-    // Get source account (use env var if not provided)
-    let source_secret = source_account
-        .map(|s| s.to_string())
-        .or_else(|| env::var("STELLAR_SECRET_KEY").ok())
-        .context("No source account provided and STELLAR_SECRET_KEY not found")?;
-
-    // Determine send mode based on method type
-    // view methods: --send=no (simulation only, no transaction)
-    // call methods: --send=yes (create actual transaction)
-    let is_view = method_type == "view";
-    let send_mode = if is_view { "no" } else { "yes" };
-
-    info!("Method type: {}, send mode: {}", method_type, send_mode);
-
-    // Build the invocation command with explicit RPC URL and network passphrase
-    let mut cmd = Command::new("stellar");
-    cmd.arg("contract")
-        .arg("invoke")
-        .arg("--id")
-        .arg(contract_id)
-        .arg("--source")
-        .arg(&source_secret)
-        .arg("--rpc-url")
-        .arg(&rpc_url)
-        .arg("--network-passphrase")
-        .arg(&network_passphrase)
-        .arg("--send")
-        .arg(send_mode);
-
-    // Add --verbose for call methods to get transaction hash
-    if !is_view {
-        cmd.arg("--verbose");
+    // Calls are signed with the server account
+    let source_secret = env::var("STELLAR_SECRET_KEY")
+        .context("STELLAR_SECRET_KEY not found in environment")?;
+    let signer = Signer::from_secret(&source_secret)?;
+
+    let rpc = RpcClient::new(&network.rpc_url)?;
+
+    let call = match invoke_function(env, contract_id, method_name, args).await? {
+        Ok(call) => call,
+        Err(error_msg) => return Ok(failed(error_msg, Vec::new(), None)),
+    };
+
+    let authorized = authorization::simulate(
+        &rpc,
+        network,
+        &signer.account_id(),
+        call.host_function.clone(),
+        auth,
+        test_signers,
+    )
+    .await?;
+    let missing = authorized.missing();
+    let authorization::Authorized { tx, simulation, auth } = authorized;
+
+    let simulated_events = events::decode(&simulation.events);
+    if let Some(error_msg) = simulation.error.clone() {
+        error!("Contract invocation failed in simulation: {}", error_msg);
+        let failure = call.failure(Some(&error_msg), &simulation.events);
+        return Ok(failed(error_msg, simulated_events, failure));
+    }
+    if let Some(preamble) = &simulation.restore_preamble {
+        return Ok(failed(
+            format!(
+                "Contract state needed by this call is archived and must be restored first \
+                 (restore fee {} stroops)",
+                preamble.min_resource_fee
+            ),
+            simulated_events,
+            None,
+        ));
     }
 
-    cmd.arg("--")
-        .arg(method_name);
-
-    // Add arguments
-    // Parse JSON args and convert to CLI arguments
-    if let Some(args_obj) = args.as_object() {
-        for (key, value) in args_obj {
-            cmd.arg(format!("--{}", key));
-
-            // Convert JSON value to string argument
-            let arg_str = match value {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Array(_) => serde_json::to_string(value)?,
-                serde_json::Value::Object(_) => serde_json::to_string(value)?,
-                serde_json::Value::Null => "null".to_string(),
-            };
-
-            cmd.arg(arg_str);
-        }
-    } else if let Some(args_array) = args.as_array() {
-        // If args is an array, pass them as positional arguments
-        for value in args_array {
-            let arg_str = match value {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                _ => serde_json::to_string(value)?,
-            };
-            cmd.arg(arg_str);
-        }
+    let tx = transaction::assemble(tx, &simulation)?;
+
+    // View methods are answered by the simulation alone
+    if method_type == "view" {
+        info!("Contract invocation successful (simulated)");
+        return Ok(InvokeResponse {
+            success: true,
+            result: simulation.return_value.as_ref().map(|value| call.decode_result(value)),
+            transaction_hash: None,
+            logs: events::logs(&simulated_events),
+            fee: None,
+            error: None,
+            failure: None,
+            raw_output: simulation.return_value.as_ref().and_then(encode),
+            events: simulated_events,
+            cost: costs::transaction_cost(
+                &tx,
+                simulation.cost.as_ref(),
+                &simulation.events,
+                simulation.return_value.as_ref(),
+                None,
+            ),
+            auth,
+        });
     }
 
-    // Log the full command for debugging
-    let cmd_str = format!("{:?}", cmd);
-    info!("Executing stellar CLI command: {}", cmd_str);
-
-    // Execute the command
-    let output = cmd
-        .output()
-        .context("Failed to execute stellar contract invoke")?;
-
-    // Log full output for debugging
-    let stdout_str = String::from_utf8_lossy(&output.stdout);
-    let stderr_str = String::from_utf8_lossy(&output.stderr);
-    info!("Invoke stdout: {}", stdout_str);
-    info!("Invoke stderr: {}", stderr_str);
-
-    // Helper function to extract transaction hash from Stellar CLI verbose output
-    fn extract_transaction_hash(stderr: &str) -> Option<String> {
-        // Stellar CLI outputs transaction hash in various formats:
-        // - "transaction hash: <hash>"
-        // - "Transaction hash is <hash>"
-        // - "Signing transaction: <hash>"
-        // - May include emoji characters before/after
-        for line in stderr.lines() {
-            let line_lower = line.to_lowercase();
-
-            // Check for "transaction hash" pattern
-            if line_lower.contains("transaction hash") {
-                // Find the position after "transaction hash" phrase
-                if let Some(pos) = line_lower.find("transaction hash") {
-                    let after_phrase = &line[pos + 16..]; // Skip "transaction hash"
-                    // Extract the hash - it's a 64-character hex string
-                    for word in after_phrase.split_whitespace() {
-                        let cleaned = word.trim_matches(|c: char| !c.is_ascii_hexdigit());
-                        if cleaned.len() == 64 && cleaned.chars().all(|c| c.is_ascii_hexdigit()) {
-                            return Some(cleaned.to_string());
-                        }
-                    }
-                }
-            }
-
-            // Check for "signing transaction:" pattern (common in invoke output)
-            if line_lower.contains("signing transaction:") {
-                // Find the position after "signing transaction:" phrase
-                if let Some(pos) = line_lower.find("signing transaction:") {
-                    let after_phrase = &line[pos + 20..]; // Skip "signing transaction:"
-                    // Extract the hash - it's a 64-character hex string
-                    for word in after_phrase.split_whitespace() {
-                        let cleaned = word.trim_matches(|c: char| !c.is_ascii_hexdigit());
-                        if cleaned.len() == 64 && cleaned.chars().all(|c| c.is_ascii_hexdigit()) {
-                            return Some(cleaned.to_string());
-                        }
-                    }
-                }
-            }
+    if !missing.is_empty() {
+        let mut response = failed(
+            format!(
+                "The call needs authorization from {}: sign the entries in `auth` and send them back",
+                missing.join(", ")
+            ),
+            simulated_events,
+            None,
+        );
+        response.auth = auth;
+        return Ok(response);
+    }
+
+    let envelope = transaction::sign(tx.clone(), &network.passphrase, &signer)?;
+    let submission = transaction::submit(&rpc, &envelope).await?;
+
+    if let Some(error_msg) = submission.failure() {
+        error!("Contract invocation failed: {}", error_msg);
+        let submitted_events = submission.events();
+        let failure = call.failure(None, &submitted_events);
+        let mut response = failed(error_msg, events::decode(&submitted_events), failure);
+        response.transaction_hash = Some(submission.hash.clone());
+        response.auth = auth;
+        response.cost = costs::transaction_cost(
+            &tx,
+            simulation.cost.as_ref(),
+            &submitted_events,
+            None,
+            submission.info.as_ref(),
+        );
+        return Ok(response);
+    }
+
+    let info = submission.info.as_ref().context("Transaction was not applied")?;
+    info!("Contract invocation successful, transaction hash: {}", submission.hash);
+
+    let recorded = info.events();
+    let return_value = info.return_value();
+    let cost = costs::transaction_cost(
+        &tx,
+        simulation.cost.as_ref(),
+        &recorded,
+        return_value.as_ref(),
+        Some(info),
+    );
+    let submitted_events = events::decode(&recorded);
+
+    Ok(InvokeResponse {
+        success: true,
+        result: return_value.as_ref().map(|value| call.decode_result(value)),
+        transaction_hash: Some(submission.hash.clone()),
+        logs: events::logs(&submitted_events),
+        fee: info.fee_charged().map(|fee| fee.to_string()),
+        error: None,
+        failure: None,
+        raw_output: return_value.as_ref().and_then(encode),
+        events: submitted_events,
+        cost,
+        auth,
+    })
+}
+
+/// A contract call built against the contract's interface
+pub struct ContractCall {
+    pub host_function: HostFunction,
+    interface: Arc<ContractInterface>,
+    output: Option<ScSpecTypeDef>,
+}
+
+impl ContractCall {
+    /// Decode the call's return value as the method's declared output type
+    pub fn decode_result(&self, value: &ScVal) -> serde_json::Value {
+        match &self.output {
+            Some(output) => self.interface.decode(value, output),
+            None => scval::scval_to_json(value),
         }
-        None
     }
 
-    // Parse the result
-    if output.status.success() {
-        let result_str = String::from_utf8_lossy(&output.stdout);
-        let result: serde_json::Value = serde_json::from_str(result_str.trim())
-            .unwrap_or_else(|_| serde_json::Value::String(result_str.trim().to_string()));
+    /// Why the call failed, with contract errors named from its interface
+    pub fn failure(
+        &self,
+        message: Option<&str>,
+        events: &[DiagnosticEvent],
+    ) -> Option<ContractFailure> {
+        contract_errors::contract_failure(message, events, Some(&self.interface))
+    }
+}
 
-        info!("Contract invocation successful");
+/// The interface of a deployed contract, read from its wasm's spec
+pub async fn contract_interface(rpc: &RpcClient, contract: &ScAddress) -> Result<ContractInterface> {
+    let wasm = rpc.contract_wasm(contract).await?;
+    Ok(ContractInterface::new(contract_spec::spec_entries(&wasm)?))
+}
 
-        // Extract transaction hash for call methods (view methods don't have one)
-        let transaction_hash = if !is_view {
-            extract_transaction_hash(&stderr_str).or_else(|| {
-                warn!("Could not extract transaction hash from call method output");
-                None
-            })
-        } else {
-            None
-        };
+/// Build the call of `method_name`, with `args` encoded against the deployed
+/// contract's own interface, cached like for argument validation. The inner
+/// error describes what is wrong with the request itself.
+pub async fn invoke_function(
+    env: CallEnv<'_>,
+    contract_id: &str,
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<std::result::Result<ContractCall, String>> {
+    let contract = match contract_id.parse::<ScAddress>() {
+        Ok(contract @ ScAddress::Contract(_)) => contract,
+        _ => return Ok(Err(format!("Invalid contract id {}", contract_id))),
+    };
+
+    let interface = env.spec_cache.get(env.network, &contract).await?;
+    build_call(interface, contract, method_name, args)
+}
 
-        if let Some(ref hash) = transaction_hash {
-            info!("Transaction hash: {}", hash);
+/// Build the call of `method_name` on `contract`, with `args` encoded
+/// against the given interface
+pub fn build_call(
+    interface: Arc<ContractInterface>,
+    contract: ScAddress,
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<std::result::Result<ContractCall, String>> {
+    let Some(function) = interface.function(method_name) else {
+        return Ok(Err(format!(
+            "Contract {} has no method {}",
+            contract, method_name
+        )));
+    };
+    let args = match interface.encode_args(function, args) {
+        Ok(args) => args,
+        Err(errors) => {
+            return Ok(Err(format!(
+                "Invalid arguments for {}: {}",
+                method_name,
+                errors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            )))
         }
+    };
+    let output = function.outputs.first().cloned();
 
-        Ok(InvokeResponse {
-            success: true,
-            result: Some(result),
-            transaction_hash,
-            logs: vec![],
-            fee: None, // TODO: Extract real fee from output
-            error: None,
-            raw_output: Some(stdout_str.to_string()),
-        })
-    } else {
-        let error_msg = String::from_utf8_lossy(&output.stderr).to_string();
-        error!("Contract invocation failed: {}", error_msg);
+    let host_function = HostFunction::InvokeContract(InvokeContractArgs {
+        contract_address: contract,
+        function_name: ScSymbol(
+            method_name
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Method name is too long"))?,
+        ),
+        args: args
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Too many arguments"))?,
+    });
 
-        Ok(InvokeResponse {
-            success: false,
-            result: None,
-            transaction_hash: None,
-            logs: vec![],
-            fee: None,
-            error: Some(error_msg.clone()),
-            raw_output: Some(error_msg),
-        })
+    Ok(Ok(ContractCall {
+        host_function,
+        interface,
+        output,
+    }))
+}
+
+/// Check a call's arguments against the contract's interface without
+/// building or simulating a transaction
+pub fn validate_args(
+    interface: &ContractInterface,
+    contract_id: &str,
+    method_name: &str,
+    args: &serde_json::Value,
+) -> ValidateArgsResponse {
+    let Some(function) = interface.function(method_name) else {
+        return ValidateArgsResponse {
+            valid: false,
+            contract_id: contract_id.to_string(),
+            method_name: method_name.to_string(),
+            parameters: Vec::new(),
+            errors: vec![ArgError {
+                path: String::new(),
+                message: format!(
+                    "Contract {} has no method {}; available methods: {}",
+                    contract_id,
+                    method_name,
+                    interface.function_names().join(", ")
+                ),
+            }],
+        };
+    };
+
+    let errors = interface.encode_args(function, args).err().unwrap_or_default();
+    ValidateArgsResponse {
+        valid: errors.is_empty(),
+        contract_id: contract_id.to_string(),
+        method_name: method_name.to_string(),
+        parameters: function
+            .inputs
+            .iter()
+            .map(|input| ParameterInfo {
+                name: input.name.to_utf8_string_lossy(),
+                type_name: scval::type_name(&input.type_),
+            })
+            .collect(),
+        errors,
     }
 }
+
+/// A failed call. When the cause was decoded, `error` is its explanation and
+/// the raw error is kept in `raw_output`.
+fn failed(
+    error: String,
+    recorded: Vec<ContractEventEntry>,
+    failure: Option<ContractFailure>,
+) -> InvokeResponse {
+    InvokeResponse {
+        success: false,
+        result: None,
+        transaction_hash: None,
+        logs: events::logs(&recorded),
+        fee: None,
+        error: Some(
+            failure
+                .as_ref()
+                .map(|failure| failure.explanation.clone())
+                .unwrap_or_else(|| error.clone()),
+        ),
+        failure,
+        raw_output: Some(error),
+        events: recorded,
+        cost: None,
+        auth: Vec::new(),
+    }
+}
+
+pub fn encode(value: &ScVal) -> Option<String> {
+    value.to_xdr_base64(Limits::none()).ok()
+}
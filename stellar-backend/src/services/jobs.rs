@@ -0,0 +1,620 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use futures_util::FutureExt;
+use log::{error, info};
+use std::any::Any;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::models::{ApiError, ApiResponse, BuildEvent, CompileResponse, JobInfo, JobStatus};
+use crate::services::build_limits::{BuildControl, BuildLimits, STATUS_CANCELLED};
+use crate::services::build_stream::BuildStreams;
+use crate::services::compilation::{self, BuildEnv, ContractSources};
+use crate::services::sandbox::Sandbox;
+use crate::services::sdk_versions::SdkVersion;
+use crate::storage::ProjectRef;
+
+// Finished jobs stay queryable for this long
+const JOB_RETENTION_MINUTES: i64 = 60;
+
+struct Job {
+    project: ProjectRef,
+    contract: Option<String>,
+    sources: Option<ContractSources>,
+    sdk: SdkVersion,
+    cancel: CancellationToken,
+    status: JobStatus,
+    created_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    result: Option<CompileResponse>,
+    error: Option<ApiError>,
+}
+
+struct QueuedJob {
+    id: String,
+    project: ProjectRef,
+    contract: Option<String>,
+    sources: ContractSources,
+    sdk: SdkVersion,
+    cancel: CancellationToken,
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: HashMap<String, Job>,
+    pending: VecDeque<String>,
+    // Projects with a running build or test run; a project is never built
+    // twice at once
+    running_projects: HashSet<ProjectRef>,
+}
+
+/// Compile job queue drained by a fixed-size pool of workers. Test runs
+/// share the pool's concurrency limit and per-project exclusion.
+pub struct JobQueue {
+    // Jobs waiting beyond this are refused instead of queued
+    capacity: usize,
+    state: Mutex<QueueState>,
+    notify: Notify,
+    // Wakes test runs waiting for their project or a free slot
+    released: Notify,
+    // One per worker; a build or test run holds one while it runs
+    slots: Arc<Semaphore>,
+    streams: Arc<BuildStreams>,
+    limits: BuildLimits,
+    sandbox: Arc<dyn Sandbox>,
+}
+
+impl JobQueue {
+    /// Create the queue and spawn `concurrency` workers draining it; at
+    /// most `capacity` jobs wait at a time
+    pub fn start(
+        streams: Arc<BuildStreams>,
+        limits: BuildLimits,
+        sandbox: Arc<dyn Sandbox>,
+        concurrency: usize,
+        capacity: usize,
+    ) -> Arc<Self> {
+        let concurrency = concurrency.max(1);
+        let queue = Arc::new(Self::new(streams, limits, sandbox, concurrency, capacity));
+
+        for worker_id in 0..concurrency {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.run_worker(worker_id).await });
+        }
+
+        info!(
+            "Started compile job queue with {} workers and room for {} jobs",
+            concurrency, capacity
+        );
+        queue
+    }
+
+    fn new(
+        streams: Arc<BuildStreams>,
+        limits: BuildLimits,
+        sandbox: Arc<dyn Sandbox>,
+        concurrency: usize,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+            released: Notify::new(),
+            slots: Arc::new(Semaphore::new(concurrency)),
+            streams,
+            limits,
+            sandbox,
+        }
+    }
+
+    /// Queue a compilation and return its initial state. Fails when the
+    /// queue is full.
+    pub fn enqueue(
+        &self,
+        project: ProjectRef,
+        contract: Option<&str>,
+        sources: ContractSources,
+        sdk: SdkVersion,
+    ) -> Result<JobInfo> {
+        let id = Uuid::new_v4().to_string();
+        let mut state = self.state.lock().unwrap();
+        Self::prune(&mut state);
+        if state.pending.len() >= self.capacity {
+            return Err(anyhow::anyhow!(
+                "{} compile jobs are already waiting",
+                state.pending.len()
+            ));
+        }
+
+        state.jobs.insert(
+            id.clone(),
+            Job {
+                project,
+                contract: contract.map(str::to_string),
+                sources: Some(sources),
+                sdk,
+                cancel: CancellationToken::new(),
+                status: JobStatus::Queued,
+                created_at: Utc::now(),
+                started_at: None,
+                finished_at: None,
+                result: None,
+                error: None,
+            },
+        );
+        state.pending.push_back(id.clone());
+
+        let info = Self::job_info(&state, &id).expect("job was just inserted");
+        drop(state);
+
+        self.notify.notify_one();
+        Ok(info)
+    }
+
+    /// Current state of a job, if it is known and belongs to `user_id`
+    pub fn get(&self, job_id: &str, user_id: &str) -> Option<JobInfo> {
+        let state = self.state.lock().unwrap();
+        state
+            .jobs
+            .get(job_id)
+            .filter(|job| job.project.user_id() == user_id)?;
+        Self::job_info(&state, job_id)
+    }
+
+    /// Cancel a queued or running job.
+    ///
+    /// A queued job is dropped immediately; a running build is killed and
+    /// finishes with a `CANCELLED` status. Returns `None` for unknown jobs
+    /// and jobs of other users.
+    pub fn cancel(&self, job_id: &str, user_id: &str) -> Option<JobInfo> {
+        let mut state = self.state.lock().unwrap();
+        let job = state
+            .jobs
+            .get_mut(job_id)
+            .filter(|job| job.project.user_id() == user_id)?;
+
+        let was_queued = match job.status {
+            JobStatus::Queued => {
+                job.status = JobStatus::Cancelled;
+                job.finished_at = Some(Utc::now());
+                job.sources = None;
+                true
+            }
+            JobStatus::Running => false,
+            _ => return Self::job_info(&state, job_id),
+        };
+
+        info!("Cancelling compile job {}", job_id);
+        job.cancel.cancel();
+
+        if was_queued {
+            state.pending.retain(|id| id != job_id);
+            let info = Self::job_info(&state, job_id);
+            drop(state);
+
+            self.streams.publish(
+                job_id,
+                BuildEvent::Error(ApiResponse::<()>::error(
+                    "JOB_CANCELLED",
+                    "Compile job was cancelled",
+                    None,
+                )),
+            );
+            return info;
+        }
+
+        Self::job_info(&state, job_id)
+    }
+
+    /// Run a task for a project once no build or other task of the project
+    /// is running and a worker slot is free. Returns `None` if `cancel`
+    /// fires before the task could start.
+    pub async fn run_exclusive<T>(
+        &self,
+        project: &ProjectRef,
+        cancel: &CancellationToken,
+        task: impl Future<Output = T>,
+    ) -> Option<T> {
+        let claim = loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            if let Some(claim) = self.try_claim(project) {
+                break claim;
+            }
+            tokio::select! {
+                _ = released => {}
+                _ = cancel.cancelled() => return None,
+            }
+        };
+
+        let output = task.await;
+        drop(claim);
+        Some(output)
+    }
+
+    fn try_claim(&self, project: &ProjectRef) -> Option<ProjectClaim<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.running_projects.contains(project) {
+            return None;
+        }
+        let slot = self.slots.clone().try_acquire_owned().ok()?;
+        state.running_projects.insert(project.clone());
+        Some(self.claim(project, slot))
+    }
+
+    fn claim(&self, project: &ProjectRef, slot: OwnedSemaphorePermit) -> ProjectClaim<'_> {
+        ProjectClaim {
+            queue: self,
+            project: project.clone(),
+            slot: Some(slot),
+        }
+    }
+
+    /// Let queued builds and waiting tasks know a project or slot was freed
+    fn wake(&self) {
+        self.notify.notify_one();
+        self.released.notify_waiters();
+    }
+
+    async fn run_worker(&self, worker_id: usize) {
+        loop {
+            let (job, claim) = self.next_job().await;
+            info!(
+                "Worker {} compiling job {} for project {}",
+                worker_id, job.id, job.project
+            );
+
+            let on_output = |stream, line: &str| self.streams.output(&job.id, stream, line);
+            let control = BuildControl::new(self.limits, job.cancel.clone());
+            let build = compilation::compile_contract(
+                &job.sources,
+                &job.project,
+                job.contract.as_deref(),
+                BuildEnv {
+                    sdk: &job.sdk,
+                    sandbox: self.sandbox.as_ref(),
+                    control: &control,
+                },
+                &on_output,
+            );
+            // A panicking build fails its job; the worker keeps going
+            let result = AssertUnwindSafe(build)
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|panic| {
+                    Err(anyhow::anyhow!("Build panicked: {}", panic_message(&panic)))
+                });
+
+            self.finish(&job, result);
+            drop(claim);
+        }
+    }
+
+    async fn next_job(&self) -> (QueuedJob, ProjectClaim<'_>) {
+        loop {
+            if let Some(job) = self.take_next() {
+                return job;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    fn take_next(&self) -> Option<(QueuedJob, ProjectClaim<'_>)> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let index = state.pending.iter().position(|id| {
+            state.jobs.get(id).is_some_and(|job| {
+                !state
+                    .running_projects
+                    .contains(&job.project)
+            })
+        })?;
+        // Test runs may hold every slot
+        let slot = self.slots.clone().try_acquire_owned().ok()?;
+        let id = state.pending.remove(index)?;
+
+        let job = state.jobs.get_mut(&id)?;
+        let queued = QueuedJob {
+            id,
+            project: job.project.clone(),
+            contract: job.contract.clone(),
+            sources: job.sources.take()?,
+            sdk: job.sdk.clone(),
+            cancel: job.cancel.clone(),
+        };
+        job.status = JobStatus::Running;
+        job.started_at = Some(Utc::now());
+        state
+            .running_projects
+            .insert(queued.project.clone());
+
+        // Not dropped before the state lock is released; dropping takes it
+        let claim = self.claim(&queued.project, slot);
+        Some((queued, claim))
+    }
+
+    fn finish(&self, queued: &QueuedJob, result: anyhow::Result<CompileResponse>) {
+        let (status, response, event) = match result {
+            Ok(response) => {
+                info!(
+                    "Compilation completed for job {}: {}",
+                    queued.id,
+                    if response.success { "success" } else { "failed" }
+                );
+                let status = if response.success {
+                    JobStatus::Succeeded
+                } else if response.details.status == STATUS_CANCELLED {
+                    JobStatus::Cancelled
+                } else {
+                    JobStatus::Failed
+                };
+                let event = BuildEvent::Result(Box::new(ApiResponse::success(response.clone())));
+                (status, Ok(response), event)
+            }
+            Err(e) => {
+                error!("Compilation error for job {}: {}", queued.id, e);
+                let response = ApiResponse::<()>::error(
+                    "COMPILATION_ERROR",
+                    "Failed to compile contract",
+                    Some(e.to_string()),
+                );
+                let error = response.error.clone();
+                (JobStatus::Failed, Err(error), BuildEvent::Error(response))
+            }
+        };
+
+        if let Some(job) = self.state.lock().unwrap().jobs.get_mut(&queued.id) {
+            job.status = status;
+            job.finished_at = Some(Utc::now());
+            match response {
+                Ok(response) => job.result = Some(response),
+                Err(error) => job.error = error,
+            }
+        }
+
+        self.streams.publish(&queued.id, event);
+    }
+
+    fn job_info(state: &QueueState, job_id: &str) -> Option<JobInfo> {
+        let job = state.jobs.get(job_id)?;
+        let queue_position = state
+            .pending
+            .iter()
+            .position(|id| id == job_id)
+            .map(|index| index + 1);
+
+        Some(JobInfo {
+            job_id: job_id.to_string(),
+            status: job.status,
+            queue_position,
+            created_at: job.created_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            result: job.result.clone(),
+            error: job.error.clone(),
+        })
+    }
+
+    fn prune(state: &mut QueueState) {
+        let cutoff = Utc::now() - Duration::minutes(JOB_RETENTION_MINUTES);
+        state
+            .jobs
+            .retain(|_, job| job.finished_at.is_none_or(|finished| finished > cutoff));
+    }
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
+/// A project and worker slot held by a build or task, released on drop
+struct ProjectClaim<'a> {
+    queue: &'a JobQueue,
+    project: ProjectRef,
+    slot: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for ProjectClaim<'_> {
+    fn drop(&mut self) {
+        self.queue
+            .state
+            .lock()
+            .unwrap()
+            .running_projects
+            .remove(&self.project);
+        self.slot.take();
+        self.queue.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::sandbox::LocalSandbox;
+    use std::path::PathBuf;
+    use std::time::Duration as StdDuration;
+
+    const USER: &str = "user-1";
+
+    /// A queue without workers, so jobs stay queued until taken by hand
+    fn queue(concurrency: usize, capacity: usize) -> JobQueue {
+        JobQueue::new(
+            Arc::new(BuildStreams::new()),
+            BuildLimits {
+                timeout: StdDuration::from_secs(60),
+                cpu_seconds: 60,
+                memory_bytes: 1 << 30,
+                max_output_bytes: 1 << 20,
+            },
+            Arc::new(LocalSandbox::new()),
+            concurrency,
+            capacity,
+        )
+    }
+
+    fn project(id: &str) -> ProjectRef {
+        ProjectRef::new(USER, id).unwrap()
+    }
+
+    fn enqueue(queue: &JobQueue, project_id: &str) -> Result<JobInfo> {
+        let sources = ContractSources::from_request(Some("// contract"), None, None).unwrap();
+        let sdk = SdkVersion {
+            version: "22.0.0".to_string(),
+            cache_dir: PathBuf::from("sdk_cache/22.0.0"),
+            target_lock: Default::default(),
+        };
+        queue.enqueue(project(project_id), None, sources, sdk)
+    }
+
+    #[test]
+    fn claims_are_limited_to_the_slots() {
+        let queue = queue(2, 10);
+        let first = queue.try_claim(&project("a")).unwrap();
+        let _second = queue.try_claim(&project("b")).unwrap();
+        assert!(queue.try_claim(&project("c")).is_none());
+
+        drop(first);
+        assert!(queue.try_claim(&project("c")).is_some());
+    }
+
+    #[test]
+    fn a_project_runs_one_thing_at_a_time() {
+        let queue = queue(3, 10);
+        let claim = queue.try_claim(&project("a")).unwrap();
+        assert!(queue.try_claim(&project("a")).is_none());
+
+        let for_a = enqueue(&queue, "a").unwrap();
+        let for_b = enqueue(&queue, "b").unwrap();
+        let (taken, _running_b) = queue.take_next().unwrap();
+        assert_eq!(taken.id, for_b.job_id);
+        assert!(queue.take_next().is_none());
+
+        drop(claim);
+        let (taken, _running_a) = queue.take_next().unwrap();
+        assert_eq!(taken.id, for_a.job_id);
+        assert_eq!(
+            queue.get(&for_a.job_id, USER).unwrap().status,
+            JobStatus::Running
+        );
+    }
+
+    #[test]
+    fn queued_builds_wait_for_a_slot() {
+        let queue = queue(1, 10);
+        let claim = queue.try_claim(&project("a")).unwrap();
+        enqueue(&queue, "b").unwrap();
+        assert!(queue.take_next().is_none());
+
+        drop(claim);
+        assert!(queue.take_next().is_some());
+    }
+
+    #[test]
+    fn full_queues_refuse_jobs() {
+        let queue = queue(1, 2);
+        enqueue(&queue, "a").unwrap();
+        enqueue(&queue, "b").unwrap();
+        let err = enqueue(&queue, "c").unwrap_err();
+        assert_eq!(err.to_string(), "2 compile jobs are already waiting");
+
+        // Running jobs don't count against the capacity
+        let _running = queue.take_next().unwrap();
+        enqueue(&queue, "c").unwrap();
+    }
+
+    #[test]
+    fn cancelled_jobs_leave_the_queue() {
+        let queue = queue(1, 10);
+        let first = enqueue(&queue, "a").unwrap();
+        let second = enqueue(&queue, "b").unwrap();
+        assert_eq!(second.queue_position, Some(2));
+
+        assert!(queue.cancel(&first.job_id, "user-2").is_none());
+        let cancelled = queue.cancel(&first.job_id, USER).unwrap();
+        assert_eq!(cancelled.status, JobStatus::Cancelled);
+        assert_eq!(cancelled.queue_position, None);
+        assert_eq!(
+            queue.get(&second.job_id, USER).unwrap().queue_position,
+            Some(1)
+        );
+
+        let (taken, _claim) = queue.take_next().unwrap();
+        assert_eq!(taken.id, second.job_id);
+    }
+
+    #[tokio::test]
+    async fn waiting_tasks_can_be_cancelled() {
+        let queue = queue(1, 10);
+        let _claim = queue.try_claim(&project("a")).unwrap();
+
+        let cancel = CancellationToken::new();
+        let cancel_soon = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(StdDuration::from_millis(50)).await;
+            cancel_soon.cancel();
+        });
+        let ran = queue.run_exclusive(&project("a"), &cancel, async {}).await;
+        assert!(ran.is_none());
+    }
+
+    #[tokio::test]
+    async fn waiting_tasks_run_once_the_project_is_released() {
+        let queue = Arc::new(queue(2, 10));
+        let claim = queue.try_claim(&project("a")).unwrap();
+
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move {
+                queue
+                    .run_exclusive(&project("a"), &CancellationToken::new(), async { 7 })
+                    .await
+            }
+        });
+        tokio::time::sleep(StdDuration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        drop(claim);
+        assert_eq!(waiting.await.unwrap(), Some(7));
+        assert!(queue.try_claim(&project("a")).is_some());
+    }
+
+    #[tokio::test]
+    async fn panicking_tasks_release_their_project() {
+        let queue = Arc::new(queue(1, 10));
+        let panicked = tokio::spawn({
+            let queue = queue.clone();
+            async move {
+                queue
+                    .run_exclusive(&project("a"), &CancellationToken::new(), async {
+                        panic!("task failed")
+                    })
+                    .await
+            }
+        })
+        .await;
+        assert!(panicked.unwrap_err().is_panic());
+        assert!(queue.try_claim(&project("a")).is_some());
+    }
+
+    #[test]
+    fn panic_messages_are_reported() {
+        let panic = std::panic::catch_unwind(|| panic!("out of {}", "cheese")).unwrap_err();
+        assert_eq!(panic_message(&panic), "out of cheese");
+        let panic = std::panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(&panic), "static");
+    }
+}
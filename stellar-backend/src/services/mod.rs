@@ -1,3 +1,23 @@
+pub mod authorization;
+pub mod build_limits;
+pub mod build_stream;
 pub mod compilation;
+pub mod contract_errors;
+pub mod contract_spec;
+pub mod costs;
 pub mod deployment;
+pub mod diagnostics;
+pub mod events;
+pub mod execution;
 pub mod invocation;
+pub mod jobs;
+pub mod networks;
+pub mod sandbox;
+pub mod scval;
+pub mod sdk_versions;
+pub mod soroban_rpc;
+pub mod spec_cache;
+pub mod target_cache;
+pub mod testing;
+pub mod transaction;
+pub mod wallet;
@@ -0,0 +1,388 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const DEFAULT_CONFIG_PATH: &str = "networks.toml";
+
+// Used when there is no config file, matching the old single-network setup
+const FALLBACK_NETWORK: &str = "testnet";
+const FALLBACK_RPC_URL: &str = "https://soroban-testnet.stellar.org";
+const FALLBACK_HORIZON_URL: &str = "https://horizon-testnet.stellar.org";
+const FALLBACK_PASSPHRASE: &str = "Test SDF Network ; September 2015";
+const FALLBACK_EXPLORER_URL: &str = "https://testnet.stellarchain.io/contracts/{contract_id}";
+
+const MAINNET_PASSPHRASE: &str = "Public Global Stellar Network ; September 2015";
+
+/// A named Stellar network that deploy and invoke requests can target
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkProfile {
+    pub name: String,
+    pub rpc_url: String,
+    pub horizon_url: Option<String>,
+    pub passphrase: String,
+    pub friendbot_url: Option<String>,
+    pub explorer_url: Option<String>, // Template, `{contract_id}` is substituted
+    pub allow_deploy: bool,
+    pub allow_server_signing: bool, // Whether /invoke may send calls signed by the server account
+    pub allow_test_signers: bool, // Whether requests may have STELLAR_TEST_SIGNERS authorize calls
+}
+
+impl NetworkProfile {
+    /// Explorer page for a contract, if the network has an explorer
+    pub fn contract_explorer_url(&self, contract_id: &str) -> Option<String> {
+        self.explorer_url
+            .as_ref()
+            .map(|template| template.replace("{contract_id}", contract_id))
+    }
+
+    /// Whether this is the public network, where fees cost real XLM
+    pub fn is_mainnet(&self) -> bool {
+        self.passphrase == MAINNET_PASSPHRASE
+    }
+
+    /// Whether calls may be signed and paid for with the server account.
+    /// Never on mainnet, whatever the profile says.
+    pub fn server_signing_allowed(&self) -> bool {
+        self.allow_server_signing && !self.is_mainnet()
+    }
+
+    /// Whether the server may sign authorizations for its test signers.
+    /// Never on mainnet, whatever the profile says.
+    pub fn test_signers_allowed(&self) -> bool {
+        self.allow_test_signers && !self.is_mainnet()
+    }
+}
+
+/// The configured network profiles
+pub struct Networks {
+    profiles: Vec<NetworkProfile>,
+    default: String,
+}
+
+impl Networks {
+    /// Load profiles from NETWORKS_CONFIG (default `networks.toml`). Without
+    /// a config file a single profile is built from the STELLAR_* variables.
+    /// STELLAR_NETWORK, if set, picks the default profile.
+    pub fn from_env() -> Result<Self> {
+        let path = PathBuf::from(
+            env::var("NETWORKS_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string()),
+        );
+
+        let (profiles, file_default) = if path.exists() {
+            let config = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read network config {:?}", path))?;
+            parse_config(&config)
+                .with_context(|| format!("Invalid network config {:?}", path))?
+        } else {
+            warn!(
+                "Network config {:?} not found, using the STELLAR_* environment variables",
+                path
+            );
+            (vec![profile_from_env()], None)
+        };
+
+        let default = env::var("STELLAR_NETWORK")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .or(file_default);
+        let networks = Self::new(profiles, default)?;
+
+        info!(
+            "Networks: {} (default {})",
+            networks
+                .profiles
+                .iter()
+                .map(|profile| profile.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            networks.default
+        );
+
+        Ok(networks)
+    }
+
+    /// The default is `default` if given, otherwise the first profile
+    fn new(profiles: Vec<NetworkProfile>, default: Option<String>) -> Result<Self> {
+        let default = default.unwrap_or_else(|| profiles[0].name.clone());
+        if !profiles.iter().any(|profile| profile.name == default) {
+            return Err(anyhow::anyhow!(
+                "Default network {} is not configured; available networks: {}",
+                default,
+                profiles
+                    .iter()
+                    .map(|profile| profile.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        Ok(Self { profiles, default })
+    }
+
+    /// The profile named `requested`, or the default one
+    pub fn resolve(&self, requested: Option<&str>) -> Result<NetworkProfile> {
+        let name = requested.unwrap_or(&self.default);
+        self.profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .cloned()
+            .with_context(|| {
+                format!(
+                    "Network {} is not configured; available networks: {}",
+                    name,
+                    self.profiles
+                        .iter()
+                        .map(|profile| profile.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+
+    pub fn list(&self) -> &[NetworkProfile] {
+        &self.profiles
+    }
+
+    pub fn default_network(&self) -> &str {
+        &self.default
+    }
+}
+
+fn profile_from_env() -> NetworkProfile {
+    profile_from_vars(|name| env::var(name).ok())
+}
+
+/// The single profile, with each STELLAR_* variable looked up by `lookup`
+fn profile_from_vars(lookup: impl Fn(&str) -> Option<String>) -> NetworkProfile {
+    let var = |name: &str, fallback: &str| {
+        lookup(name)
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| fallback.to_string())
+    };
+
+    // The single profile is normally testnet, where spending is harmless
+    let passphrase = var("STELLAR_NETWORK_PASSPHRASE", FALLBACK_PASSPHRASE);
+    let test_network = passphrase != MAINNET_PASSPHRASE;
+
+    NetworkProfile {
+        name: var("STELLAR_NETWORK", FALLBACK_NETWORK),
+        rpc_url: var("STELLAR_RPC_URL", FALLBACK_RPC_URL),
+        horizon_url: Some(var("STELLAR_HORIZON_URL", FALLBACK_HORIZON_URL)),
+        passphrase,
+        friendbot_url: None,
+        explorer_url: Some(FALLBACK_EXPLORER_URL.to_string()),
+        allow_deploy: test_network,
+        allow_server_signing: test_network,
+        allow_test_signers: test_network,
+    }
+}
+
+/// Parse `[networks.<name>]` tables and the optional top-level `default`
+fn parse_config(config: &str) -> Result<(Vec<NetworkProfile>, Option<String>)> {
+    let document = config
+        .parse::<toml_edit::DocumentMut>()
+        .context("Failed to parse TOML")?;
+
+    let default = document
+        .get("default")
+        .and_then(|default| default.as_str())
+        .map(str::to_string);
+
+    let networks = document
+        .get("networks")
+        .and_then(|networks| networks.as_table_like())
+        .context("Missing [networks] table")?;
+
+    let profiles = networks
+        .iter()
+        .map(|(name, table)| {
+            let table = table
+                .as_table_like()
+                .with_context(|| format!("networks.{} must be a table", name))?;
+            let string = |key: &str| {
+                table
+                    .get(key)
+                    .map(|value| {
+                        value
+                            .as_str()
+                            .map(str::to_string)
+                            .with_context(|| format!("networks.{}.{} must be a string", name, key))
+                    })
+                    .transpose()
+            };
+            let required = |key: &str| {
+                string(key)?.with_context(|| format!("networks.{} is missing {}", name, key))
+            };
+            // Permissions spend the server's funds, so they are off unless set
+            let flag = |key: &str| match table.get(key) {
+                Some(value) => value
+                    .as_bool()
+                    .with_context(|| format!("networks.{}.{} must be a boolean", name, key)),
+                None => Ok(false),
+            };
+
+            Ok(NetworkProfile {
+                name: name.to_string(),
+                rpc_url: required("rpc_url")?,
+                horizon_url: string("horizon_url")?,
+                passphrase: required("passphrase")?,
+                friendbot_url: string("friendbot_url")?,
+                explorer_url: string("explorer_url")?,
+                allow_deploy: flag("allow_deploy")?,
+                allow_server_signing: flag("allow_server_signing")?,
+                allow_test_signers: flag("allow_test_signers")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if profiles.is_empty() {
+        return Err(anyhow::anyhow!("No networks configured"));
+    }
+
+    Ok((profiles, default))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const TESTNET: &str = r#"
+[networks.testnet]
+rpc_url = "https://soroban-testnet.stellar.org"
+passphrase = "Test SDF Network ; September 2015"
+"#;
+
+    fn mainnet(flags: &str) -> NetworkProfile {
+        let config = format!(
+            "[networks.mainnet]\nrpc_url = \"https://mainnet.example\"\npassphrase = \"{}\"\n{}",
+            MAINNET_PASSPHRASE, flags
+        );
+        let (mut profiles, _) = parse_config(&config).unwrap();
+        profiles.remove(0)
+    }
+
+    fn from_vars(vars: &[(&str, &str)]) -> NetworkProfile {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        profile_from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn flags_default_to_false() {
+        let (profiles, default) = parse_config(TESTNET).unwrap();
+        assert_eq!(default, None);
+        let testnet = &profiles[0];
+        assert_eq!(testnet.name, "testnet");
+        assert!(!testnet.allow_deploy);
+        assert!(!testnet.allow_server_signing);
+        assert!(!testnet.allow_test_signers);
+        assert!(!testnet.server_signing_allowed());
+        assert!(!testnet.test_signers_allowed());
+    }
+
+    #[test]
+    fn flags_are_read_when_set() {
+        let config = format!(
+            "{}allow_deploy = true\nallow_server_signing = true\nallow_test_signers = false\n",
+            TESTNET
+        );
+        let (profiles, _) = parse_config(&config).unwrap();
+        let testnet = &profiles[0];
+        assert!(testnet.allow_deploy);
+        assert!(testnet.server_signing_allowed());
+        assert!(!testnet.test_signers_allowed());
+    }
+
+    #[test]
+    fn flags_must_be_booleans() {
+        let config = format!("{}allow_server_signing = \"yes\"\n", TESTNET);
+        let err = parse_config(&config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "networks.testnet.allow_server_signing must be a boolean"
+        );
+    }
+
+    #[test]
+    fn mainnet_is_never_allowed_to_sign() {
+        let profile = mainnet(
+            "allow_deploy = true\nallow_server_signing = true\nallow_test_signers = true\n",
+        );
+        assert!(profile.is_mainnet());
+        assert!(profile.allow_server_signing);
+        assert!(!profile.server_signing_allowed());
+        assert!(!profile.test_signers_allowed());
+
+        let profile = from_vars(&[("STELLAR_NETWORK_PASSPHRASE", MAINNET_PASSPHRASE)]);
+        assert!(profile.is_mainnet());
+        assert!(!profile.allow_deploy);
+        assert!(!profile.server_signing_allowed());
+        assert!(!profile.test_signers_allowed());
+    }
+
+    #[test]
+    fn env_profile_defaults_to_testnet() {
+        let profile = from_vars(&[("STELLAR_NETWORK", "")]);
+        assert_eq!(profile.name, FALLBACK_NETWORK);
+        assert_eq!(profile.passphrase, FALLBACK_PASSPHRASE);
+        assert!(profile.allow_deploy);
+        assert!(profile.server_signing_allowed());
+        assert!(profile.test_signers_allowed());
+    }
+
+    #[test]
+    fn default_must_name_a_configured_profile() {
+        let config = format!("default = \"testnet\"\n{}", TESTNET);
+        let (profiles, default) = parse_config(&config).unwrap();
+        assert_eq!(default.as_deref(), Some("testnet"));
+        let networks = Networks::new(profiles.clone(), default).unwrap();
+        assert_eq!(networks.default_network(), "testnet");
+
+        let networks = Networks::new(profiles.clone(), None).unwrap();
+        assert_eq!(networks.default_network(), "testnet");
+
+        let err = Networks::new(profiles, Some("futurenet".to_string()))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Default network futurenet is not configured; available networks: testnet"
+        );
+    }
+
+    #[test]
+    fn networks_table_is_required() {
+        for config in [
+            "",
+            "default = \"testnet\"\n",
+            "[network.testnet]\nrpc_url = \"x\"\npassphrase = \"y\"\n",
+            "networks = \"testnet\"\n",
+        ] {
+            let err = parse_config(config).unwrap_err();
+            assert_eq!(err.to_string(), "Missing [networks] table", "{:?}", config);
+        }
+
+        assert_eq!(
+            parse_config("[networks]\n").unwrap_err().to_string(),
+            "No networks configured"
+        );
+        assert_eq!(
+            parse_config("[networks]\ntestnet = 1\n")
+                .unwrap_err()
+                .to_string(),
+            "networks.testnet must be a table"
+        );
+    }
+
+    #[test]
+    fn profiles_need_an_rpc_url_and_passphrase() {
+        let err = parse_config("[networks.testnet]\nrpc_url = \"x\"\n").unwrap_err();
+        assert_eq!(err.to_string(), "networks.testnet is missing passphrase");
+    }
+}
@@ -0,0 +1,350 @@
+use anyhow::{Context, Result};
+use log::info;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::process::Command;
+
+use crate::services::sdk_versions::SdkVersion;
+
+// Unprivileged uid/gid builds run as inside the sandbox (nobody)
+const SANDBOX_UID: &str = "65534";
+const SANDBOX_GID: &str = "65534";
+
+/// Whether a sandboxed cargo run may reach the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    /// Dependency fetching; no user code runs
+    Allowed,
+    /// Builds and tests, which run build scripts, proc macros and tests
+    Denied,
+}
+
+/// Isolation strategy for cargo runs over user code.
+///
+/// Implementations return a command that runs `cargo` for the given project;
+/// callers append the subcommand and its arguments.
+pub trait Sandbox: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn cargo(&self, project_path: &Path, sdk: &SdkVersion, network: Network) -> Result<Command>;
+}
+
+/// Pick the sandbox from BUILD_SANDBOX (`bwrap`, the default, or `local`)
+pub fn from_env() -> Result<Arc<dyn Sandbox>> {
+    let sandbox: Arc<dyn Sandbox> = match env::var("BUILD_SANDBOX").as_deref() {
+        Ok("local") => Arc::new(LocalSandbox::new()),
+        Ok("bwrap") | Err(_) => Arc::new(BubblewrapSandbox::new()?),
+        Ok(other) => return Err(anyhow::anyhow!("Unknown BUILD_SANDBOX {:?}", other)),
+    };
+
+    info!("Running user builds with the {} sandbox", sandbox.name());
+    Ok(sandbox)
+}
+
+/// The only variables a build sees; in particular nothing from the backend's
+/// own configuration such as STELLAR_SECRET_KEY
+fn scrubbed_env(command: &mut Command, home: &Path, sdk: &SdkVersion) {
+    command.env_clear();
+    for name in ["PATH", "RUSTUP_HOME", "RUSTUP_TOOLCHAIN", "LANG"] {
+        if let Ok(value) = env::var(name) {
+            command.env(name, value);
+        }
+    }
+    command.env("HOME", home);
+    sdk.configure(command);
+}
+
+fn network_args(command: &mut Command, network: Network) {
+    if network == Network::Denied {
+        // Fail fast instead of hanging on DNS if something wants the registry
+        command.arg("--offline");
+    }
+}
+
+/// Runs cargo directly with a scrubbed environment and `--offline` builds.
+///
+/// Offers no isolation from the host; meant for local development.
+pub struct LocalSandbox {
+    home: PathBuf,
+}
+
+impl LocalSandbox {
+    pub fn new() -> Self {
+        Self {
+            home: env::var("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| env::temp_dir()),
+        }
+    }
+}
+
+impl Sandbox for LocalSandbox {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn cargo(&self, project_path: &Path, sdk: &SdkVersion, network: Network) -> Result<Command> {
+        let mut command = Command::new("cargo");
+        scrubbed_env(&mut command, &self.home, sdk);
+        network_args(&mut command, network);
+        command.current_dir(project_path);
+        Ok(command)
+    }
+}
+
+/// Runs cargo under bubblewrap as an unprivileged user in fresh namespaces.
+///
+/// The host filesystem is mounted read-only, with the backend's working
+/// directory (other projects, caches, `.env`) and home directory hidden.
+/// Only the project is writable; the dependency cache is writable while
+/// fetching and read-only while building, and the network is only
+/// reachable while fetching.
+pub struct BubblewrapSandbox {
+    work_dir: PathBuf,
+    home: Option<PathBuf>,
+    // Toolchain locations that may live under a hidden directory
+    tool_dirs: Vec<PathBuf>,
+}
+
+impl BubblewrapSandbox {
+    pub fn new() -> Result<Self> {
+        let version = std::process::Command::new("bwrap")
+            .arg("--version")
+            .output()
+            .context("bubblewrap (bwrap) not found; install it or set BUILD_SANDBOX=local")?;
+        info!("Found {}", String::from_utf8_lossy(&version.stdout).trim());
+
+        let work_dir = env::current_dir()
+            .and_then(fs::canonicalize)
+            .context("Failed to resolve working directory")?;
+        let home = env::var("HOME")
+            .ok()
+            .and_then(|home| fs::canonicalize(home).ok());
+
+        let mut tool_dirs = Vec::new();
+        let rustup_home = env::var("RUSTUP_HOME")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| home.as_ref().map(|home| home.join(".rustup")));
+        if let Some(rustup_home) = rustup_home.and_then(|dir| fs::canonicalize(dir).ok()) {
+            tool_dirs.push(rustup_home);
+        }
+        // Directory holding the rustup proxies (`cargo`, `rustc`) on PATH
+        if let Some(bin_dir) = env::var_os("PATH")
+            .and_then(|path| env::split_paths(&path).find(|dir| dir.join("cargo").is_file()))
+        {
+            tool_dirs.push(fs::canonicalize(bin_dir)?);
+        }
+
+        Ok(Self {
+            work_dir,
+            home,
+            tool_dirs,
+        })
+    }
+}
+
+impl Sandbox for BubblewrapSandbox {
+    fn name(&self) -> &'static str {
+        "bwrap"
+    }
+
+    fn cargo(&self, project_path: &Path, sdk: &SdkVersion, network: Network) -> Result<Command> {
+        let project_path =
+            fs::canonicalize(project_path).context("Failed to resolve project directory")?;
+        fs::create_dir_all(sdk.cargo_home()).context("Failed to create cargo home")?;
+        let cargo_home =
+            fs::canonicalize(sdk.cargo_home()).context("Failed to resolve cargo home")?;
+
+        let mut command = Command::new("bwrap");
+        command.args(["--ro-bind", "/", "/"]);
+        if let Some(home) = &self.home {
+            command.arg("--tmpfs").arg(home);
+        }
+        command.arg("--tmpfs").arg(&self.work_dir);
+        for dir in &self.tool_dirs {
+            command.arg("--ro-bind").arg(dir).arg(dir);
+        }
+        command
+            .arg("--bind")
+            .arg(&project_path)
+            .arg(&project_path)
+            .arg(match network {
+                Network::Allowed => "--bind",
+                Network::Denied => "--ro-bind",
+            })
+            .arg(&cargo_home)
+            .arg(&cargo_home)
+            .args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"])
+            .args(["--unshare-all", "--unshare-user"])
+            .args(["--uid", SANDBOX_UID, "--gid", SANDBOX_GID]);
+        if network == Network::Allowed {
+            command.arg("--share-net");
+        }
+        command
+            .args(["--die-with-parent", "--new-session", "--cap-drop", "ALL"])
+            .arg("--chdir")
+            .arg(&project_path)
+            .args(["--", "cargo"]);
+
+        // bwrap passes its environment on to cargo
+        scrubbed_env(&mut command, Path::new("/tmp"), sdk);
+        command.env("CARGO_HOME", &cargo_home);
+        network_args(&mut command, network);
+        command.current_dir(&project_path);
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Layout {
+        _root: tempfile::TempDir,
+        sandbox: BubblewrapSandbox,
+        project: PathBuf,
+        sdk: SdkVersion,
+    }
+
+    // A backend working directory with one project, laid out on disk since
+    // the sandbox canonicalizes its paths
+    fn layout() -> Layout {
+        let root = tempfile::tempdir().unwrap();
+        let base = fs::canonicalize(root.path()).unwrap();
+        let work_dir = base.join("backend");
+        let project = work_dir.join("projects").join("user").join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(base.join("home")).unwrap();
+        fs::create_dir_all(base.join("rustup")).unwrap();
+
+        Layout {
+            sandbox: BubblewrapSandbox {
+                work_dir: work_dir.clone(),
+                home: Some(base.join("home")),
+                tool_dirs: vec![base.join("rustup")],
+            },
+            project,
+            sdk: SdkVersion {
+                version: "22.0.0".to_string(),
+                cache_dir: work_dir.join("sdk_cache").join("22.0.0"),
+                target_lock: Default::default(),
+            },
+            _root: root,
+        }
+    }
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn path(path: &Path) -> String {
+        path.to_string_lossy().into_owned()
+    }
+
+    // Whether `args` has `expected` as consecutive arguments
+    fn has(args: &[String], expected: &[&str]) -> bool {
+        args.windows(expected.len())
+            .any(|window| window == expected)
+    }
+
+    // Paths mounted writable, in mount order
+    fn writable(args: &[String]) -> Vec<&str> {
+        args.windows(2)
+            .filter(|window| window[0] == "--bind")
+            .map(|window| window[1].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn builds_run_in_fresh_namespaces_without_network() {
+        let layout = layout();
+        let command = layout
+            .sandbox
+            .cargo(&layout.project, &layout.sdk, Network::Denied)
+            .unwrap();
+        let args = args(&command);
+        let project = path(&layout.project);
+        let cargo_home = path(&layout.sdk.cargo_home());
+        let work_dir = path(&layout.sandbox.work_dir);
+        let home = path(layout.sandbox.home.as_ref().unwrap());
+        let rustup = path(&layout.sandbox.tool_dirs[0]);
+
+        assert_eq!(command.as_std().get_program(), "bwrap");
+        assert_eq!(&args[..3], ["--ro-bind", "/", "/"]);
+        assert!(has(&args, &["--tmpfs", &home]));
+        assert!(has(&args, &["--tmpfs", &work_dir]));
+        assert!(has(&args, &["--ro-bind", &rustup, &rustup]));
+        assert!(has(&args, &["--ro-bind", &cargo_home, &cargo_home]));
+        assert_eq!(writable(&args), [project.as_str()]);
+        assert!(has(&args, &["--tmpfs", "/tmp"]));
+
+        assert!(has(&args, &["--unshare-all", "--unshare-user"]));
+        assert!(!args.iter().any(|arg| arg == "--share-net"));
+        assert!(has(&args, &["--uid", SANDBOX_UID, "--gid", SANDBOX_GID]));
+        assert!(has(
+            &args,
+            &["--die-with-parent", "--new-session", "--cap-drop", "ALL"]
+        ));
+        assert!(has(
+            &args,
+            &["--chdir", &project, "--", "cargo", "--offline"]
+        ));
+    }
+
+    #[test]
+    fn fetches_may_reach_the_network_and_write_the_cache() {
+        let layout = layout();
+        let command = layout
+            .sandbox
+            .cargo(&layout.project, &layout.sdk, Network::Allowed)
+            .unwrap();
+        let args = args(&command);
+        let project = path(&layout.project);
+        let cargo_home = path(&layout.sdk.cargo_home());
+
+        assert_eq!(writable(&args), [project.as_str(), cargo_home.as_str()]);
+        assert!(has(&args, &["--unshare-all", "--unshare-user"]));
+        assert!(has(&args, &["--gid", SANDBOX_GID, "--share-net"]));
+        assert_eq!(args.last().unwrap(), "cargo");
+    }
+
+    #[test]
+    fn the_environment_is_cleared() {
+        let layout = layout();
+        let command = layout
+            .sandbox
+            .cargo(&layout.project, &layout.sdk, Network::Denied)
+            .unwrap();
+
+        // The only way to observe env_clear() on a Command
+        assert!(format!("{:?}", command.as_std()).contains(" env -i "));
+        for (name, value) in command.as_std().get_envs() {
+            let name = name.to_string_lossy();
+            assert!(
+                [
+                    "PATH",
+                    "RUSTUP_HOME",
+                    "RUSTUP_TOOLCHAIN",
+                    "LANG",
+                    "HOME",
+                    "CARGO_HOME"
+                ]
+                .contains(&name.as_ref()),
+                "{} passed to the sandbox",
+                name
+            );
+            match name.as_ref() {
+                "HOME" => assert_eq!(value, Some("/tmp".as_ref())),
+                "CARGO_HOME" => assert_eq!(value, Some(layout.sdk.cargo_home().as_os_str())),
+                _ => {}
+            }
+        }
+    }
+}
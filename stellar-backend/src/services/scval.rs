@@ -0,0 +1,1473 @@
+use serde_json::{Map, Value};
+use std::fmt;
+use stellar_xdr::curr::{
+    Duration, Int128Parts, Int256Parts, ScAddress, ScBytes, ScError, ScMap, ScMapEntry,
+    ScSpecEntry, ScSpecFunctionV0, ScSpecTypeDef, ScSpecUdtStructV0, ScSpecUdtUnionCaseV0,
+    ScSpecUdtUnionV0, ScString, ScSymbol, ScVal, ScVec, TimePoint, UInt128Parts, UInt256Parts,
+};
+
+use crate::models::ArgError;
+
+// User defined types can refer to each other, so a malicious spec could
+// describe an infinitely nested type
+const MAX_DEPTH: usize = 64;
+
+/// A contract's spec, used to convert between JSON and contract values
+#[derive(Debug, Clone)]
+pub struct ContractInterface {
+    entries: Vec<ScSpecEntry>,
+}
+
+type Encoded = std::result::Result<ScVal, ArgError>;
+
+impl ContractInterface {
+    pub fn new(entries: Vec<ScSpecEntry>) -> Self {
+        Self { entries }
+    }
+
+    pub fn function(&self, name: &str) -> Option<&ScSpecFunctionV0> {
+        self.entries.iter().find_map(|entry| match entry {
+            ScSpecEntry::FunctionV0(function) if function.name.to_utf8_string_lossy() == name => {
+                Some(function)
+            }
+            _ => None,
+        })
+    }
+
+    /// Names of the contract's callable functions
+    pub fn function_names(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry {
+                ScSpecEntry::FunctionV0(function) => Some(function.name.to_utf8_string_lossy()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Name and doc of the error enum variant with this code
+    pub fn error_case(&self, code: u32) -> Option<(String, String)> {
+        self.entries.iter().find_map(|entry| match entry {
+            ScSpecEntry::UdtErrorEnumV0(udt) => udt
+                .cases
+                .iter()
+                .find(|case| case.value == code)
+                .map(|case| {
+                    (
+                        case.name.to_utf8_string_lossy(),
+                        case.doc.to_utf8_string_lossy(),
+                    )
+                }),
+            _ => None,
+        })
+    }
+
+    fn udt(&self, name: &str) -> Option<&ScSpecEntry> {
+        self.entries.iter().find(|entry| {
+            let udt_name = match entry {
+                ScSpecEntry::UdtStructV0(udt) => &udt.name,
+                ScSpecEntry::UdtUnionV0(udt) => &udt.name,
+                ScSpecEntry::UdtEnumV0(udt) => &udt.name,
+                ScSpecEntry::UdtErrorEnumV0(udt) => &udt.name,
+                _ => return false,
+            };
+            udt_name.to_utf8_string_lossy() == name
+        })
+    }
+
+    /// Encode a call's JSON arguments, given either as an object keyed by
+    /// parameter name or as a positional array. Every argument is checked,
+    /// so all problems are reported at once.
+    pub fn encode_args(
+        &self,
+        function: &ScSpecFunctionV0,
+        args: &Value,
+    ) -> std::result::Result<Vec<ScVal>, Vec<ArgError>> {
+        let name = function.name.to_utf8_string_lossy();
+        let inputs: Vec<_> = function
+            .inputs
+            .iter()
+            .map(|input| (input.name.to_utf8_string_lossy(), &input.type_))
+            .collect();
+
+        let values: Vec<(String, &ScSpecTypeDef, Option<&Value>)> = match args {
+            Value::Array(values) => {
+                if values.len() != inputs.len() {
+                    return Err(vec![error(
+                        "",
+                        format!(
+                            "{} takes {} arguments, got {}",
+                            name,
+                            inputs.len(),
+                            values.len()
+                        ),
+                    )]);
+                }
+                inputs
+                    .into_iter()
+                    .zip(values)
+                    .map(|((input_name, type_def), value)| (input_name, type_def, Some(value)))
+                    .collect()
+            }
+            Value::Object(values) => {
+                let unknown: Vec<_> = values
+                    .keys()
+                    .filter(|key| !inputs.iter().any(|(input_name, _)| input_name == *key))
+                    .map(|key| error(key, format!("{} has no argument {}", name, key)))
+                    .collect();
+                if !unknown.is_empty() {
+                    return Err(unknown);
+                }
+                inputs
+                    .into_iter()
+                    .map(|(input_name, type_def)| {
+                        let value = values.get(&input_name);
+                        (input_name, type_def, value)
+                    })
+                    .collect()
+            }
+            Value::Null if inputs.is_empty() => Vec::new(),
+            _ => {
+                return Err(vec![error(
+                    "",
+                    format!("Arguments for {} must be an object or an array", name),
+                )])
+            }
+        };
+
+        let mut encoded = Vec::new();
+        let mut errors = Vec::new();
+        for (input_name, type_def, value) in values {
+            let result = match value {
+                Some(value) => self.encode(value, type_def, &input_name, 0),
+                // Omitted optional arguments are None
+                None if matches!(type_def, ScSpecTypeDef::Option(_)) => Ok(ScVal::Void),
+                None => Err(error(
+                    &input_name,
+                    format!("missing argument of type {}", type_name(type_def)),
+                )),
+            };
+            match result {
+                Ok(value) => encoded.push(value),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(encoded)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Encode a JSON value as the given spec type
+    fn encode(&self, value: &Value, type_def: &ScSpecTypeDef, path: &str, depth: usize) -> Encoded {
+        if depth > MAX_DEPTH {
+            return Err(error(path, "value is nested too deeply"));
+        }
+        let expected = || {
+            error(
+                path,
+                format!("expected {}, got {}", type_name(type_def), describe(value)),
+            )
+        };
+
+        Ok(match type_def {
+            ScSpecTypeDef::Bool => ScVal::Bool(value.as_bool().ok_or_else(expected)?),
+            ScSpecTypeDef::Void => match value {
+                Value::Null => ScVal::Void,
+                _ => return Err(expected()),
+            },
+            ScSpecTypeDef::U32 => ScVal::U32(integer(value).ok_or_else(expected)?),
+            ScSpecTypeDef::I32 => ScVal::I32(integer(value).ok_or_else(expected)?),
+            ScSpecTypeDef::U64 => ScVal::U64(integer(value).ok_or_else(expected)?),
+            ScSpecTypeDef::I64 => ScVal::I64(integer(value).ok_or_else(expected)?),
+            ScSpecTypeDef::Timepoint => {
+                ScVal::Timepoint(TimePoint(integer(value).ok_or_else(expected)?))
+            }
+            ScSpecTypeDef::Duration => {
+                ScVal::Duration(Duration(integer(value).ok_or_else(expected)?))
+            }
+            ScSpecTypeDef::U128 => ScVal::U128(
+                integer_text(value)
+                    .and_then(|text| text.parse::<UInt128Parts>().ok())
+                    .ok_or_else(expected)?,
+            ),
+            ScSpecTypeDef::I128 => ScVal::I128(
+                integer_text(value)
+                    .and_then(|text| text.parse::<Int128Parts>().ok())
+                    .ok_or_else(expected)?,
+            ),
+            ScSpecTypeDef::U256 => ScVal::U256(
+                integer_text(value)
+                    .and_then(|text| text.parse::<UInt256Parts>().ok())
+                    .ok_or_else(expected)?,
+            ),
+            ScSpecTypeDef::I256 => ScVal::I256(
+                integer_text(value)
+                    .and_then(|text| text.parse::<Int256Parts>().ok())
+                    .ok_or_else(expected)?,
+            ),
+            ScSpecTypeDef::Bytes => ScVal::Bytes(bytes(value).ok_or_else(expected)?),
+            ScSpecTypeDef::BytesN(bytes_n) => {
+                let bytes = bytes(value).ok_or_else(expected)?;
+                if bytes.len() != bytes_n.n as usize {
+                    return Err(error(
+                        path,
+                        format!("expected {} bytes, got {}", bytes_n.n, bytes.len()),
+                    ));
+                }
+                ScVal::Bytes(bytes)
+            }
+            ScSpecTypeDef::String => ScVal::String(ScString(
+                value
+                    .as_str()
+                    .ok_or_else(expected)?
+                    .try_into()
+                    .map_err(|_| error(path, "string is too long"))?,
+            )),
+            ScSpecTypeDef::Symbol => {
+                let text = value.as_str().ok_or_else(expected)?;
+                if !text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(error(path, "symbols may only contain a-z, A-Z, 0-9 and _"));
+                }
+                ScVal::Symbol(ScSymbol(
+                    text.try_into()
+                        .map_err(|_| error(path, "symbol is longer than 32 characters"))?,
+                ))
+            }
+            ScSpecTypeDef::Address | ScSpecTypeDef::MuxedAddress => {
+                let address = value
+                    .as_str()
+                    .and_then(|text| text.trim().parse::<ScAddress>().ok())
+                    .ok_or_else(|| {
+                        error(
+                            path,
+                            format!("expected a G... or C... address, got {}", describe(value)),
+                        )
+                    })?;
+                match (&address, type_def) {
+                    (ScAddress::Account(_) | ScAddress::Contract(_), _)
+                    | (ScAddress::MuxedAccount(_), ScSpecTypeDef::MuxedAddress) => {}
+                    _ => {
+                        return Err(error(
+                            path,
+                            format!("{} is not an account or contract address", address),
+                        ))
+                    }
+                }
+                ScVal::Address(address)
+            }
+            ScSpecTypeDef::Option(option) => match value {
+                Value::Null => ScVal::Void,
+                value => self.encode(value, &option.value_type, path, depth + 1)?,
+            },
+            ScSpecTypeDef::Vec(vec) => {
+                let values = value.as_array().ok_or_else(expected)?;
+                let elements = values
+                    .iter()
+                    .enumerate()
+                    .map(|(index, element)| {
+                        self.encode(
+                            element,
+                            &vec.element_type,
+                            &index_path(path, index),
+                            depth + 1,
+                        )
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                vec_val(elements, path)?
+            }
+            ScSpecTypeDef::Tuple(tuple) => {
+                let values = value.as_array().ok_or_else(expected)?;
+                if values.len() != tuple.value_types.len() {
+                    return Err(error(
+                        path,
+                        format!(
+                            "expected {} elements, got {}",
+                            tuple.value_types.len(),
+                            values.len()
+                        ),
+                    ));
+                }
+                let elements = values
+                    .iter()
+                    .zip(tuple.value_types.iter())
+                    .enumerate()
+                    .map(|(index, (element, type_def))| {
+                        self.encode(element, type_def, &index_path(path, index), depth + 1)
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                vec_val(elements, path)?
+            }
+            ScSpecTypeDef::Map(map) => {
+                // Objects for string-like keys, [[key, value], ...] for any key
+                let entries: Vec<(Value, &Value)> = match value {
+                    Value::Object(object) => object
+                        .iter()
+                        .map(|(key, value)| (Value::String(key.clone()), value))
+                        .collect(),
+                    Value::Array(pairs) => pairs
+                        .iter()
+                        .enumerate()
+                        .map(|(index, pair)| match pair.as_array().map(Vec::as_slice) {
+                            Some([key, value]) => Ok((key.clone(), value)),
+                            _ => Err(error(
+                                &index_path(path, index),
+                                "expected a [key, value] pair",
+                            )),
+                        })
+                        .collect::<std::result::Result<_, _>>()?,
+                    _ => return Err(expected()),
+                };
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| {
+                        let entry_path = index_path(path, key);
+                        Ok(ScMapEntry {
+                            key: self.encode(key, &map.key_type, &entry_path, depth + 1)?,
+                            val: self.encode(value, &map.value_type, &entry_path, depth + 1)?,
+                        })
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                map_val(entries, path)?
+            }
+            ScSpecTypeDef::Udt(udt) => {
+                let name = udt.name.to_utf8_string_lossy();
+                match self.udt(&name) {
+                    Some(ScSpecEntry::UdtStructV0(udt)) => {
+                        self.encode_struct(value, udt, path, depth)?
+                    }
+                    Some(ScSpecEntry::UdtUnionV0(udt)) => {
+                        self.encode_union(value, udt, path, depth)?
+                    }
+                    Some(ScSpecEntry::UdtEnumV0(udt)) => {
+                        let cases: Vec<_> = udt
+                            .cases
+                            .iter()
+                            .map(|case| (case.name.to_utf8_string_lossy(), case.value))
+                            .collect();
+                        ScVal::U32(case_value(value, &name, &cases, path)?)
+                    }
+                    Some(ScSpecEntry::UdtErrorEnumV0(udt)) => {
+                        let cases: Vec<_> = udt
+                            .cases
+                            .iter()
+                            .map(|case| (case.name.to_utf8_string_lossy(), case.value))
+                            .collect();
+                        ScVal::Error(ScError::Contract(case_value(value, &name, &cases, path)?))
+                    }
+                    _ => {
+                        return Err(error(
+                            path,
+                            format!("the contract does not define type {}", name),
+                        ))
+                    }
+                }
+            }
+            ScSpecTypeDef::Val => infer(value, path, depth)?,
+            ScSpecTypeDef::Error => {
+                ScVal::Error(ScError::Contract(integer(value).ok_or_else(expected)?))
+            }
+            ScSpecTypeDef::Result(_) => {
+                return Err(error(path, "Result values cannot be passed as arguments"))
+            }
+        })
+    }
+
+    /// Structs with named fields are objects; tuple structs are arrays
+    fn encode_struct(
+        &self,
+        value: &Value,
+        udt: &ScSpecUdtStructV0,
+        path: &str,
+        depth: usize,
+    ) -> Encoded {
+        let name = udt.name.to_utf8_string_lossy();
+
+        if is_tuple_struct(udt) {
+            let values = value.as_array().ok_or_else(|| {
+                error(
+                    path,
+                    format!("expected {} as an array, got {}", name, describe(value)),
+                )
+            })?;
+            if values.len() != udt.fields.len() {
+                return Err(error(
+                    path,
+                    format!(
+                        "{} has {} fields, got {}",
+                        name,
+                        udt.fields.len(),
+                        values.len()
+                    ),
+                ));
+            }
+            let elements = udt
+                .fields
+                .iter()
+                .zip(values)
+                .enumerate()
+                .map(|(index, (field, value))| {
+                    self.encode(value, &field.type_, &index_path(path, index), depth + 1)
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            return vec_val(elements, path);
+        }
+
+        let object = value.as_object().ok_or_else(|| {
+            error(
+                path,
+                format!("expected {} as an object, got {}", name, describe(value)),
+            )
+        })?;
+        if let Some(unknown) = object.keys().find(|key| {
+            !udt.fields
+                .iter()
+                .any(|field| field.name.to_utf8_string_lossy() == **key)
+        }) {
+            return Err(error(
+                &field_path(path, unknown),
+                format!("{} has no field {}", name, unknown),
+            ));
+        }
+
+        let entries = udt
+            .fields
+            .iter()
+            .map(|field| {
+                let field_name = field.name.to_utf8_string_lossy();
+                let field_path = field_path(path, &field_name);
+                let value = match object.get(&field_name) {
+                    Some(value) => value,
+                    None if matches!(field.type_, ScSpecTypeDef::Option(_)) => &Value::Null,
+                    None => {
+                        return Err(error(
+                            &field_path,
+                            format!("missing field of type {}", type_name(&field.type_)),
+                        ))
+                    }
+                };
+                Ok(ScMapEntry {
+                    key: ScVal::Symbol(ScSymbol(
+                        field_name
+                            .as_str()
+                            .try_into()
+                            .map_err(|_| error(&field_path, "field name is too long"))?,
+                    )),
+                    val: self.encode(value, &field.type_, &field_path, depth + 1)?,
+                })
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        map_val(entries, path)
+    }
+
+    /// Unit variants are their name; variants with data are
+    /// `{"Name": value}`, or `{"Name": [values...]}` when they hold several
+    fn encode_union(
+        &self,
+        value: &Value,
+        udt: &ScSpecUdtUnionV0,
+        path: &str,
+        depth: usize,
+    ) -> Encoded {
+        let name = udt.name.to_utf8_string_lossy();
+        let (case_name, payload) = match value {
+            Value::String(case_name) => (case_name.as_str(), None),
+            Value::Object(object) if object.len() == 1 => {
+                let (case_name, payload) = object.iter().next().expect("one entry");
+                (case_name.as_str(), Some(payload))
+            }
+            _ => {
+                return Err(error(
+                    path,
+                    format!(
+                        "expected a {} variant as \"Name\" or {{\"Name\": value}}, got {}",
+                        name,
+                        describe(value)
+                    ),
+                ))
+            }
+        };
+
+        let case = udt
+            .cases
+            .iter()
+            .find(|case| union_case_name(case) == case_name);
+        let case_path = field_path(path, case_name);
+        let mut elements = vec![ScVal::Symbol(ScSymbol(
+            case_name
+                .try_into()
+                .map_err(|_| error(path, "variant name is too long"))?,
+        ))];
+
+        match (case, payload) {
+            (Some(ScSpecUdtUnionCaseV0::VoidV0(_)), None | Some(Value::Null)) => {}
+            (Some(ScSpecUdtUnionCaseV0::VoidV0(_)), Some(_)) => {
+                return Err(error(
+                    &case_path,
+                    format!("{}::{} holds no value", name, case_name),
+                ))
+            }
+            (Some(ScSpecUdtUnionCaseV0::TupleV0(case)), Some(payload)) => {
+                let types = case.type_.as_slice();
+                let values: Vec<&Value> = match (types.len(), payload) {
+                    (1, payload) => vec![payload],
+                    (_, Value::Array(values)) if values.len() == types.len() => {
+                        values.iter().collect()
+                    }
+                    _ => {
+                        return Err(error(
+                            &case_path,
+                            format!("{}::{} holds {} values", name, case_name, types.len()),
+                        ))
+                    }
+                };
+                for (index, (value, type_def)) in values.into_iter().zip(types).enumerate() {
+                    let element_path = if types.len() == 1 {
+                        case_path.clone()
+                    } else {
+                        index_path(&case_path, index)
+                    };
+                    elements.push(self.encode(value, type_def, &element_path, depth + 1)?);
+                }
+            }
+            (Some(ScSpecUdtUnionCaseV0::TupleV0(case)), None) => {
+                return Err(error(
+                    &case_path,
+                    format!(
+                        "{}::{} holds {}; pass {{\"{}\": value}}",
+                        name,
+                        case_name,
+                        case.type_
+                            .iter()
+                            .map(type_name)
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        case_name
+                    ),
+                ))
+            }
+            (None, _) => {
+                return Err(error(
+                    path,
+                    format!(
+                        "{} has no variant {}; expected one of {}",
+                        name,
+                        case_name,
+                        udt.cases
+                            .iter()
+                            .map(union_case_name)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                ))
+            }
+        }
+
+        vec_val(elements, path)
+    }
+
+    /// Decode a contract value into JSON shaped by its spec type, the
+    /// inverse of argument encoding. Parts that don't match the type fall
+    /// back to `scval_to_json`.
+    pub fn decode(&self, value: &ScVal, type_def: &ScSpecTypeDef) -> Value {
+        self.decode_at(value, type_def, 0)
+    }
+
+    fn decode_at(&self, value: &ScVal, type_def: &ScSpecTypeDef, depth: usize) -> Value {
+        if depth > MAX_DEPTH {
+            return scval_to_json(value);
+        }
+
+        match (type_def, value) {
+            (ScSpecTypeDef::Option(_), ScVal::Void) => Value::Null,
+            (ScSpecTypeDef::Option(option), value) => {
+                self.decode_at(value, &option.value_type, depth + 1)
+            }
+            (ScSpecTypeDef::Result(result), ScVal::Error(_)) => {
+                self.decode_at(value, &result.error_type, depth + 1)
+            }
+            (ScSpecTypeDef::Result(result), value) => {
+                self.decode_at(value, &result.ok_type, depth + 1)
+            }
+            (ScSpecTypeDef::Vec(vec), ScVal::Vec(Some(elements))) => Value::Array(
+                elements
+                    .iter()
+                    .map(|element| self.decode_at(element, &vec.element_type, depth + 1))
+                    .collect(),
+            ),
+            (ScSpecTypeDef::Tuple(tuple), ScVal::Vec(Some(elements)))
+                if elements.len() == tuple.value_types.len() =>
+            {
+                Value::Array(
+                    elements
+                        .iter()
+                        .zip(tuple.value_types.iter())
+                        .map(|(element, type_def)| self.decode_at(element, type_def, depth + 1))
+                        .collect(),
+                )
+            }
+            (ScSpecTypeDef::Map(map), ScVal::Map(Some(entries))) => {
+                if matches!(*map.key_type, ScSpecTypeDef::String | ScSpecTypeDef::Symbol) {
+                    Value::Object(
+                        entries
+                            .iter()
+                            .map(|entry| {
+                                (
+                                    string_key(&entry.key),
+                                    self.decode_at(&entry.val, &map.value_type, depth + 1),
+                                )
+                            })
+                            .collect(),
+                    )
+                } else {
+                    Value::Array(
+                        entries
+                            .iter()
+                            .map(|entry| {
+                                Value::Array(vec![
+                                    self.decode_at(&entry.key, &map.key_type, depth + 1),
+                                    self.decode_at(&entry.val, &map.value_type, depth + 1),
+                                ])
+                            })
+                            .collect(),
+                    )
+                }
+            }
+            (ScSpecTypeDef::Udt(udt), value) => {
+                self.decode_udt(value, &udt.name.to_utf8_string_lossy(), depth)
+            }
+            (_, value) => scval_to_json(value),
+        }
+    }
+
+    fn decode_udt(&self, value: &ScVal, name: &str, depth: usize) -> Value {
+        match (self.udt(name), value) {
+            (Some(ScSpecEntry::UdtStructV0(udt)), ScVal::Map(Some(entries))) => Value::Object(
+                entries
+                    .iter()
+                    .map(|entry| {
+                        let key = string_key(&entry.key);
+                        let decoded = match udt
+                            .fields
+                            .iter()
+                            .find(|field| field.name.to_utf8_string_lossy() == key)
+                        {
+                            Some(field) => self.decode_at(&entry.val, &field.type_, depth + 1),
+                            None => scval_to_json(&entry.val),
+                        };
+                        (key, decoded)
+                    })
+                    .collect(),
+            ),
+            (Some(ScSpecEntry::UdtStructV0(udt)), ScVal::Vec(Some(elements)))
+                if elements.len() == udt.fields.len() =>
+            {
+                Value::Array(
+                    elements
+                        .iter()
+                        .zip(udt.fields.iter())
+                        .map(|(element, field)| self.decode_at(element, &field.type_, depth + 1))
+                        .collect(),
+                )
+            }
+            (Some(ScSpecEntry::UdtUnionV0(udt)), ScVal::Vec(Some(elements))) => {
+                match elements.split_first() {
+                    Some((ScVal::Symbol(case_name), values)) => {
+                        let case_name = case_name.0.to_utf8_string_lossy();
+                        let types = udt.cases.iter().find_map(|case| match case {
+                            ScSpecUdtUnionCaseV0::TupleV0(case)
+                                if case.name.to_utf8_string_lossy() == case_name =>
+                            {
+                                Some(case.type_.as_slice())
+                            }
+                            _ => None,
+                        });
+                        let mut decoded: Vec<Value> = values
+                            .iter()
+                            .enumerate()
+                            .map(
+                                |(index, value)| match types.and_then(|types| types.get(index)) {
+                                    Some(type_def) => self.decode_at(value, type_def, depth + 1),
+                                    None => scval_to_json(value),
+                                },
+                            )
+                            .collect();
+                        let payload = match decoded.len() {
+                            0 => return Value::String(case_name),
+                            1 => decoded.remove(0),
+                            _ => Value::Array(decoded),
+                        };
+                        Value::Object(Map::from_iter([(case_name, payload)]))
+                    }
+                    _ => scval_to_json(value),
+                }
+            }
+            (Some(ScSpecEntry::UdtEnumV0(udt)), ScVal::U32(number)) => udt
+                .cases
+                .iter()
+                .find(|case| case.value == *number)
+                .map(|case| Value::String(case.name.to_utf8_string_lossy()))
+                .unwrap_or_else(|| Value::from(*number)),
+            (Some(ScSpecEntry::UdtErrorEnumV0(udt)), ScVal::Error(ScError::Contract(code))) => udt
+                .cases
+                .iter()
+                .find(|case| case.value == *code)
+                .map(|case| Value::String(case.name.to_utf8_string_lossy()))
+                .unwrap_or_else(|| scval_to_json(value)),
+            _ => scval_to_json(value),
+        }
+    }
+}
+
+/// Decode a contract value into plain JSON, without a spec type to go by.
+/// 128-bit and wider integers are rendered as strings.
+pub fn scval_to_json(value: &ScVal) -> Value {
+    match value {
+        ScVal::Bool(value) => Value::Bool(*value),
+        ScVal::Void => Value::Null,
+        ScVal::U32(value) => Value::from(*value),
+        ScVal::I32(value) => Value::from(*value),
+        ScVal::U64(value)
+        | ScVal::Timepoint(TimePoint(value))
+        | ScVal::Duration(Duration(value)) => Value::from(*value),
+        ScVal::I64(value) => Value::from(*value),
+        ScVal::U128(parts) => Value::String(parts.to_string()),
+        ScVal::I128(parts) => Value::String(parts.to_string()),
+        ScVal::U256(parts) => Value::String(parts.to_string()),
+        ScVal::I256(parts) => Value::String(parts.to_string()),
+        ScVal::Bytes(bytes) => Value::String(hex::encode(bytes.as_slice())),
+        ScVal::String(string) => Value::String(string.0.to_utf8_string_lossy()),
+        ScVal::Symbol(symbol) => Value::String(symbol.0.to_utf8_string_lossy()),
+        ScVal::Address(address) => Value::String(address.to_string()),
+        ScVal::Vec(Some(vec)) => Value::Array(vec.iter().map(scval_to_json).collect()),
+        ScVal::Vec(None) => Value::Array(Vec::new()),
+        ScVal::Map(Some(map)) => {
+            let string_keys = map
+                .iter()
+                .all(|entry| matches!(entry.key, ScVal::Symbol(_) | ScVal::String(_)));
+            if string_keys {
+                Value::Object(
+                    map.iter()
+                        .map(|entry| (string_key(&entry.key), scval_to_json(&entry.val)))
+                        .collect::<Map<_, _>>(),
+                )
+            } else {
+                Value::Array(
+                    map.iter()
+                        .map(|entry| {
+                            Value::Array(vec![scval_to_json(&entry.key), scval_to_json(&entry.val)])
+                        })
+                        .collect(),
+                )
+            }
+        }
+        ScVal::Map(None) => Value::Object(Map::new()),
+        ScVal::Error(error) => Value::String(format!("{:?}", error)),
+        other => Value::String(format!("{:?}", other)),
+    }
+}
+
+/// A spec type as it is written in Rust, e.g. `Option<Vec<u32>>`
+pub fn type_name(type_def: &ScSpecTypeDef) -> String {
+    match type_def {
+        ScSpecTypeDef::Val => "Val".to_string(),
+        ScSpecTypeDef::Bool => "bool".to_string(),
+        ScSpecTypeDef::Void => "()".to_string(),
+        ScSpecTypeDef::Error => "Error".to_string(),
+        ScSpecTypeDef::U32 => "u32".to_string(),
+        ScSpecTypeDef::I32 => "i32".to_string(),
+        ScSpecTypeDef::U64 => "u64".to_string(),
+        ScSpecTypeDef::I64 => "i64".to_string(),
+        ScSpecTypeDef::Timepoint => "Timepoint".to_string(),
+        ScSpecTypeDef::Duration => "Duration".to_string(),
+        ScSpecTypeDef::U128 => "u128".to_string(),
+        ScSpecTypeDef::I128 => "i128".to_string(),
+        ScSpecTypeDef::U256 => "U256".to_string(),
+        ScSpecTypeDef::I256 => "I256".to_string(),
+        ScSpecTypeDef::Bytes => "Bytes".to_string(),
+        ScSpecTypeDef::String => "String".to_string(),
+        ScSpecTypeDef::Symbol => "Symbol".to_string(),
+        ScSpecTypeDef::Address => "Address".to_string(),
+        ScSpecTypeDef::MuxedAddress => "MuxedAddress".to_string(),
+        ScSpecTypeDef::Option(option) => format!("Option<{}>", type_name(&option.value_type)),
+        ScSpecTypeDef::Result(result) => format!(
+            "Result<{}, {}>",
+            type_name(&result.ok_type),
+            type_name(&result.error_type)
+        ),
+        ScSpecTypeDef::Vec(vec) => format!("Vec<{}>", type_name(&vec.element_type)),
+        ScSpecTypeDef::Map(map) => format!(
+            "Map<{}, {}>",
+            type_name(&map.key_type),
+            type_name(&map.value_type)
+        ),
+        ScSpecTypeDef::Tuple(tuple) => format!(
+            "({})",
+            tuple
+                .value_types
+                .iter()
+                .map(type_name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        ScSpecTypeDef::BytesN(bytes) => format!("BytesN<{}>", bytes.n),
+        ScSpecTypeDef::Udt(udt) => udt.name.to_utf8_string_lossy(),
+    }
+}
+
+fn error(path: &str, message: impl Into<String>) -> ArgError {
+    ArgError {
+        path: path.to_string(),
+        message: message.into(),
+    }
+}
+
+fn field_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn index_path(path: &str, index: impl fmt::Display) -> String {
+    format!("{}[{}]", path, index)
+}
+
+/// Short rendering of a JSON value for error messages
+fn describe(value: &Value) -> String {
+    let text = value.to_string();
+    if text.chars().count() > 40 {
+        format!("{}...", text.chars().take(40).collect::<String>())
+    } else {
+        text
+    }
+}
+
+fn string_key(key: &ScVal) -> String {
+    match scval_to_json(key) {
+        Value::String(key) => key,
+        key => key.to_string(),
+    }
+}
+
+fn vec_val(elements: Vec<ScVal>, path: &str) -> Encoded {
+    Ok(ScVal::Vec(Some(ScVec(
+        elements
+            .try_into()
+            .map_err(|_| error(path, "too many elements"))?,
+    ))))
+}
+
+fn map_val(mut entries: Vec<ScMapEntry>, path: &str) -> Encoded {
+    // The host only accepts maps sorted by key, without duplicates
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    if entries.windows(2).any(|pair| pair[0].key == pair[1].key) {
+        return Err(error(path, "duplicate map key"));
+    }
+    Ok(ScVal::Map(Some(ScMap(
+        entries
+            .try_into()
+            .map_err(|_| error(path, "too many entries"))?,
+    ))))
+}
+
+/// Tuple structs (`struct Pair(u32, u32)`) have fields named 0, 1, ...
+fn is_tuple_struct(udt: &ScSpecUdtStructV0) -> bool {
+    !udt.fields.is_empty()
+        && udt
+            .fields
+            .iter()
+            .enumerate()
+            .all(|(index, field)| field.name.to_utf8_string_lossy() == index.to_string())
+}
+
+fn union_case_name(case: &ScSpecUdtUnionCaseV0) -> String {
+    match case {
+        ScSpecUdtUnionCaseV0::VoidV0(case) => case.name.to_utf8_string_lossy(),
+        ScSpecUdtUnionCaseV0::TupleV0(case) => case.name.to_utf8_string_lossy(),
+    }
+}
+
+/// Enum variants may be given by name or by value
+fn case_value(
+    value: &Value,
+    name: &str,
+    cases: &[(String, u32)],
+    path: &str,
+) -> std::result::Result<u32, ArgError> {
+    let found = match value {
+        Value::String(text) => cases.iter().find(|(case, _)| case == text),
+        Value::Number(number) => cases
+            .iter()
+            .find(|(_, case)| Some(u64::from(*case)) == number.as_u64()),
+        _ => None,
+    };
+    found.map(|(_, case)| *case).ok_or_else(|| {
+        error(
+            path,
+            format!(
+                "expected a {} variant ({}), got {}",
+                name,
+                cases
+                    .iter()
+                    .map(|(case, _)| case.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                describe(value)
+            ),
+        )
+    })
+}
+
+/// Encode JSON by its shape, for `Val` parameters that accept anything
+fn infer(value: &Value, path: &str, depth: usize) -> Encoded {
+    if depth > MAX_DEPTH {
+        return Err(error(path, "value is nested too deeply"));
+    }
+    Ok(match value {
+        Value::Null => ScVal::Void,
+        Value::Bool(value) => ScVal::Bool(*value),
+        Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+            (Some(number), _) => ScVal::I64(number),
+            (None, Some(number)) => ScVal::U64(number),
+            _ => return Err(error(path, "only integers are supported")),
+        },
+        Value::String(text) => ScVal::String(ScString(
+            text.as_str()
+                .try_into()
+                .map_err(|_| error(path, "string is too long"))?,
+        )),
+        Value::Array(values) => vec_val(
+            values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| infer(value, &index_path(path, index), depth + 1))
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+            path,
+        )?,
+        Value::Object(object) => map_val(
+            object
+                .iter()
+                .map(|(key, value)| {
+                    let key_path = field_path(path, key);
+                    Ok(ScMapEntry {
+                        key: ScVal::Symbol(ScSymbol(
+                            key.as_str()
+                                .try_into()
+                                .map_err(|_| error(&key_path, "key is too long"))?,
+                        )),
+                        val: infer(value, &key_path, depth + 1)?,
+                    })
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+            path,
+        )?,
+    })
+}
+
+fn integer<T: TryFrom<i128>>(value: &Value) -> Option<T> {
+    integer_text(value)?
+        .parse::<i128>()
+        .ok()
+        .and_then(|number| T::try_from(number).ok())
+}
+
+/// Integers may be JSON numbers or, to avoid precision loss, strings
+fn integer_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Number(number) if number.is_i64() || number.is_u64() => Some(number.to_string()),
+        Value::String(text) => Some(text.trim().to_string()),
+        _ => None,
+    }
+}
+
+/// Bytes are hex strings (optionally 0x-prefixed) or arrays of numbers
+fn bytes(value: &Value) -> Option<ScBytes> {
+    let bytes = match value {
+        Value::String(text) => hex::decode(text.trim().trim_start_matches("0x")).ok()?,
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+            .collect::<Option<Vec<_>>>()?,
+        _ => return None,
+    };
+    bytes.try_into().ok().map(ScBytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use stellar_xdr::curr::{
+        AccountId, ContractId, Hash, MuxedEd25519Account, PublicKey, ScSpecFunctionInputV0,
+        ScSpecTypeBytesN, ScSpecTypeMap, ScSpecTypeOption, ScSpecTypeTuple, ScSpecTypeUdt,
+        ScSpecTypeVec, ScSpecUdtEnumCaseV0, ScSpecUdtEnumV0, ScSpecUdtStructFieldV0,
+        ScSpecUdtUnionCaseTupleV0, ScSpecUdtUnionCaseVoidV0, Uint256,
+    };
+
+    fn udt(name: &str) -> ScSpecTypeDef {
+        ScSpecTypeDef::Udt(ScSpecTypeUdt {
+            name: name.try_into().unwrap(),
+        })
+    }
+
+    fn option(value_type: ScSpecTypeDef) -> ScSpecTypeDef {
+        ScSpecTypeDef::Option(Box::new(ScSpecTypeOption {
+            value_type: Box::new(value_type),
+        }))
+    }
+
+    fn vec_of(element_type: ScSpecTypeDef) -> ScSpecTypeDef {
+        ScSpecTypeDef::Vec(Box::new(ScSpecTypeVec {
+            element_type: Box::new(element_type),
+        }))
+    }
+
+    fn map_of(key_type: ScSpecTypeDef, value_type: ScSpecTypeDef) -> ScSpecTypeDef {
+        ScSpecTypeDef::Map(Box::new(ScSpecTypeMap {
+            key_type: Box::new(key_type),
+            value_type: Box::new(value_type),
+        }))
+    }
+
+    fn tuple_of(value_types: Vec<ScSpecTypeDef>) -> ScSpecTypeDef {
+        ScSpecTypeDef::Tuple(Box::new(ScSpecTypeTuple {
+            value_types: value_types.try_into().unwrap(),
+        }))
+    }
+
+    fn struct_entry(name: &str, fields: &[(&str, ScSpecTypeDef)]) -> ScSpecEntry {
+        ScSpecEntry::UdtStructV0(ScSpecUdtStructV0 {
+            doc: Default::default(),
+            lib: Default::default(),
+            name: name.try_into().unwrap(),
+            fields: fields
+                .iter()
+                .map(|(name, type_)| ScSpecUdtStructFieldV0 {
+                    doc: Default::default(),
+                    name: (*name).try_into().unwrap(),
+                    type_: type_.clone(),
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap(),
+        })
+    }
+
+    fn function_entry(name: &str, inputs: &[(&str, ScSpecTypeDef)]) -> ScSpecEntry {
+        ScSpecEntry::FunctionV0(ScSpecFunctionV0 {
+            doc: Default::default(),
+            name: ScSymbol(name.try_into().unwrap()),
+            inputs: inputs
+                .iter()
+                .map(|(name, type_)| ScSpecFunctionInputV0 {
+                    doc: Default::default(),
+                    name: (*name).try_into().unwrap(),
+                    type_: type_.clone(),
+                })
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap(),
+            outputs: Default::default(),
+        })
+    }
+
+    /// A spec with one of each kind of user defined type
+    fn interface() -> ContractInterface {
+        ContractInterface::new(vec![
+            struct_entry(
+                "Point",
+                &[
+                    ("x", ScSpecTypeDef::I32),
+                    ("label", option(ScSpecTypeDef::Symbol)),
+                ],
+            ),
+            struct_entry(
+                "Pair",
+                &[("0", ScSpecTypeDef::U32), ("1", ScSpecTypeDef::U32)],
+            ),
+            struct_entry("Node", &[("next", option(udt("Node")))]),
+            ScSpecEntry::UdtUnionV0(ScSpecUdtUnionV0 {
+                doc: Default::default(),
+                lib: Default::default(),
+                name: "Action".try_into().unwrap(),
+                cases: vec![
+                    ScSpecUdtUnionCaseV0::VoidV0(ScSpecUdtUnionCaseVoidV0 {
+                        doc: Default::default(),
+                        name: "Stop".try_into().unwrap(),
+                    }),
+                    ScSpecUdtUnionCaseV0::TupleV0(ScSpecUdtUnionCaseTupleV0 {
+                        doc: Default::default(),
+                        name: "Move".try_into().unwrap(),
+                        type_: vec![ScSpecTypeDef::I32, ScSpecTypeDef::I32]
+                            .try_into()
+                            .unwrap(),
+                    }),
+                    ScSpecUdtUnionCaseV0::TupleV0(ScSpecUdtUnionCaseTupleV0 {
+                        doc: Default::default(),
+                        name: "Say".try_into().unwrap(),
+                        type_: vec![ScSpecTypeDef::String].try_into().unwrap(),
+                    }),
+                ]
+                .try_into()
+                .unwrap(),
+            }),
+            ScSpecEntry::UdtEnumV0(ScSpecUdtEnumV0 {
+                doc: Default::default(),
+                lib: Default::default(),
+                name: "Color".try_into().unwrap(),
+                cases: [("Red", 1), ("Green", 2)]
+                    .iter()
+                    .map(|(name, value)| ScSpecUdtEnumCaseV0 {
+                        doc: Default::default(),
+                        name: (*name).try_into().unwrap(),
+                        value: *value,
+                    })
+                    .collect::<Vec<_>>()
+                    .try_into()
+                    .unwrap(),
+            }),
+            function_entry(
+                "draw",
+                &[
+                    ("points", vec_of(udt("Point"))),
+                    ("color", udt("Color")),
+                    ("note", option(ScSpecTypeDef::String)),
+                ],
+            ),
+        ])
+    }
+
+    fn encode(type_def: &ScSpecTypeDef, value: Value) -> Encoded {
+        interface().encode(&value, type_def, "arg", 0)
+    }
+
+    /// Encoding then decoding gives back the value, as `expected` if the
+    /// input was in an alternative form
+    fn assert_round_trip(type_def: &ScSpecTypeDef, value: Value, expected: Value) {
+        let interface = interface();
+        let encoded = interface
+            .encode(&value, type_def, "arg", 0)
+            .unwrap_or_else(|e| panic!("{} failed to encode: {}", value, e));
+        assert_eq!(interface.decode(&encoded, type_def), expected);
+    }
+
+    fn assert_error(result: Encoded, path: &str, message: &str) {
+        let error = result.expect_err("encoding should fail");
+        assert_eq!(error.path, path);
+        assert!(
+            error.message.contains(message),
+            "{:?} does not contain {:?}",
+            error.message,
+            message
+        );
+    }
+
+    #[test]
+    fn wide_integers_round_trip_as_strings() {
+        let u128_max = u128::MAX.to_string();
+        let i128_min = i128::MIN.to_string();
+        let u256_max =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        let i256_min =
+            "-57896044618658097711785492504343953926634992332820282019728792003956564819968";
+
+        assert_round_trip(&ScSpecTypeDef::U128, json!(u128_max), json!(u128_max));
+        assert_round_trip(&ScSpecTypeDef::I128, json!(i128_min), json!(i128_min));
+        assert_round_trip(&ScSpecTypeDef::U256, json!(u256_max), json!(u256_max));
+        assert_round_trip(&ScSpecTypeDef::I256, json!(i256_min), json!(i256_min));
+        // Small values may be JSON numbers
+        assert_round_trip(&ScSpecTypeDef::I128, json!(-5), json!("-5"));
+    }
+
+    #[test]
+    fn wide_integers_out_of_range_are_rejected() {
+        assert_error(
+            encode(&ScSpecTypeDef::U128, json!("-1")),
+            "arg",
+            "expected u128",
+        );
+        let too_big = format!("{}0", u128::MAX);
+        assert_error(
+            encode(&ScSpecTypeDef::U128, json!(too_big)),
+            "arg",
+            "expected u128",
+        );
+        assert_error(
+            encode(&ScSpecTypeDef::I256, json!(1.5)),
+            "arg",
+            "expected I256",
+        );
+        assert_error(
+            encode(&ScSpecTypeDef::U32, json!(-1)),
+            "arg",
+            "expected u32",
+        );
+    }
+
+    #[test]
+    fn bytes_n_checks_the_length() {
+        let bytes_4 = ScSpecTypeDef::BytesN(ScSpecTypeBytesN { n: 4 });
+        assert_round_trip(&bytes_4, json!("0xDEADBEEF"), json!("deadbeef"));
+        assert_round_trip(&bytes_4, json!([1, 2, 3, 255]), json!("010203ff"));
+        assert_error(
+            encode(&bytes_4, json!("abcd")),
+            "arg",
+            "expected 4 bytes, got 2",
+        );
+        assert_error(encode(&bytes_4, json!("xyz")), "arg", "expected BytesN<4>");
+        assert_error(
+            encode(&bytes_4, json!([1, 2, 3, 256])),
+            "arg",
+            "expected BytesN<4>",
+        );
+    }
+
+    #[test]
+    fn symbols_are_limited_to_their_charset_and_length() {
+        assert_round_trip(
+            &ScSpecTypeDef::Symbol,
+            json!("hello_World9"),
+            json!("hello_World9"),
+        );
+        assert_error(
+            encode(&ScSpecTypeDef::Symbol, json!("no-dashes")),
+            "arg",
+            "symbols may only contain",
+        );
+        assert_error(
+            encode(&ScSpecTypeDef::Symbol, json!("a".repeat(33))),
+            "arg",
+            "longer than 32 characters",
+        );
+    }
+
+    #[test]
+    fn addresses_accept_accounts_and_contracts() {
+        let account =
+            ScAddress::Account(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256([7; 32]))))
+                .to_string();
+        let contract = ScAddress::Contract(ContractId(Hash([9; 32]))).to_string();
+        let muxed = ScAddress::MuxedAccount(MuxedEd25519Account {
+            id: 42,
+            ed25519: Uint256([7; 32]),
+        })
+        .to_string();
+
+        assert_round_trip(&ScSpecTypeDef::Address, json!(account), json!(account));
+        assert_round_trip(&ScSpecTypeDef::Address, json!(contract), json!(contract));
+        assert_round_trip(&ScSpecTypeDef::MuxedAddress, json!(muxed), json!(muxed));
+        assert_error(
+            encode(&ScSpecTypeDef::Address, json!(muxed)),
+            "arg",
+            "is not an account or contract address",
+        );
+        assert_error(
+            encode(&ScSpecTypeDef::Address, json!("GNOTANADDRESS")),
+            "arg",
+            "expected a G... or C... address",
+        );
+    }
+
+    #[test]
+    fn options_are_null_or_the_value() {
+        let optional = option(ScSpecTypeDef::U32);
+        assert_round_trip(&optional, json!(null), json!(null));
+        assert_round_trip(&optional, json!(3), json!(3));
+        assert_error(encode(&optional, json!("x")), "arg", "expected u32");
+    }
+
+    #[test]
+    fn maps_take_objects_or_pairs() {
+        let by_symbol = map_of(ScSpecTypeDef::Symbol, ScSpecTypeDef::U32);
+        assert_round_trip(&by_symbol, json!({"b": 2, "a": 1}), json!({"a": 1, "b": 2}));
+
+        // Keys that aren't strings are given as pairs, and come back sorted
+        let by_number = map_of(ScSpecTypeDef::U32, ScSpecTypeDef::Bool);
+        assert_round_trip(
+            &by_number,
+            json!([[2, false], [1, true]]),
+            json!([[1, true], [2, false]]),
+        );
+        assert_error(
+            encode(&by_number, json!([[1, true], [2]])),
+            "arg[1]",
+            "expected a [key, value] pair",
+        );
+        assert_error(
+            encode(&by_number, json!([[1, true], [1, false]])),
+            "arg",
+            "duplicate map key",
+        );
+        assert_error(
+            encode(&by_number, json!([[1, "yes"]])),
+            "arg[1]",
+            "expected bool",
+        );
+    }
+
+    #[test]
+    fn tuples_need_every_element() {
+        let pair = tuple_of(vec![ScSpecTypeDef::U32, ScSpecTypeDef::String]);
+        assert_round_trip(&pair, json!([1, "one"]), json!([1, "one"]));
+        assert_error(
+            encode(&pair, json!([1])),
+            "arg",
+            "expected 2 elements, got 1",
+        );
+        assert_error(encode(&pair, json!([1, 2])), "arg[1]", "expected String");
+    }
+
+    #[test]
+    fn structs_round_trip() {
+        assert_round_trip(
+            &udt("Point"),
+            json!({"x": -3, "label": "origin"}),
+            json!({"label": "origin", "x": -3}),
+        );
+        // Omitted optional fields are None
+        assert_round_trip(
+            &udt("Point"),
+            json!({"x": 1}),
+            json!({"label": null, "x": 1}),
+        );
+        assert_round_trip(&udt("Pair"), json!([1, 2]), json!([1, 2]));
+
+        assert_error(
+            encode(&udt("Point"), json!({"x": 1, "y": 2})),
+            "arg.y",
+            "Point has no field y",
+        );
+        assert_error(
+            encode(&udt("Point"), json!({"label": "a"})),
+            "arg.x",
+            "missing field of type i32",
+        );
+        assert_error(
+            encode(&udt("Pair"), json!([1])),
+            "arg",
+            "Pair has 2 fields, got 1",
+        );
+        assert_error(
+            encode(&udt("Missing"), json!(1)),
+            "arg",
+            "does not define type Missing",
+        );
+    }
+
+    #[test]
+    fn unions_round_trip() {
+        assert_round_trip(&udt("Action"), json!("Stop"), json!("Stop"));
+        assert_round_trip(
+            &udt("Action"),
+            json!({"Move": [1, -1]}),
+            json!({"Move": [1, -1]}),
+        );
+        assert_round_trip(&udt("Action"), json!({"Say": "hi"}), json!({"Say": "hi"}));
+
+        assert_error(
+            encode(&udt("Action"), json!("Jump")),
+            "arg",
+            "Action has no variant Jump; expected one of Stop, Move, Say",
+        );
+        assert_error(
+            encode(&udt("Action"), json!({"Stop": 1})),
+            "arg.Stop",
+            "Action::Stop holds no value",
+        );
+        assert_error(
+            encode(&udt("Action"), json!({"Move": [1]})),
+            "arg.Move",
+            "Action::Move holds 2 values",
+        );
+        assert_error(
+            encode(&udt("Action"), json!({"Move": [1, "x"]})),
+            "arg.Move[1]",
+            "expected i32",
+        );
+        assert_error(
+            encode(&udt("Action"), json!("Say")),
+            "arg.Say",
+            "pass {\"Say\": value}",
+        );
+    }
+
+    #[test]
+    fn enums_take_names_or_values() {
+        assert_round_trip(&udt("Color"), json!("Green"), json!("Green"));
+        assert_round_trip(&udt("Color"), json!(1), json!("Red"));
+        assert_error(
+            encode(&udt("Color"), json!("Blue")),
+            "arg",
+            "expected a Color variant (Red, Green)",
+        );
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let mut node = json!({});
+        for _ in 0..MAX_DEPTH {
+            node = json!({ "next": node });
+        }
+        let error = encode(&udt("Node"), node).expect_err("encoding should fail");
+        assert_eq!(error.message, "value is nested too deeply");
+        assert!(error.path.starts_with("arg.next.next."));
+
+        let mut nested = json!(1);
+        for _ in 0..=MAX_DEPTH {
+            nested = json!([nested]);
+        }
+        assert_error(
+            encode(&ScSpecTypeDef::Val, nested),
+            &format!("arg{}", "[0]".repeat(MAX_DEPTH + 1)),
+            "nested too deeply",
+        );
+    }
+
+    #[test]
+    fn argument_errors_carry_their_path() {
+        let interface = interface();
+        let draw = interface.function("draw").unwrap();
+
+        let encoded = interface
+            .encode_args(draw, &json!({"points": [{"x": 1}], "color": "Red"}))
+            .unwrap();
+        assert_eq!(encoded.len(), 3);
+        assert_eq!(encoded[2], ScVal::Void);
+
+        let errors = interface
+            .encode_args(draw, &json!({"points": [{"x": 1}, {"x": "two"}]}))
+            .unwrap_err();
+        let errors: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|error| (error.path.as_str(), error.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("points[1].x", "expected i32, got \"two\""),
+                ("color", "missing argument of type Color"),
+            ]
+        );
+
+        assert_error(
+            interface
+                .encode_args(draw, &json!([[], "Red"]))
+                .map(|_| ScVal::Void)
+                .map_err(|mut errors| errors.remove(0)),
+            "",
+            "draw takes 3 arguments, got 2",
+        );
+        assert_error(
+            interface
+                .encode_args(draw, &json!({"colour": "Red"}))
+                .map(|_| ScVal::Void)
+                .map_err(|mut errors| errors.remove(0)),
+            "colour",
+            "draw has no argument colour",
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::process::Command;
+use tokio::sync::RwLock;
+
+use crate::services::target_cache;
+use crate::storage::{self, copy_dir_all};
+
+// Used when SOROBAN_SDK_VERSIONS is not set; matches the base project
+const FALLBACK_SDK_VERSION: &str = "22.0.0";
+const DEFAULT_CACHE_DIR: &str = "sdk_cache";
+
+/// A supported soroban-sdk version and where its dependency cache lives
+#[derive(Debug, Clone)]
+pub struct SdkVersion {
+    pub version: String,
+    pub cache_dir: PathBuf, // e.g. sdk_cache/22.0.0
+    pub target_lock: Arc<RwLock<()>>, // Written while the shared target cache is built
+}
+
+impl SdkVersion {
+    /// Cargo home holding the registry index and crate downloads for this version
+    pub fn cargo_home(&self) -> PathBuf {
+        self.cache_dir.join("cargo-home")
+    }
+
+    /// Workspace resolved against this version, whose lockfile seeds new projects
+    pub fn workspace_dir(&self) -> PathBuf {
+        self.cache_dir.join("workspace")
+    }
+
+    pub fn lockfile(&self) -> PathBuf {
+        self.workspace_dir().join("Cargo.lock")
+    }
+
+    /// Shared cargo target directory with prebuilt dependencies for this version
+    pub fn target_dir(&self) -> PathBuf {
+        self.cache_dir.join("target")
+    }
+
+    /// Point a cargo command at this version's dependency cache
+    pub fn configure(&self, command: &mut Command) {
+        command.env("CARGO_HOME", self.cargo_home());
+    }
+
+    /// Rewrite the workspace's soroban-sdk dependency to this exact version.
+    ///
+    /// When the version changes (or the project has no lockfile yet) the
+    /// project's lockfile is replaced with the pre-resolved one so the build
+    /// only needs crates that are already in the cache.
+    pub fn apply_to_workspace(&self, project_path: &Path) -> Result<()> {
+        let manifest_path = project_path.join("Cargo.toml");
+        let mut manifest = fs::read_to_string(&manifest_path)
+            .context("Failed to read workspace manifest")?
+            .parse::<toml_edit::DocumentMut>()
+            .context("Failed to parse workspace manifest")?;
+
+        let requirement = format!("={}", self.version);
+        let current = manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies"))
+            .and_then(|dependencies| dependencies.get("soroban-sdk"))
+            .and_then(|dependency| dependency.as_str())
+            .map(str::to_string);
+
+        let lockfile = project_path.join("Cargo.lock");
+        if current.as_deref() == Some(requirement.as_str()) && lockfile.exists() {
+            return Ok(());
+        }
+
+        info!(
+            "Switching project {:?} to soroban-sdk {} (was {:?})",
+            project_path, self.version, current
+        );
+
+        manifest["workspace"]["dependencies"]["soroban-sdk"] = toml_edit::value(&requirement);
+        fs::write(&manifest_path, manifest.to_string())
+            .context("Failed to write workspace manifest")?;
+
+        let cached_lockfile = self.lockfile();
+        if cached_lockfile == lockfile {
+            // This is the cache workspace itself
+        } else if cached_lockfile.exists() {
+            fs::copy(&cached_lockfile, &lockfile).context("Failed to copy cached lockfile")?;
+        } else if lockfile.exists() {
+            // Resolved against another version; let cargo resolve again
+            fs::remove_file(&lockfile).context("Failed to remove stale lockfile")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Server-side allowlist of soroban-sdk versions projects may build against
+pub struct SdkVersions {
+    versions: Vec<SdkVersion>,
+    default: String,
+}
+
+impl SdkVersions {
+    /// Read the allowlist from SOROBAN_SDK_VERSIONS (comma separated) and the
+    /// default from SOROBAN_SDK_DEFAULT_VERSION, falling back to the first entry
+    pub fn from_env() -> Self {
+        // Absolute, since cargo resolves a relative CARGO_HOME against its own
+        // working directory (the project)
+        let cache_root = env::current_dir().unwrap_or_default().join(
+            env::var("SDK_CACHE_DIR").unwrap_or_else(|_| DEFAULT_CACHE_DIR.to_string()),
+        );
+
+        let mut versions: Vec<String> = env::var("SOROBAN_SDK_VERSIONS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|version| version.trim().to_string())
+            .filter(|version| !version.is_empty())
+            .collect();
+        versions.dedup();
+        if versions.is_empty() {
+            versions.push(FALLBACK_SDK_VERSION.to_string());
+        }
+
+        let default = match env::var("SOROBAN_SDK_DEFAULT_VERSION") {
+            Ok(version) if versions.contains(&version) => version,
+            Ok(version) => {
+                warn!(
+                    "SOROBAN_SDK_DEFAULT_VERSION {} is not in SOROBAN_SDK_VERSIONS, using {}",
+                    version, versions[0]
+                );
+                versions[0].clone()
+            }
+            Err(_) => versions[0].clone(),
+        };
+
+        info!(
+            "Supported soroban-sdk versions: {} (default {})",
+            versions.join(", "),
+            default
+        );
+
+        Self {
+            versions: versions
+                .into_iter()
+                .map(|version| SdkVersion {
+                    cache_dir: cache_root.join(&version),
+                    version,
+                    target_lock: Arc::new(RwLock::new(())),
+                })
+                .collect(),
+            default,
+        }
+    }
+
+    /// Look up the requested version, or the default when none was requested
+    pub fn resolve(&self, requested: Option<&str>) -> Result<SdkVersion> {
+        let version = requested.unwrap_or(&self.default);
+        self.versions
+            .iter()
+            .find(|sdk| sdk.version == version)
+            .cloned()
+            .with_context(|| {
+                format!(
+                    "soroban-sdk {} is not supported; available versions: {}",
+                    version,
+                    self.list().join(", ")
+                )
+            })
+    }
+
+    /// The version a project was last built against, as pinned in its
+    /// workspace manifest by `SdkVersion::apply_to_workspace`
+    pub fn for_project(&self, project_path: &Path) -> Result<SdkVersion> {
+        let manifest = fs::read_to_string(project_path.join("Cargo.toml"))
+            .context("Failed to read workspace manifest")?
+            .parse::<toml_edit::DocumentMut>()
+            .context("Failed to parse workspace manifest")?;
+        let pinned = manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("dependencies"))
+            .and_then(|dependencies| dependencies.get("soroban-sdk"))
+            .and_then(|dependency| dependency.as_str())
+            .and_then(|requirement| requirement.strip_prefix('='));
+        self.resolve(pinned)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.versions
+            .iter()
+            .map(|sdk| sdk.version.clone())
+            .collect()
+    }
+
+    pub fn default_version(&self) -> &str {
+        &self.default
+    }
+
+    /// Resolve, download and build dependencies for every supported version
+    /// so the first build against each one does not pay for it
+    pub async fn prewarm(&self) {
+        for sdk in &self.versions {
+            match prewarm_version(sdk).await {
+                Ok(()) => info!("Dependency cache ready for soroban-sdk {}", sdk.version),
+                Err(e) => error!(
+                    "Failed to pre-warm dependency cache for soroban-sdk {}: {}",
+                    sdk.version, e
+                ),
+            }
+        }
+    }
+}
+
+async fn prewarm_version(sdk: &SdkVersion) -> Result<()> {
+    let workspace = sdk.workspace_dir();
+    if !workspace.exists() {
+        copy_dir_all(&storage::base_project_dir(), &workspace)
+            .context("Failed to copy base project template")?;
+    }
+    sdk.apply_to_workspace(&workspace)?;
+
+    if !sdk.lockfile().exists() {
+        run_cargo(sdk, &workspace, "generate-lockfile").await?;
+    }
+    run_cargo(sdk, &workspace, "fetch").await?;
+
+    target_cache::warm(sdk).await
+}
+
+async fn run_cargo(sdk: &SdkVersion, workspace: &Path, subcommand: &str) -> Result<()> {
+    let mut command = Command::new("cargo");
+    command.arg(subcommand).current_dir(workspace);
+    sdk.configure(&mut command);
+
+    let output = command
+        .output()
+        .await
+        .with_context(|| format!("Failed to execute cargo {}", subcommand))?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "cargo {} failed: {}",
+            subcommand,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
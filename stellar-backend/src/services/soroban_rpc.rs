@@ -0,0 +1,992 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+use stellar_xdr::curr::{
+    AccountId, ContractDataDurability, ContractEvent, ContractEventBody, ContractEventType,
+    ContractEventV0, ContractExecutable, DiagnosticEvent, ExtensionPoint, Hash, LedgerEntryData,
+    LedgerKey, LedgerKeyAccount, LedgerKeyContractCode, LedgerKeyContractData, Limits, ReadXdr,
+    ScAddress, ScVal, SorobanAuthorizationEntry, SorobanTransactionData, SorobanTransactionMetaExt,
+    TransactionEnvelope, TransactionMeta, TransactionResult, WriteXdr,
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// How long to wait for a submitted transaction to be included in a ledger
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Client for the Soroban JSON-RPC API
+#[derive(Clone)]
+pub struct RpcClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct RpcRequest<'a, P> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: P,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<R> {
+    result: Option<R>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<serde_json::Value>,
+}
+
+/// Outcome of getLatestLedger
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatestLedger {
+    pub protocol_version: u32,
+    pub sequence: u32,
+}
+
+/// A ledger entry as returned by getLedgerEntries
+#[derive(Debug)]
+pub struct LedgerEntry {
+    pub key: LedgerKey,
+    pub data: LedgerEntryData,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawLedgerEntries {
+    entries: Option<Vec<RawLedgerEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawLedgerEntry {
+    key: String,
+    xdr: String,
+}
+
+/// Outcome of simulateTransaction
+#[derive(Debug)]
+pub struct Simulation {
+    pub min_resource_fee: i64,
+    pub transaction_data: Option<SorobanTransactionData>,
+    pub auth: Vec<SorobanAuthorizationEntry>,
+    pub return_value: Option<ScVal>,
+    pub events: Vec<DiagnosticEvent>,
+    pub error: Option<String>, // Set when the host function failed
+    pub restore_preamble: Option<RestorePreamble>,
+    pub cost: Option<SimulationCost>, // Not reported by every RPC version
+}
+
+/// CPU and memory the host function used in simulation
+#[derive(Debug)]
+pub struct SimulationCost {
+    pub cpu_instructions: u64,
+    pub memory_bytes: u64,
+}
+
+/// Archived entries the transaction needs restored before it can run
+#[derive(Debug)]
+pub struct RestorePreamble {
+    pub min_resource_fee: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSimulation {
+    min_resource_fee: Option<String>,
+    transaction_data: Option<String>,
+    results: Option<Vec<RawHostFunctionResult>>,
+    events: Option<Vec<String>>,
+    error: Option<String>,
+    restore_preamble: Option<RawRestorePreamble>,
+    cost: Option<RawCost>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawCost {
+    cpu_insns: String,
+    mem_bytes: String,
+}
+
+#[derive(Deserialize)]
+struct RawHostFunctionResult {
+    auth: Option<Vec<String>>,
+    xdr: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawRestorePreamble {
+    min_resource_fee: String,
+}
+
+/// Outcome of sendTransaction
+#[derive(Debug)]
+pub struct SendResult {
+    pub status: String, // PENDING, DUPLICATE, TRY_AGAIN_LATER or ERROR
+    pub hash: String,
+    pub error_result: Option<TransactionResult>,
+    pub diagnostic_events: Vec<DiagnosticEvent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSendResult {
+    status: String,
+    hash: String,
+    error_result_xdr: Option<String>,
+    diagnostic_events_xdr: Option<Vec<String>>,
+}
+
+/// Outcome of getTransaction
+#[derive(Debug)]
+pub struct TransactionInfo {
+    pub status: String, // SUCCESS, FAILED or NOT_FOUND
+    pub ledger: Option<u32>,
+    pub result: Option<TransactionResult>,
+    pub result_meta: Option<TransactionMeta>,
+    pub diagnostic_events: Vec<DiagnosticEvent>,
+}
+
+impl TransactionInfo {
+    /// Value returned by the transaction's host function, if it ran
+    pub fn return_value(&self) -> Option<ScVal> {
+        match self.result_meta.as_ref()? {
+            TransactionMeta::V3(meta) => meta
+                .soroban_meta
+                .as_ref()
+                .map(|soroban| soroban.return_value.clone()),
+            TransactionMeta::V4(meta) => meta.soroban_meta.as_ref()?.return_value.clone(),
+            _ => None,
+        }
+    }
+
+    /// Events the transaction recorded. With diagnostics enabled on the RPC
+    /// node these include the host's diagnostic events; otherwise only
+    /// contract and system events are available.
+    pub fn events(&self) -> Vec<DiagnosticEvent> {
+        if !self.diagnostic_events.is_empty() {
+            return self.diagnostic_events.clone();
+        }
+        let (diagnostic, contract): (Vec<DiagnosticEvent>, Vec<ContractEvent>) =
+            match &self.result_meta {
+                Some(TransactionMeta::V3(meta)) => match &meta.soroban_meta {
+                    Some(soroban) => (soroban.diagnostic_events.to_vec(), soroban.events.to_vec()),
+                    None => (Vec::new(), Vec::new()),
+                },
+                Some(TransactionMeta::V4(meta)) => (
+                    meta.diagnostic_events.to_vec(),
+                    meta.operations
+                        .iter()
+                        .flat_map(|operation| operation.events.iter().cloned())
+                        .collect(),
+                ),
+                _ => (Vec::new(), Vec::new()),
+            };
+        if !diagnostic.is_empty() {
+            return diagnostic;
+        }
+        // Only events of a successful transaction make it into the meta
+        contract
+            .into_iter()
+            .map(|event| DiagnosticEvent {
+                in_successful_contract_call: true,
+                event,
+            })
+            .collect()
+    }
+
+    /// Non-refundable, refundable and rent parts of the resource fee charged
+    pub fn resource_fees_charged(&self) -> Option<(i64, i64, i64)> {
+        let ext = match self.result_meta.as_ref()? {
+            TransactionMeta::V3(meta) => &meta.soroban_meta.as_ref()?.ext,
+            TransactionMeta::V4(meta) => &meta.soroban_meta.as_ref()?.ext,
+            _ => return None,
+        };
+        match ext {
+            SorobanTransactionMetaExt::V1(fees) => Some((
+                fees.total_non_refundable_resource_fee_charged,
+                fees.total_refundable_resource_fee_charged,
+                fees.rent_fee_charged,
+            )),
+            SorobanTransactionMetaExt::V0 => None,
+        }
+    }
+
+    pub fn fee_charged(&self) -> Option<i64> {
+        self.result.as_ref().map(|result| result.fee_charged)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTransactionInfo {
+    status: String,
+    ledger: Option<u32>,
+    result_xdr: Option<String>,
+    result_meta_xdr: Option<String>,
+    diagnostic_events_xdr: Option<Vec<String>>,
+}
+
+fn decode<T: ReadXdr>(base64: &str) -> Result<T> {
+    T::from_xdr_base64(base64, Limits::none())
+        .with_context(|| format!("Invalid {} XDR in RPC response", std::any::type_name::<T>()))
+}
+
+fn decode_all<T: ReadXdr>(values: Option<Vec<String>>) -> Result<Vec<T>> {
+    values
+        .unwrap_or_default()
+        .iter()
+        .map(|value| decode(value))
+        .collect()
+}
+
+fn parse_fee(fee: &str) -> Result<i64> {
+    fee.parse()
+        .with_context(|| format!("Invalid fee {:?} in RPC response", fee))
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        Ok(Self {
+            url: url.to_string(),
+            http,
+        })
+    }
+
+    async fn call<P: Serialize, R: DeserializeOwned>(&self, method: &str, params: P) -> Result<R> {
+        debug!("Soroban RPC {} -> {}", method, self.url);
+
+        let response: RpcResponse<R> = self
+            .http
+            .post(&self.url)
+            .json(&RpcRequest {
+                jsonrpc: "2.0",
+                id: 1,
+                method,
+                params,
+            })
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach Soroban RPC at {}", self.url))?
+            .error_for_status()
+            .with_context(|| format!("Soroban RPC {} failed", method))?
+            .json()
+            .await
+            .with_context(|| format!("Invalid response to Soroban RPC {}", method))?;
+
+        if let Some(error) = response.error {
+            return Err(anyhow::anyhow!(
+                "Soroban RPC {} failed ({}): {}{}",
+                method,
+                error.code,
+                error.message,
+                error
+                    .data
+                    .map(|data| format!(" {}", data))
+                    .unwrap_or_default()
+            ));
+        }
+        response
+            .result
+            .with_context(|| format!("Soroban RPC {} returned no result", method))
+    }
+
+    pub async fn get_latest_ledger(&self) -> Result<LatestLedger> {
+        self.call("getLatestLedger", serde_json::json!({})).await
+    }
+
+    /// Fetch ledger entries; keys that don't exist are left out
+    pub async fn get_ledger_entries(&self, keys: &[LedgerKey]) -> Result<Vec<LedgerEntry>> {
+        let keys = keys
+            .iter()
+            .map(|key| key.to_xdr_base64(Limits::none()))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to encode ledger key")?;
+
+        let raw: RawLedgerEntries = self
+            .call("getLedgerEntries", serde_json::json!({ "keys": keys }))
+            .await?;
+
+        raw.entries
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| {
+                Ok(LedgerEntry {
+                    key: decode(&entry.key)?,
+                    data: decode(&entry.xdr)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_ledger_entry(&self, key: LedgerKey) -> Result<Option<LedgerEntryData>> {
+        Ok(self
+            .get_ledger_entries(std::slice::from_ref(&key))
+            .await?
+            .into_iter()
+            .find(|entry| entry.key == key)
+            .map(|entry| entry.data))
+    }
+
+    pub async fn simulate_transaction(&self, envelope: &TransactionEnvelope) -> Result<Simulation> {
+        let transaction = envelope
+            .to_xdr_base64(Limits::none())
+            .context("Failed to encode transaction")?;
+        let raw: RawSimulation = self
+            .call(
+                "simulateTransaction",
+                serde_json::json!({ "transaction": transaction }),
+            )
+            .await?;
+
+        let result = raw.results.and_then(|results| results.into_iter().next());
+        let (auth, return_value) = match result {
+            Some(result) => (decode_all(result.auth)?, Some(decode(&result.xdr)?)),
+            None => (Vec::new(), None),
+        };
+
+        Ok(Simulation {
+            min_resource_fee: raw
+                .min_resource_fee
+                .as_deref()
+                .map(parse_fee)
+                .transpose()?
+                .unwrap_or_default(),
+            transaction_data: raw
+                .transaction_data
+                .as_deref()
+                .filter(|data| !data.is_empty())
+                .map(decode)
+                .transpose()?,
+            auth,
+            return_value,
+            events: decode_all(raw.events)?,
+            error: raw.error,
+            restore_preamble: raw
+                .restore_preamble
+                .map(|preamble| -> Result<RestorePreamble> {
+                    Ok(RestorePreamble {
+                        min_resource_fee: parse_fee(&preamble.min_resource_fee)?,
+                    })
+                })
+                .transpose()?,
+            cost: raw.cost.and_then(|cost| {
+                Some(SimulationCost {
+                    cpu_instructions: cost.cpu_insns.parse().ok()?,
+                    memory_bytes: cost.mem_bytes.parse().ok()?,
+                })
+            }),
+        })
+    }
+
+    pub async fn send_transaction(&self, envelope: &TransactionEnvelope) -> Result<SendResult> {
+        let transaction = envelope
+            .to_xdr_base64(Limits::none())
+            .context("Failed to encode transaction")?;
+        let raw: RawSendResult = self
+            .call(
+                "sendTransaction",
+                serde_json::json!({ "transaction": transaction }),
+            )
+            .await?;
+
+        Ok(SendResult {
+            status: raw.status,
+            hash: raw.hash,
+            error_result: raw.error_result_xdr.as_deref().map(decode).transpose()?,
+            diagnostic_events: decode_all(raw.diagnostic_events_xdr)?,
+        })
+    }
+
+    pub async fn get_transaction(&self, hash: &str) -> Result<TransactionInfo> {
+        let raw: RawTransactionInfo = self
+            .call("getTransaction", serde_json::json!({ "hash": hash }))
+            .await?;
+
+        Ok(TransactionInfo {
+            status: raw.status,
+            ledger: raw.ledger,
+            result: raw.result_xdr.as_deref().map(decode).transpose()?,
+            result_meta: raw.result_meta_xdr.as_deref().map(decode).transpose()?,
+            diagnostic_events: decode_all(raw.diagnostic_events_xdr)?,
+        })
+    }
+
+    /// Poll getTransaction until the transaction is in a ledger or the
+    /// confirmation timeout passes
+    pub async fn wait_for_transaction(&self, hash: &str) -> Result<TransactionInfo> {
+        let deadline = tokio::time::Instant::now() + CONFIRMATION_TIMEOUT;
+        loop {
+            let info = self.get_transaction(hash).await?;
+            if info.status != "NOT_FOUND" {
+                info!("Transaction {} finished with status {}", hash, info.status);
+                return Ok(info);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow::anyhow!(
+                    "Transaction {} was not included in a ledger within {}s",
+                    hash,
+                    CONFIRMATION_TIMEOUT.as_secs()
+                ));
+            }
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Current sequence number of an account
+    pub async fn account_sequence(&self, account_id: &AccountId) -> Result<i64> {
+        let key = LedgerKey::Account(LedgerKeyAccount {
+            account_id: account_id.clone(),
+        });
+        match self.get_ledger_entry(key).await? {
+            Some(LedgerEntryData::Account(account)) => Ok(account.seq_num.0),
+            _ => Err(anyhow::anyhow!(
+                "Account {} not found; fund it before sending transactions",
+                account_id
+            )),
+        }
+    }
+
+    /// Wasm code a deployed contract runs
+    pub async fn contract_wasm(&self, contract: &ScAddress) -> Result<Vec<u8>> {
+        let instance_key = LedgerKey::ContractData(LedgerKeyContractData {
+            contract: contract.clone(),
+            key: ScVal::LedgerKeyContractInstance,
+            durability: ContractDataDurability::Persistent,
+        });
+        let wasm_hash = match self.get_ledger_entry(instance_key).await? {
+            Some(LedgerEntryData::ContractData(data)) => match data.val {
+                ScVal::ContractInstance(instance) => match instance.executable {
+                    ContractExecutable::Wasm(hash) => hash,
+                    ContractExecutable::StellarAsset => {
+                        return Err(anyhow::anyhow!(
+                            "Contract {} is a Stellar Asset Contract and has no wasm",
+                            contract
+                        ))
+                    }
+                },
+                _ => return Err(anyhow::anyhow!("Contract {} has no instance", contract)),
+            },
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Contract {} not found on this network (or archived)",
+                    contract
+                ))
+            }
+        };
+
+        self.wasm_code(&wasm_hash).await
+    }
+
+    /// Whether wasm with this hash has been uploaded
+    pub async fn wasm_installed(&self, hash: &Hash) -> Result<bool> {
+        let key = LedgerKey::ContractCode(LedgerKeyContractCode { hash: hash.clone() });
+        Ok(self.get_ledger_entry(key).await?.is_some())
+    }
+
+    /// Uploaded wasm code by hash
+    pub async fn wasm_code(&self, hash: &Hash) -> Result<Vec<u8>> {
+        let key = LedgerKey::ContractCode(LedgerKeyContractCode { hash: hash.clone() });
+        match self.get_ledger_entry(key).await? {
+            Some(LedgerEntryData::ContractCode(code)) => Ok(code.code.to_vec()),
+            _ => Err(anyhow::anyhow!(
+                "Wasm {} not found on this network (or archived)",
+                hex::encode(hash.0)
+            )),
+        }
+    }
+}
+
+// getEvents, which no route uses yet
+#[allow(dead_code)]
+pub mod ledger_events {
+    use super::*;
+
+    /// Kinds of events getEvents filters on
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum EventType {
+        Contract,
+        System,
+        Diagnostic,
+    }
+
+    impl FromStr for EventType {
+        type Err = anyhow::Error;
+
+        fn from_str(value: &str) -> Result<Self> {
+            match value {
+                "contract" => Ok(Self::Contract),
+                "system" => Ok(Self::System),
+                "diagnostic" => Ok(Self::Diagnostic),
+                other => Err(anyhow::anyhow!(
+                    "Unknown event type {:?}; expected contract, system or diagnostic",
+                    other
+                )),
+            }
+        }
+    }
+
+    /// One position of a topic pattern
+    #[derive(Debug, Clone)]
+    pub enum TopicSegment {
+        Any,          // Exactly one topic, of any value
+        AnyRemaining, // Any number of further topics; only valid last
+        Value(ScVal),
+    }
+
+    /// Events matching any one filter are returned; within a filter every
+    /// field that is set must match
+    #[derive(Debug, Clone, Default)]
+    pub struct EventFilter {
+        pub event_type: Option<EventType>,
+        pub contract_ids: Vec<ScAddress>,
+        pub topics: Vec<Vec<TopicSegment>>, // Matches events fitting any of these patterns
+    }
+
+    impl EventFilter {
+        fn to_json(&self) -> Result<serde_json::Value> {
+            let mut filter = serde_json::Map::new();
+            if let Some(event_type) = self.event_type {
+                filter.insert("type".to_string(), serde_json::to_value(event_type)?);
+            }
+            if !self.contract_ids.is_empty() {
+                let ids: Vec<String> = self.contract_ids.iter().map(ToString::to_string).collect();
+                filter.insert("contractIds".to_string(), ids.into());
+            }
+            if !self.topics.is_empty() {
+                let topics = self
+                    .topics
+                    .iter()
+                    .map(|pattern| {
+                        pattern
+                            .iter()
+                            .map(|segment| match segment {
+                                TopicSegment::Any => Ok("*".to_string()),
+                                TopicSegment::AnyRemaining => Ok("**".to_string()),
+                                TopicSegment::Value(value) => value
+                                    .to_xdr_base64(Limits::none())
+                                    .context("Failed to encode event topic"),
+                            })
+                            .collect::<Result<Vec<_>>>()
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                filter.insert("topics".to_string(), serde_json::to_value(topics)?);
+            }
+            Ok(filter.into())
+        }
+    }
+
+    /// Where a page of events starts
+    #[derive(Debug, Clone)]
+    pub enum EventStart {
+        Ledger(u32),
+        Cursor(String), // The cursor of the previous page
+    }
+
+    /// An event as stored in a ledger
+    #[derive(Debug)]
+    pub struct LedgerEvent {
+        pub id: String,
+        pub ledger: u32,
+        pub transaction_hash: String,
+        pub event: DiagnosticEvent,
+    }
+
+    /// Outcome of getEvents
+    #[derive(Debug)]
+    pub struct EventPage {
+        pub events: Vec<LedgerEvent>,
+        pub cursor: Option<String>, // Start of the next page
+        pub latest_ledger: u32,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RawEventPage {
+        events: Option<Vec<RawEvent>>,
+        cursor: Option<String>, // Older RPC versions only give each event's paging token
+        latest_ledger: u32,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RawEvent {
+        #[serde(rename = "type")]
+        event_type: String,
+        ledger: u32,
+        contract_id: Option<String>,
+        id: String,
+        paging_token: Option<String>,
+        topic: Vec<String>,
+        value: String,
+        in_successful_contract_call: bool,
+        tx_hash: String,
+    }
+
+    impl RawEvent {
+        fn decode(self) -> Result<LedgerEvent> {
+            let contract_id = match self.contract_id.as_deref().filter(|id| !id.is_empty()) {
+                Some(id) => match id.parse::<ScAddress>() {
+                    Ok(ScAddress::Contract(id)) => Some(id),
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "Invalid contract id {:?} in RPC response",
+                            id
+                        ))
+                    }
+                },
+                None => None,
+            };
+            let type_ = match self.event_type.parse()? {
+                EventType::Contract => ContractEventType::Contract,
+                EventType::System => ContractEventType::System,
+                EventType::Diagnostic => ContractEventType::Diagnostic,
+            };
+            let topics: Vec<ScVal> = decode_all(Some(self.topic))?;
+
+            Ok(LedgerEvent {
+                id: self.id,
+                ledger: self.ledger,
+                transaction_hash: self.tx_hash,
+                event: DiagnosticEvent {
+                    in_successful_contract_call: self.in_successful_contract_call,
+                    event: ContractEvent {
+                        ext: ExtensionPoint::V0,
+                        contract_id,
+                        type_,
+                        body: ContractEventBody::V0(ContractEventV0 {
+                            topics: topics
+                                .try_into()
+                                .context("Too many event topics in RPC response")?,
+                            data: decode(&self.value)?,
+                        }),
+                    },
+                },
+            })
+        }
+    }
+
+    impl RpcClient {
+        /// A page of at most `limit` events matching any of the filters, from
+        /// a ledger or where a previous page ended
+        pub async fn get_events(
+            &self,
+            start: &EventStart,
+            filters: &[EventFilter],
+            limit: u32,
+        ) -> Result<EventPage> {
+            let mut params = serde_json::json!({
+                "filters": filters
+                    .iter()
+                    .map(EventFilter::to_json)
+                    .collect::<Result<Vec<_>>>()?,
+                "pagination": { "limit": limit },
+            });
+            match start {
+                EventStart::Ledger(ledger) => params["startLedger"] = (*ledger).into(),
+                EventStart::Cursor(cursor) => {
+                    params["pagination"]["cursor"] = cursor.as_str().into()
+                }
+            }
+            let raw: RawEventPage = self.call("getEvents", params).await?;
+
+            let raw_events = raw.events.unwrap_or_default();
+            let cursor = raw.cursor.or_else(|| {
+                raw_events
+                    .last()
+                    .and_then(|event| event.paging_token.clone())
+            });
+            Ok(EventPage {
+                events: raw_events
+                    .into_iter()
+                    .map(RawEvent::decode)
+                    .collect::<Result<_>>()?,
+                cursor,
+                latest_ledger: raw.latest_ledger,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ledger_events::{EventFilter, EventStart, TopicSegment};
+    use super::*;
+    use crate::services::transaction;
+    use std::sync::{Arc, Mutex};
+    use stellar_xdr::curr::{
+        HostFunction, InvokeContractArgs, OperationResult, PublicKey, ScSymbol, SorobanCredentials,
+        TransactionResultResult, Uint256,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const CONTRACT: &str = "CAAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQC526";
+
+    /// Serve the responses in order, one per connection, recording the
+    /// JSON-RPC requests made
+    async fn replay(
+        responses: Vec<&'static str>,
+    ) -> (RpcClient, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = Vec::new();
+                let body = loop {
+                    let mut chunk = [0; 4096];
+                    let read = stream.read(&mut chunk).await.unwrap();
+                    buffer.extend_from_slice(&chunk[..read]);
+                    let text = String::from_utf8_lossy(&buffer);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length: usize = text[..end]
+                            .lines()
+                            .find_map(|line| {
+                                let (name, value) = line.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if buffer.len() >= end + 4 + length {
+                            break buffer[end + 4..end + 4 + length].to_vec();
+                        }
+                    }
+                };
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_slice(&body).unwrap());
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+
+        (RpcClient::new(&url).unwrap(), requests)
+    }
+
+    fn envelope() -> TransactionEnvelope {
+        let source = AccountId(PublicKey::PublicKeyTypeEd25519(Uint256([2; 32])));
+        let host_function = HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address: CONTRACT.parse().unwrap(),
+            function_name: "hello".try_into().unwrap(),
+            args: Default::default(),
+        });
+        let tx = transaction::host_function_transaction(&source, 1, host_function).unwrap();
+        transaction::unsigned_envelope(&tx)
+    }
+
+    #[tokio::test]
+    async fn simulation_errors_come_with_their_events() {
+        let (rpc, requests) = replay(vec![include_str!("testdata/rpc_simulate_error.json")]).await;
+        let simulation = rpc.simulate_transaction(&envelope()).await.unwrap();
+
+        assert!(simulation
+            .error
+            .as_deref()
+            .unwrap()
+            .starts_with("HostError: Error(Contract, #1)"));
+        assert_eq!(simulation.events.len(), 1);
+        assert!(!simulation.events[0].in_successful_contract_call);
+        assert_eq!(simulation.min_resource_fee, 0);
+        assert!(simulation.transaction_data.is_none());
+        assert!(simulation.return_value.is_none());
+        assert!(simulation.restore_preamble.is_none());
+
+        let request = &requests.lock().unwrap()[0];
+        assert_eq!(request["method"], "simulateTransaction");
+        assert_eq!(
+            request["params"]["transaction"],
+            envelope().to_xdr_base64(Limits::none()).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn simulations_report_archived_entries_to_restore() {
+        let (rpc, _) = replay(vec![include_str!("testdata/rpc_simulate_restore.json")]).await;
+        let simulation = rpc.simulate_transaction(&envelope()).await.unwrap();
+
+        assert_eq!(simulation.restore_preamble.unwrap().min_resource_fee, 12345);
+        assert_eq!(simulation.min_resource_fee, 90000);
+        assert_eq!(simulation.return_value, Some(ScVal::U32(42)));
+        assert!(simulation.error.is_none());
+        assert!(simulation.cost.is_none());
+    }
+
+    #[tokio::test]
+    async fn simulations_decode_results_and_cost() {
+        let (rpc, _) = replay(vec![include_str!("testdata/rpc_simulate_success.json")]).await;
+        let simulation = rpc.simulate_transaction(&envelope()).await.unwrap();
+
+        let cost = simulation.cost.unwrap();
+        assert_eq!(cost.cpu_instructions, 1_234_567);
+        assert_eq!(cost.memory_bytes, 89_012);
+        assert_eq!(simulation.min_resource_fee, 90000);
+        let data = simulation.transaction_data.unwrap();
+        assert_eq!(data.resources.instructions, 1_500_000);
+        assert_eq!(data.resource_fee, 90000);
+        assert_eq!(simulation.auth.len(), 1);
+        assert_eq!(
+            simulation.auth[0].credentials,
+            SorobanCredentials::SourceAccount
+        );
+        assert_eq!(simulation.return_value, Some(ScVal::U32(42)));
+        assert_eq!(simulation.events.len(), 1);
+        assert!(simulation.restore_preamble.is_none());
+    }
+
+    #[tokio::test]
+    async fn rpc_errors_are_reported() {
+        let (rpc, _) = replay(vec![include_str!("testdata/rpc_error.json")]).await;
+        let err = rpc.simulate_transaction(&envelope()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Soroban RPC simulateTransaction failed (-32602): invalid parameters \
+             \"[-32602] invalid parameters: transaction: xdr value invalid\""
+        );
+    }
+
+    #[tokio::test]
+    async fn transactions_are_polled_until_found() {
+        let (rpc, requests) = replay(vec![
+            include_str!("testdata/rpc_transaction_not_found.json"),
+            include_str!("testdata/rpc_transaction_success.json"),
+        ])
+        .await;
+        let info = rpc.wait_for_transaction("abc123").await.unwrap();
+
+        assert_eq!(info.status, "SUCCESS");
+        assert_eq!(info.ledger, Some(1181));
+        assert_eq!(info.fee_charged(), Some(100_123));
+        assert!(info.return_value().is_none());
+        assert!(info.events().is_empty());
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|request| request["method"] == "getTransaction"
+                && request["params"]["hash"] == "abc123"));
+    }
+
+    #[tokio::test]
+    async fn transaction_statuses_are_kept() {
+        let (rpc, _) = replay(vec![
+            include_str!("testdata/rpc_transaction_not_found.json"),
+            include_str!("testdata/rpc_transaction_failed.json"),
+        ])
+        .await;
+
+        let not_found = rpc.get_transaction("abc123").await.unwrap();
+        assert_eq!(not_found.status, "NOT_FOUND");
+        assert_eq!(not_found.ledger, None);
+        assert!(not_found.result.is_none());
+
+        let failed = rpc.get_transaction("abc123").await.unwrap();
+        assert_eq!(failed.status, "FAILED");
+        assert_eq!(failed.ledger, Some(1181));
+        assert_eq!(failed.fee_charged(), Some(80_000));
+        assert!(matches!(
+            &failed.result.as_ref().unwrap().result,
+            TransactionResultResult::TxFailed(results)
+                if matches!(results.as_slice(), [OperationResult::OpInner(_)])
+        ));
+        assert_eq!(failed.events().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn events_are_paged_by_cursor() {
+        let (rpc, requests) = replay(vec![
+            include_str!("testdata/rpc_events_first_page.json"),
+            include_str!("testdata/rpc_events_legacy_page.json"),
+        ])
+        .await;
+        let filter = EventFilter {
+            contract_ids: vec![CONTRACT.parse().unwrap()],
+            topics: vec![vec![
+                TopicSegment::Value(ScVal::Symbol(ScSymbol("transfer".try_into().unwrap()))),
+                TopicSegment::AnyRemaining,
+            ]],
+            ..EventFilter::default()
+        };
+
+        let first = rpc
+            .get_events(&EventStart::Ledger(1000), std::slice::from_ref(&filter), 1)
+            .await
+            .unwrap();
+        assert_eq!(first.latest_ledger, 1180);
+        assert_eq!(first.cursor.as_deref(), Some("0004724464025600-0000000001"));
+        assert_eq!(first.events.len(), 1);
+        let event = &first.events[0];
+        assert_eq!(event.ledger, 1100);
+        assert_eq!(event.id, "0004724464025600-0000000001");
+        assert!(event.transaction_hash.starts_with("5d9a2c1f"));
+        assert_eq!(
+            event.event.event.contract_id.as_ref().unwrap().to_string(),
+            CONTRACT
+        );
+        let ContractEventBody::V0(body) = &event.event.event.body;
+        assert_eq!(body.data, ScVal::U32(7));
+
+        // Older RPC versions give no page cursor, only each event's token
+        let second = rpc
+            .get_events(&EventStart::Cursor(first.cursor.unwrap()), &[filter], 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            second.cursor.as_deref(),
+            Some("0004943507357696-0000000003")
+        );
+        assert_eq!(second.events.len(), 2);
+        assert_eq!(
+            second.events[1].event.event.type_,
+            ContractEventType::Diagnostic
+        );
+        assert!(second.events[1].event.event.contract_id.is_none());
+        assert!(!second.events[1].event.in_successful_contract_call);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0]["method"], "getEvents");
+        assert_eq!(
+            requests[0]["params"],
+            serde_json::json!({
+                "startLedger": 1000,
+                "filters": [{
+                    "contractIds": [CONTRACT],
+                    "topics": [["AAAADwAAAAh0cmFuc2Zlcg==", "**"]],
+                }],
+                "pagination": { "limit": 1 },
+            })
+        );
+        assert_eq!(
+            requests[1]["params"]["pagination"],
+            serde_json::json!({ "limit": 2, "cursor": "0004724464025600-0000000001" })
+        );
+        assert!(requests[1]["params"].get("startLedger").is_none());
+    }
+}
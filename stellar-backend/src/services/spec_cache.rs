@@ -0,0 +1,80 @@
+use anyhow::Result;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use stellar_xdr::curr::ScAddress;
+
+use crate::config::env_or;
+use crate::services::invocation;
+use crate::services::networks::NetworkProfile;
+use crate::services::scval::ContractInterface;
+use crate::services::soroban_rpc::RpcClient;
+
+// Contracts can be upgraded to new wasm, so cached interfaces go stale
+const DEFAULT_TTL_SECS: u64 = 300;
+const MAX_ENTRIES: usize = 1000;
+
+struct CachedInterface {
+    interface: Arc<ContractInterface>,
+    fetched_at: Instant,
+}
+
+/// Interfaces of deployed contracts, keyed by network and contract id
+pub struct SpecCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<(String, String), CachedInterface>>,
+}
+
+impl SpecCache {
+    pub fn from_env() -> Self {
+        let ttl_secs = env_or("SPEC_CACHE_TTL_SECS", DEFAULT_TTL_SECS);
+        info!("Contract spec cache TTL: {}s", ttl_secs);
+
+        Self {
+            ttl: Duration::from_secs(ttl_secs),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The interface of a deployed contract, fetched over RPC unless a fresh
+    /// copy is cached
+    pub async fn get(
+        &self,
+        network: &NetworkProfile,
+        contract: &ScAddress,
+    ) -> Result<Arc<ContractInterface>> {
+        let key = (network.name.clone(), contract.to_string());
+        if let Some(cached) = self.entries.lock().unwrap().get(&key) {
+            if cached.fetched_at.elapsed() < self.ttl {
+                debug!("Using cached spec of {} on {}", key.1, key.0);
+                return Ok(cached.interface.clone());
+            }
+        }
+
+        let rpc = RpcClient::new(&network.rpc_url)?;
+        let interface = Arc::new(invocation::contract_interface(&rpc, contract).await?);
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, cached| cached.fetched_at.elapsed() < self.ttl);
+        if entries.len() >= MAX_ENTRIES {
+            // Evict the oldest entry to stay bounded
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.fetched_at)
+                .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            CachedInterface {
+                interface: interface.clone(),
+                fetched_at: Instant::now(),
+            },
+        );
+
+        Ok(interface)
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::services::compilation::{
+    self, ContractCrate, WasmVariant, DEFAULT_CONTRACT, WASM_TARGET,
+};
+use crate::services::sdk_versions::SdkVersion;
+
+// Written once a version's cache has been fully built
+const COMPLETE_MARKER: &str = ".complete";
+
+/// Which kind of build a target directory is seeded for
+#[derive(Debug, Clone, Copy)]
+pub enum BuildProfile {
+    /// Optimized wasm builds (`cargo rustc --release --target wasm32v1-none`)
+    Release,
+    /// The same with the `release-with-logs` profile, built on demand
+    ReleaseWithLogs,
+    /// Native test builds (`cargo test`)
+    Test,
+}
+
+impl BuildProfile {
+    /// Target subdirectories holding this profile's artifacts. Release
+    /// builds also compile build scripts and proc macros for the host.
+    fn dirs(self) -> Vec<PathBuf> {
+        match self {
+            BuildProfile::Release => wasm_dirs(WasmVariant::Release),
+            BuildProfile::ReleaseWithLogs => wasm_dirs(WasmVariant::WithLogs),
+            BuildProfile::Test => vec![PathBuf::from("debug")],
+        }
+    }
+}
+
+fn wasm_dirs(variant: WasmVariant) -> Vec<PathBuf> {
+    vec![
+        PathBuf::from(variant.profile()),
+        Path::new(WASM_TARGET).join(variant.profile()),
+    ]
+}
+
+/// Build the dependency tree of the base project for `sdk` into its shared
+/// target directory, once per version.
+///
+/// Holds the version's write lock for the whole build; projects skip seeding
+/// rather than wait for it.
+pub async fn warm(sdk: &SdkVersion) -> Result<()> {
+    let target_dir = sdk.target_dir();
+    if target_dir.join(COMPLETE_MARKER).exists() {
+        debug!("Target cache for soroban-sdk {} already built", sdk.version);
+        return Ok(());
+    }
+
+    let _guard = sdk.target_lock.write().await;
+    info!(
+        "Building shared target cache for soroban-sdk {}",
+        sdk.version
+    );
+
+    let workspace = sdk.workspace_dir();
+    let contract = ContractCrate {
+        name: DEFAULT_CONTRACT.to_string(),
+        dir: Path::new(compilation::CONTRACTS_DIR).join(DEFAULT_CONTRACT),
+    };
+
+    // Same flags as real builds, otherwise fingerprints would not match
+    let mut release = Command::new("cargo");
+    compilation::add_wasm_build_args(&mut release, &contract, WasmVariant::Release);
+    release.current_dir(&workspace);
+    let mut with_logs = Command::new("cargo");
+    compilation::add_wasm_build_args(&mut with_logs, &contract, WasmVariant::WithLogs);
+    with_logs.current_dir(&workspace);
+    let mut test = Command::new("cargo");
+    test.arg("test")
+        .arg("--workspace")
+        .arg("--no-run")
+        .current_dir(&workspace);
+
+    for command in [&mut release, &mut with_logs, &mut test] {
+        sdk.configure(command);
+        command.env("CARGO_TARGET_DIR", &target_dir);
+
+        let output = command
+            .stdout(Stdio::null())
+            .output()
+            .await
+            .context("Failed to execute cargo")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Target cache build failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+    }
+
+    fs::write(target_dir.join(COMPLETE_MARKER), "").context("Failed to mark target cache")?;
+    Ok(())
+}
+
+/// Give a project's first build of `profile` the prebuilt dependencies.
+///
+/// Copies (never links) so builds in the project can't modify the shared
+/// cache. Does nothing if the project already has artifacts for the profile
+/// or the cache is unavailable.
+pub fn seed(sdk: &SdkVersion, project_path: &Path, profile: BuildProfile) -> Result<()> {
+    let project_target = project_path.join("target");
+    let dirs = profile.dirs();
+    if dirs.iter().any(|dir| project_target.join(dir).exists()) {
+        return Ok(());
+    }
+
+    let _guard = match sdk.target_lock.try_read() {
+        Ok(guard) => guard,
+        Err(_) => {
+            info!(
+                "Target cache for soroban-sdk {} is still building, not seeding {:?}",
+                sdk.version, project_path
+            );
+            return Ok(());
+        }
+    };
+
+    let cache_target = sdk.target_dir();
+    if !cache_target.join(COMPLETE_MARKER).exists() {
+        return Ok(());
+    }
+
+    info!(
+        "Seeding {:?} build of {:?} from soroban-sdk {} target cache",
+        profile, project_path, sdk.version
+    );
+
+    for dir in dirs {
+        let source = cache_target.join(&dir);
+        if source.exists() {
+            if let Err(e) = copy_preserving_mtime(&source, &project_target.join(&dir)) {
+                warn!("Failed to seed target directory {:?}: {}", dir, e);
+                // A partial copy would only confuse cargo's fingerprints
+                let _ = fs::remove_dir_all(project_target.join(&dir));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Recursively copy a target directory, keeping modification times so cargo
+/// considers the copied artifacts fresh. Incremental state is skipped.
+fn copy_preserving_mtime(src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst).context("Failed to create target directory")?;
+
+    for entry in fs::read_dir(src).context("Failed to read target cache directory")? {
+        let entry = entry.context("Failed to read directory entry")?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            if entry.file_name() == "incremental" {
+                continue;
+            }
+            copy_preserving_mtime(&src_path, &dst_path)?;
+        } else if file_type.is_file() {
+            fs::copy(&src_path, &dst_path)
+                .with_context(|| format!("Failed to copy {:?}", src_path))?;
+            let modified = entry.metadata()?.modified()?;
+            fs::File::options()
+                .write(true)
+                .open(&dst_path)?
+                .set_modified(modified)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use anyhow::Result;
+use log::{debug, info};
+use serde::Deserialize;
+use std::time::Instant;
+
+use crate::models::{TestCaseResult, TestResponse, TestStatus};
+use crate::services::compilation::{self, BuildEnv, ContractSources};
+use crate::services::sandbox::Network;
+use crate::services::target_cache::{self, BuildProfile};
+use crate::storage::ProjectRef;
+
+// A single line of libtest's `--format json` output
+#[derive(Debug, Deserialize)]
+struct LibtestEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    event: String,
+    name: Option<String>,
+    exec_time: Option<f64>,
+    stdout: Option<String>,
+    filtered_out: Option<u32>,
+}
+
+/// Check a test name filter. It is passed to the test harness after its
+/// options, so it must not look like one (e.g. `--logfile=...`).
+pub fn validate_filter(filter: &str) -> Result<()> {
+    if filter.starts_with('-') {
+        return Err(anyhow::anyhow!(
+            "Invalid test filter {:?}: must not start with '-'",
+            filter
+        ));
+    }
+    Ok(())
+}
+
+pub async fn run_contract_tests(
+    sources: &ContractSources,
+    project: &ProjectRef,
+    contract: Option<&str>,
+    filter: Option<&str>,
+    build: BuildEnv<'_>,
+) -> Result<TestResponse> {
+    let start_time = Instant::now();
+    filter.map(validate_filter).transpose()?;
+
+    let project_path = compilation::prepare_project(project, sources, build.sdk)?;
+    target_cache::seed(build.sdk, &project_path, BuildProfile::Test)?;
+
+    let fetch = compilation::fetch_dependencies(&project_path, build, &|_, _| {}).await?;
+    if let Some(reason) = fetch.terminated {
+        return Err(anyhow::anyhow!("{}", reason.message(&build.control.limits)));
+    }
+    if !fetch.status.success() {
+        return Err(anyhow::anyhow!("Failed to fetch dependencies: {}", fetch.stderr));
+    }
+
+    // Tests run natively against soroban-sdk's testutils, not as wasm.
+    // libtest's JSON output is still unstable, hence RUSTC_BOOTSTRAP. It is
+    // set for the test binaries only, through a runner, so that rustc
+    // doesn't accept unstable features in the user's code. Doctests would
+    // bypass the runner, so only unit and integration tests run.
+    let mut command = build.sandbox.cargo(&project_path, build.sdk, Network::Denied)?;
+    command
+        .arg("test")
+        .arg("--tests")
+        .arg("--config")
+        .arg("target.'cfg(all())'.runner = ['env', 'RUSTC_BOOTSTRAP=1']");
+
+    match contract {
+        Some(name) => {
+            let contracts = compilation::select_contracts(&project_path, Some(name))?;
+            command.arg("--manifest-path").arg(contracts[0].manifest_path());
+        }
+        None => {
+            command.arg("--workspace");
+        }
+    }
+
+    command
+        .arg("--message-format=json")
+        .arg("--")
+        .arg("-Z")
+        .arg("unstable-options")
+        .arg("--format=json")
+        .arg("--report-time")
+        .arg("--show-output");
+
+    if let Some(filter) = filter {
+        command.arg(filter);
+    }
+
+    let output = compilation::run_cargo(&mut command, build.control, &|_, _| {}).await?;
+    if let Some(reason) = output.terminated {
+        return Err(anyhow::anyhow!("{}", reason.message(&build.control.limits)));
+    }
+
+    let mut tests = Vec::new();
+    let mut filtered_out = 0;
+    let mut stdout = String::new();
+
+    for line in output.stdout.lines() {
+        let event = match serde_json::from_str::<LibtestEvent>(line) {
+            Ok(event) => event,
+            Err(_) => {
+                stdout.push_str(line);
+                stdout.push('\n');
+                continue;
+            }
+        };
+
+        match event.kind.as_str() {
+            "test" => {
+                if let Some(result) = test_case_result(event) {
+                    tests.push(result);
+                }
+            }
+            "suite" => filtered_out += event.filtered_out.unwrap_or(0),
+            _ => {}
+        }
+    }
+
+    let count = |status: TestStatus| tests.iter().filter(|test| test.status == status).count();
+    let passed = count(TestStatus::Passed);
+    let failed = count(TestStatus::Failed);
+    let ignored = count(TestStatus::Ignored);
+
+    let total_time = start_time.elapsed().as_secs_f64();
+    info!(
+        "Tests for project {} finished in {:.2}s: {} passed, {} failed, {} ignored",
+        project, total_time, passed, failed, ignored
+    );
+
+    Ok(TestResponse {
+        success: output.status.success(),
+        exit_code: output.status.code().unwrap_or(-1),
+        passed,
+        failed,
+        ignored,
+        filtered_out,
+        total_time,
+        tests,
+        diagnostics: output.diagnostics,
+        stdout,
+        stderr: output.stderr,
+    })
+}
+
+fn test_case_result(event: LibtestEvent) -> Option<TestCaseResult> {
+    let status = match event.event.as_str() {
+        "ok" => TestStatus::Passed,
+        "failed" | "timeout" => TestStatus::Failed,
+        "ignored" => TestStatus::Ignored,
+        // "started" carries nothing we report
+        _ => return None,
+    };
+
+    let name = event.name?;
+    debug!("Test {} finished: {:?}", name, status);
+
+    let panic_message = match status {
+        TestStatus::Failed => event.stdout.as_deref().and_then(extract_panic_message),
+        _ => None,
+    };
+
+    Some(TestCaseResult {
+        name,
+        status,
+        exec_time: event.exec_time,
+        stdout: event.stdout.filter(|stdout| !stdout.is_empty()),
+        panic_message,
+    })
+}
+
+/// Pull the panic payload out of a failed test's captured output.
+///
+/// Handles both the current format (`panicked at src/test.rs:10:5:` followed
+/// by the message on the next lines) and the pre-1.73 single-line format.
+/// A backtrace printed after the message, when RUST_BACKTRACE is set, is dropped.
+fn extract_panic_message(stdout: &str) -> Option<String> {
+    let mut lines = stdout.lines().skip_while(|line| !line.contains("panicked at"));
+    let header = lines.next()?;
+    let after = &header[header.find("panicked at")? + "panicked at".len()..];
+
+    if let Some(quoted) = after.trim().strip_prefix('\'') {
+        // thread 'x' panicked at 'message', src/test.rs:10:5
+        return quoted.rfind("', ").map(|end| quoted[..end].to_string());
+    }
+
+    let message: Vec<&str> = lines
+        .take_while(|line| {
+            !line.starts_with("note: run with `RUST_BACKTRACE") && *line != "stack backtrace:"
+        })
+        .collect();
+
+    if message.is_empty() {
+        None
+    } else {
+        Some(message.join("\n").trim_end().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Recorded `cargo test -- -Z unstable-options --format=json --report-time
+    // --show-output test_` output: one passing, one failing and one ignored
+    // test, with another filtered out. The last line is the failure again,
+    // run with RUST_BACKTRACE=1.
+    const RECORDED: &str = include_str!("testdata/libtest_events.jsonl");
+
+    fn event(index: usize) -> LibtestEvent {
+        let line = RECORDED.lines().nth(index).expect("recorded line");
+        serde_json::from_str(line).unwrap()
+    }
+
+    const ASSERTION: &str = "assertion `left == right` failed: sum is wrong\n  left: 3\n right: 4";
+
+    #[test]
+    fn passed_test() {
+        let result = test_case_result(event(2)).unwrap();
+        assert_eq!(result.name, "test::test_add");
+        assert_eq!(result.status, TestStatus::Passed);
+        assert!(result.exec_time.is_some());
+        assert_eq!(result.stdout, None);
+        assert_eq!(result.panic_message, None);
+    }
+
+    #[test]
+    fn failed_test_with_panic() {
+        let result = test_case_result(event(4)).unwrap();
+        assert_eq!(result.name, "test::test_overflow");
+        assert_eq!(result.status, TestStatus::Failed);
+        assert!(result.stdout.unwrap().starts_with("adding large values\n"));
+        assert_eq!(result.panic_message.as_deref(), Some(ASSERTION));
+    }
+
+    #[test]
+    fn backtrace_is_not_part_of_the_panic_message() {
+        let result = test_case_result(event(8)).unwrap();
+        assert!(result.stdout.unwrap().contains("stack backtrace:"));
+        assert_eq!(result.panic_message.as_deref(), Some(ASSERTION));
+    }
+
+    #[test]
+    fn ignored_test() {
+        let result = test_case_result(event(6)).unwrap();
+        assert_eq!(result.name, "test::test_slow");
+        assert_eq!(result.status, TestStatus::Ignored);
+        assert_eq!(result.exec_time, None);
+        assert_eq!(result.panic_message, None);
+    }
+
+    #[test]
+    fn started_events_are_skipped() {
+        assert!(test_case_result(event(1)).is_none());
+        assert!(test_case_result(event(0)).is_none());
+    }
+
+    #[test]
+    fn suite_summary() {
+        let summary = event(7);
+        assert_eq!(summary.kind, "suite");
+        assert_eq!(summary.event, "failed");
+        assert_eq!(summary.filtered_out, Some(1));
+        assert_eq!(event(0).filtered_out, None);
+    }
+
+    #[test]
+    fn single_line_panic_message() {
+        let stdout = "thread 'test::test_overflow' panicked at 'sum is wrong', src/lib.rs:15:9\n\
+                      note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace\n";
+        assert_eq!(
+            extract_panic_message(stdout).as_deref(),
+            Some("sum is wrong")
+        );
+        assert_eq!(extract_panic_message("adding large values\n"), None);
+    }
+
+    #[test]
+    fn filters_must_not_look_like_options() {
+        assert!(validate_filter("test_").is_ok());
+        assert!(validate_filter("test::test_add").is_ok());
+        for filter in [
+            "-Zunstable-options",
+            "--logfile=/tmp/out",
+            "--include-ignored",
+            "-",
+        ] {
+            let err = validate_filter(filter).unwrap_err();
+            assert!(
+                err.to_string().ends_with("must not start with '-'"),
+                "{}",
+                filter
+            );
+        }
+    }
+}
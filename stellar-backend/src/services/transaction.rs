@@ -0,0 +1,386 @@
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signer as _, SigningKey};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+use stellar_xdr::curr::{
+    AccountId, DecoratedSignature, DiagnosticEvent, HostFunction, InvokeHostFunctionOp, Limits,
+    Memo, MuxedAccount, Operation, OperationBody, Preconditions, PublicKey, ReadXdr,
+    SequenceNumber, Signature, SignatureHint, SorobanAuthorizationEntry, TimeBounds, TimePoint,
+    Transaction, TransactionEnvelope, TransactionExt, TransactionV1Envelope, Uint256, WriteXdr,
+};
+
+use crate::services::soroban_rpc::{RpcClient, SendResult, Simulation, TransactionInfo};
+
+// Inclusion fee bid, in stroops, on top of the simulated resource fee
+pub const BASE_FEE: u32 = 100;
+// Transactions not included within this long are dropped by the network
+const TX_VALIDITY_SECS: u64 = 300;
+// Nesting allowed in XDR that clients send: far more than real transactions
+// use, and shallow enough that a hostile value can't overflow the stack
+const CLIENT_XDR_DEPTH: u32 = 500;
+
+/// An ed25519 keypair that signs transactions
+pub struct Signer {
+    key: SigningKey,
+}
+
+impl Signer {
+    /// Parse an `S...` secret seed
+    pub fn from_secret(secret: &str) -> Result<Self> {
+        let seed = stellar_strkey::ed25519::PrivateKey::from_string(secret.trim())
+            .map_err(|_| anyhow::anyhow!("Invalid Stellar secret key"))?;
+        Ok(Self {
+            key: SigningKey::from_bytes(&seed.0),
+        })
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    pub fn account_id(&self) -> AccountId {
+        AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(self.public_key())))
+    }
+
+    /// `G...` address
+    pub fn address(&self) -> String {
+        stellar_strkey::ed25519::PublicKey(self.public_key()).to_string()
+    }
+
+    /// Raw ed25519 signature of a payload
+    pub fn signature(&self, payload: &[u8]) -> [u8; 64] {
+        self.key.sign(payload).to_bytes()
+    }
+
+    fn sign(&self, payload: &[u8]) -> DecoratedSignature {
+        let public_key = self.public_key();
+        DecoratedSignature {
+            hint: SignatureHint(public_key[28..].try_into().expect("4 byte hint")),
+            signature: Signature(
+                self.signature(payload)
+                    .to_vec()
+                    .try_into()
+                    .expect("64 byte signature"),
+            ),
+        }
+    }
+}
+
+/// Parse a `G...` account address
+pub fn parse_account(address: &str) -> Result<AccountId> {
+    let key = stellar_strkey::ed25519::PublicKey::from_string(address.trim())
+        .map_err(|_| anyhow::anyhow!("Invalid Stellar account address {}", address))?;
+    Ok(AccountId(PublicKey::PublicKeyTypeEd25519(Uint256(key.0))))
+}
+
+/// Network id that transaction hashes and signatures commit to
+pub fn network_id(passphrase: &str) -> [u8; 32] {
+    Sha256::digest(passphrase.as_bytes()).into()
+}
+
+/// Build a transaction with a single host function invocation, to be
+/// simulated before it is signed
+pub fn host_function_transaction(
+    source: &AccountId,
+    sequence: i64,
+    host_function: HostFunction,
+) -> Result<Transaction> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    Ok(Transaction {
+        source_account: MuxedAccount::Ed25519(match &source.0 {
+            PublicKey::PublicKeyTypeEd25519(key) => key.clone(),
+        }),
+        fee: BASE_FEE,
+        seq_num: SequenceNumber(sequence + 1),
+        cond: Preconditions::Time(TimeBounds {
+            min_time: TimePoint(0),
+            max_time: TimePoint(now + TX_VALIDITY_SECS),
+        }),
+        memo: Memo::None,
+        operations: vec![Operation {
+            source_account: None,
+            body: OperationBody::InvokeHostFunction(InvokeHostFunctionOp {
+                host_function,
+                auth: Default::default(),
+            }),
+        }]
+        .try_into()
+        .context("Failed to build operation list")?,
+        ext: TransactionExt::V0,
+    })
+}
+
+/// Unsigned envelope, as simulateTransaction expects
+pub fn unsigned_envelope(tx: &Transaction) -> TransactionEnvelope {
+    TransactionEnvelope::Tx(TransactionV1Envelope {
+        tx: tx.clone(),
+        signatures: Default::default(),
+    })
+}
+
+/// Build a host function transaction for `source` at its current sequence
+/// number and simulate it
+pub async fn simulate(
+    rpc: &RpcClient,
+    source: &AccountId,
+    host_function: HostFunction,
+) -> Result<(Transaction, Simulation)> {
+    simulate_authorized(rpc, source, host_function, Vec::new()).await
+}
+
+/// Like `simulate`, with authorization entries attached. RPC then checks
+/// their signatures instead of recording which ones the call needs.
+pub async fn simulate_authorized(
+    rpc: &RpcClient,
+    source: &AccountId,
+    host_function: HostFunction,
+    auth: Vec<SorobanAuthorizationEntry>,
+) -> Result<(Transaction, Simulation)> {
+    let sequence = rpc.account_sequence(source).await?;
+    let tx = with_auth(
+        host_function_transaction(source, sequence, host_function)?,
+        auth,
+    )?;
+    let simulation = rpc.simulate_transaction(&unsigned_envelope(&tx)).await?;
+    Ok((tx, simulation))
+}
+
+/// Replace the authorization entries of a host function transaction
+pub fn with_auth(mut tx: Transaction, auth: Vec<SorobanAuthorizationEntry>) -> Result<Transaction> {
+    let mut operations = tx.operations.to_vec();
+    if let Some(OperationBody::InvokeHostFunction(op)) =
+        operations.first_mut().map(|op| &mut op.body)
+    {
+        op.auth = auth.try_into().context("Too many authorization entries")?;
+    }
+    tx.operations = operations
+        .try_into()
+        .context("Failed to build operation list")?;
+    Ok(tx)
+}
+
+/// Apply a simulation's footprint, resource fee and auth entries. Auth
+/// entries already on the transaction are kept.
+pub fn assemble(mut tx: Transaction, simulation: &Simulation) -> Result<Transaction> {
+    let transaction_data = simulation
+        .transaction_data
+        .clone()
+        .context("Simulation returned no resource footprint")?;
+
+    tx.fee = u32::try_from(i64::from(tx.fee) + simulation.min_resource_fee)
+        .context("Transaction fee overflow")?;
+    tx.ext = TransactionExt::V1(transaction_data);
+
+    let authorized = tx.operations.first().is_some_and(
+        |op| matches!(&op.body, OperationBody::InvokeHostFunction(op) if !op.auth.is_empty()),
+    );
+    if authorized {
+        return Ok(tx);
+    }
+    with_auth(tx, simulation.auth.clone())
+}
+
+/// Sign a transaction for the network with the given passphrase
+pub fn sign(tx: Transaction, passphrase: &str, signer: &Signer) -> Result<TransactionEnvelope> {
+    let mut envelope = TransactionV1Envelope {
+        tx,
+        signatures: Default::default(),
+    };
+    let hash = envelope
+        .hash(network_id(passphrase))
+        .context("Failed to hash transaction")?;
+    envelope.signatures = vec![signer.sign(&hash)]
+        .try_into()
+        .context("Failed to attach signature")?;
+
+    Ok(TransactionEnvelope::Tx(envelope))
+}
+
+/// Hex hash of an envelope, as wallets and explorers show it
+pub fn transaction_hash(envelope: &TransactionEnvelope, passphrase: &str) -> Result<String> {
+    let hash = match envelope {
+        TransactionEnvelope::Tx(envelope) => envelope.hash(network_id(passphrase)),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Only v1 transaction envelopes are supported"
+            ))
+        }
+    };
+    hash.map(hex::encode).context("Failed to hash transaction")
+}
+
+pub fn encode_envelope(envelope: &TransactionEnvelope) -> Result<String> {
+    envelope
+        .to_xdr_base64(Limits::none())
+        .context("Failed to encode transaction")
+}
+
+/// Limits for decoding base64 XDR a client sent: bounded nesting, and no
+/// more bytes than the text itself holds
+pub fn client_xdr_limits(xdr: &str) -> Limits {
+    Limits {
+        depth: CLIENT_XDR_DEPTH,
+        len: xdr.len(),
+    }
+}
+
+/// Parse a base64 envelope, which must carry a single host function
+/// invocation, as every transaction this service prepares does
+pub fn decode_envelope(xdr: &str) -> Result<(TransactionEnvelope, HostFunction)> {
+    let xdr = xdr.trim();
+    let envelope = TransactionEnvelope::from_xdr_base64(xdr, client_xdr_limits(xdr))
+        .map_err(|_| anyhow::anyhow!("Invalid transaction envelope XDR"))?;
+
+    let host_function = match &envelope {
+        TransactionEnvelope::Tx(TransactionV1Envelope { tx, .. }) => match tx.operations.as_slice()
+        {
+            [Operation {
+                body: OperationBody::InvokeHostFunction(op),
+                ..
+            }] => op.host_function.clone(),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Transaction must contain a single host function invocation"
+                ))
+            }
+        },
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Only v1 transaction envelopes are supported"
+            ))
+        }
+    };
+
+    Ok((envelope, host_function))
+}
+
+/// Send a signed transaction and wait for it to be applied
+pub async fn submit(rpc: &RpcClient, envelope: &TransactionEnvelope) -> Result<Submission> {
+    let sent = rpc.send_transaction(envelope).await?;
+    info!("Sent transaction {}: {}", sent.hash, sent.status);
+
+    match sent.status.as_str() {
+        "PENDING" | "DUPLICATE" => {}
+        _ => {
+            warn!(
+                "Transaction {} rejected: {:?}",
+                sent.hash, sent.error_result
+            );
+            return Ok(Submission {
+                hash: sent.hash.clone(),
+                info: None,
+                rejected: Some(sent),
+            });
+        }
+    }
+
+    let info = rpc.wait_for_transaction(&sent.hash).await?;
+    Ok(Submission {
+        hash: sent.hash,
+        info: Some(info),
+        rejected: None,
+    })
+}
+
+/// A submitted transaction and what became of it
+#[derive(Debug)]
+pub struct Submission {
+    pub hash: String,
+    pub info: Option<TransactionInfo>, // Set once it was included in a ledger
+    pub rejected: Option<SendResult>,  // Set if the network refused it
+}
+
+impl Submission {
+    /// Events recorded while the transaction was checked or applied
+    pub fn events(&self) -> Vec<DiagnosticEvent> {
+        match (&self.info, &self.rejected) {
+            (Some(info), _) => info.events(),
+            (None, Some(rejected)) => rejected.diagnostic_events.clone(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Human readable reason the transaction did not succeed
+    pub fn failure(&self) -> Option<String> {
+        if let Some(rejected) = &self.rejected {
+            return Some(match &rejected.error_result {
+                Some(result) => format!(
+                    "Transaction {} was rejected ({}): {:?}",
+                    self.hash, rejected.status, result.result
+                ),
+                None => format!(
+                    "Transaction {} was rejected ({})",
+                    self.hash, rejected.status
+                ),
+            });
+        }
+        match &self.info {
+            Some(info) if info.status == "SUCCESS" => None,
+            Some(info) => Some(match &info.result {
+                Some(result) => format!("Transaction {} failed: {:?}", self.hash, result.result),
+                None => format!("Transaction {} failed", self.hash),
+            }),
+            None => Some(format!("Transaction {} was not applied", self.hash)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use stellar_xdr::curr::{ContractId, Hash, InvokeContractArgs, ScAddress, ScVal};
+
+    fn envelope_with_arg(arg: ScVal) -> String {
+        let signer =
+            Signer::from_secret(&stellar_strkey::ed25519::PrivateKey([7; 32]).to_string()).unwrap();
+        let host_function = HostFunction::InvokeContract(InvokeContractArgs {
+            contract_address: ScAddress::Contract(ContractId(Hash([1; 32]))),
+            function_name: "hello".try_into().unwrap(),
+            args: vec![arg].try_into().unwrap(),
+        });
+        let tx = host_function_transaction(&signer.account_id(), 1, host_function).unwrap();
+        encode_envelope(&unsigned_envelope(&tx)).unwrap()
+    }
+
+    #[test]
+    fn envelopes_decode() {
+        let xdr = envelope_with_arg(ScVal::U32(7));
+        let (_, host_function) = decode_envelope(&format!(" {}\n", xdr)).unwrap();
+        match host_function {
+            HostFunction::InvokeContract(call) => assert_eq!(call.args[0], ScVal::U32(7)),
+            other => panic!("unexpected host function {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deeply_nested_envelopes_are_rejected() {
+        // Vec([Void]) in the envelope is replaced with tens of thousands of
+        // nested vecs, far more than any encoder would be allowed to write
+        let vec_header: &[u8] = &[0, 0, 0, 16, 0, 0, 0, 1, 0, 0, 0, 1];
+        let void: &[u8] = &[0, 0, 0, 1];
+        let shallow = [vec_header, void].concat();
+        let mut deep = vec_header.repeat(50_000);
+        deep.extend_from_slice(void);
+
+        let engine = base64::engine::general_purpose::STANDARD;
+        let bytes = engine
+            .decode(envelope_with_arg(ScVal::Vec(Some(
+                vec![ScVal::Void].try_into().unwrap(),
+            ))))
+            .unwrap();
+        let at = bytes
+            .windows(shallow.len())
+            .position(|window| window == shallow)
+            .unwrap();
+        let nested = [&bytes[..at], &deep, &bytes[at + shallow.len()..]].concat();
+
+        let error = decode_envelope(&engine.encode(nested)).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid transaction envelope XDR");
+    }
+}
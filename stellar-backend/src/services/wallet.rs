@@ -0,0 +1,262 @@
+use anyhow::{Context, Result};
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use stellar_xdr::curr::{
+    Hash, HostFunction, InvokeContractArgs, ScVal, Transaction, TransactionEnvelope,
+};
+
+use crate::models::{AuthEntry, PreparedTransaction, SignedAuthEntry, SubmitResponse};
+use crate::services::compilation::WasmVariant;
+use crate::services::invocation::CallEnv;
+use crate::services::networks::NetworkProfile;
+use crate::services::scval::ContractInterface;
+use crate::services::soroban_rpc::{RpcClient, Simulation};
+use crate::services::spec_cache::SpecCache;
+use crate::services::transaction::{self, Signer};
+use crate::services::{
+    authorization, contract_errors, contract_spec, costs, deployment, events, invocation, scval,
+};
+use crate::storage::ProjectRef;
+
+/// Prepare the next deploy step for a wallet to sign: the wasm upload if the
+/// code is not on-chain yet, otherwise the contract creation
+pub async fn prepare_deploy(
+    project: &ProjectRef,
+    contract: Option<&str>,
+    public_key: &str,
+    network: &NetworkProfile,
+    variant: WasmVariant,
+    constructor_args: Option<&serde_json::Value>,
+) -> Result<PreparedTransaction> {
+    let source = transaction::parse_account(public_key)?;
+    let wasm = deployment::load_wasm(project, contract, variant).await?;
+    let wasm_hash: [u8; 32] = Sha256::digest(&wasm).into();
+
+    // Checked at the upload step too, before the wallet pays for it
+    let interface = ContractInterface::new(contract_spec::spec_entries(&wasm)?);
+    let constructor_args = deployment::encode_constructor_args(&interface, constructor_args)
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+    let rpc = RpcClient::new(&network.rpc_url)?;
+    let (step, host_function) = if rpc.wasm_installed(&Hash(wasm_hash)).await? {
+        let salt = deployment::random_salt();
+        (
+            "create",
+            deployment::create_function(&source, wasm_hash, salt, constructor_args)?,
+        )
+    } else {
+        ("upload", deployment::upload_function(wasm)?)
+    };
+    info!(
+        "Preparing {} of project {} for {} on {}",
+        step, project, public_key, network.name
+    );
+
+    let (tx, simulation) = transaction::simulate(&rpc, &source, host_function).await?;
+    let mut prepared = prepared(tx, &simulation, Vec::new(), network)?;
+    prepared.step = Some(step.to_string());
+    prepared.wasm_hash = Some(hex::encode(wasm_hash));
+    Ok(prepared)
+}
+
+/// Prepare a contract call for a wallet to sign
+pub async fn prepare_invoke(
+    contract_id: &str,
+    method_name: &str,
+    args: &serde_json::Value,
+    public_key: &str,
+    env: CallEnv<'_>,
+    auth: &[SignedAuthEntry],
+    test_signers: &[Signer],
+) -> Result<PreparedTransaction> {
+    let network = env.network;
+    let source = transaction::parse_account(public_key)?;
+    let rpc = RpcClient::new(&network.rpc_url)?;
+    let call = invocation::invoke_function(env, contract_id, method_name, args)
+        .await?
+        .map_err(|e| anyhow::anyhow!(e))?;
+    info!(
+        "Preparing call of {} on {} for {} on {}",
+        method_name, contract_id, public_key, network.name
+    );
+
+    let authorized = authorization::simulate(
+        &rpc,
+        network,
+        &source,
+        call.host_function.clone(),
+        auth,
+        test_signers,
+    )
+    .await?;
+    let simulation = authorized.simulation;
+    let mut prepared = prepared(authorized.tx, &simulation, authorized.auth, network)?;
+    prepared.result = simulation
+        .return_value
+        .as_ref()
+        .map(|value| call.decode_result(value));
+    Ok(prepared)
+}
+
+/// Submit a wallet-signed envelope and wait for its result
+pub async fn submit_signed(
+    envelope: &TransactionEnvelope,
+    host_function: &HostFunction,
+    network: &NetworkProfile,
+    spec_cache: &SpecCache,
+) -> Result<SubmitResponse> {
+    let rpc = RpcClient::new(&network.rpc_url)?;
+    let submission = transaction::submit(&rpc, envelope).await?;
+    let signed_tx = match envelope {
+        TransactionEnvelope::Tx(envelope) => Some(&envelope.tx),
+        _ => None,
+    };
+
+    if let Some(error_msg) = submission.failure() {
+        error!("Signed transaction failed: {}", error_msg);
+        let submitted_events = submission.events();
+        let interface = match host_function {
+            HostFunction::InvokeContract(call) => {
+                spec_cache.get(network, &call.contract_address).await.ok()
+            }
+            _ => None,
+        };
+        let failure =
+            contract_errors::contract_failure(None, &submitted_events, interface.as_deref());
+        let cost = signed_tx.and_then(|tx| {
+            costs::transaction_cost(tx, None, &submitted_events, None, submission.info.as_ref())
+        });
+        let submitted_events = events::decode(&submitted_events);
+        return Ok(SubmitResponse {
+            success: false,
+            transaction_hash: submission.hash.clone(),
+            ledger_sequence: submission.info.as_ref().and_then(|info| info.ledger),
+            result: None,
+            fee: submission
+                .info
+                .as_ref()
+                .and_then(|info| info.fee_charged())
+                .map(|fee| fee.to_string()),
+            error: Some(error_msg),
+            failure,
+            logs: events::logs(&submitted_events),
+            events: submitted_events,
+            cost,
+        });
+    }
+
+    let info = submission
+        .info
+        .as_ref()
+        .context("Transaction was not applied")?;
+    info!("Signed transaction {} succeeded", submission.hash);
+
+    let recorded = info.events();
+    let return_value = info.return_value();
+    let cost = signed_tx.and_then(|tx| {
+        costs::transaction_cost(tx, None, &recorded, return_value.as_ref(), Some(info))
+    });
+    let submitted_events = events::decode(&recorded);
+    let result = match (host_function, &return_value) {
+        (HostFunction::InvokeContract(call), Some(value)) => {
+            Some(decode_call_result(spec_cache, network, call, value).await)
+        }
+        (_, value) => value.as_ref().map(scval::scval_to_json),
+    };
+
+    Ok(SubmitResponse {
+        success: true,
+        transaction_hash: submission.hash.clone(),
+        ledger_sequence: info.ledger,
+        result,
+        fee: info.fee_charged().map(|fee| fee.to_string()),
+        error: None,
+        failure: None,
+        logs: events::logs(&submitted_events),
+        events: submitted_events,
+        cost,
+    })
+}
+
+/// Decode a signed call's result with the contract's interface, falling
+/// back to untyped JSON if the spec can't be fetched
+async fn decode_call_result(
+    spec_cache: &SpecCache,
+    network: &NetworkProfile,
+    call: &InvokeContractArgs,
+    value: &ScVal,
+) -> serde_json::Value {
+    let function_name = call.function_name.0.to_utf8_string_lossy();
+    match spec_cache.get(network, &call.contract_address).await {
+        Ok(interface) => match interface
+            .function(&function_name)
+            .and_then(|function| function.outputs.first())
+        {
+            Some(output) => interface.decode(value, output),
+            None => scval::scval_to_json(value),
+        },
+        Err(e) => {
+            warn!(
+                "Failed to load the interface of {}: {}",
+                call.contract_address, e
+            );
+            scval::scval_to_json(value)
+        }
+    }
+}
+
+/// Whether a host function deploys code, which networks may forbid
+pub fn is_deploy(host_function: &HostFunction) -> bool {
+    matches!(
+        host_function,
+        HostFunction::UploadContractWasm(_)
+            | HostFunction::CreateContract(_)
+            | HostFunction::CreateContractV2(_)
+    )
+}
+
+/// Assemble a simulated transaction into the unsigned envelope a wallet signs
+fn prepared(
+    tx: Transaction,
+    simulation: &Simulation,
+    auth: Vec<AuthEntry>,
+    network: &NetworkProfile,
+) -> Result<PreparedTransaction> {
+    if let Some(error_msg) = &simulation.error {
+        return Err(anyhow::anyhow!("Simulation failed: {}", error_msg));
+    }
+    if let Some(preamble) = &simulation.restore_preamble {
+        return Err(anyhow::anyhow!(
+            "Ledger entries this transaction needs are archived and must be restored first \
+             (restore fee {} stroops)",
+            preamble.min_resource_fee
+        ));
+    }
+
+    let tx = transaction::assemble(tx, simulation)?;
+    let fee = tx.fee;
+    let envelope = transaction::unsigned_envelope(&tx);
+    let simulated_events = events::decode(&simulation.events);
+    let cost = costs::transaction_cost(
+        &tx,
+        simulation.cost.as_ref(),
+        &simulation.events,
+        simulation.return_value.as_ref(),
+        None,
+    );
+
+    Ok(PreparedTransaction {
+        xdr: transaction::encode_envelope(&envelope)?,
+        hash: transaction::transaction_hash(&envelope, &network.passphrase)?,
+        network: network.name.clone(),
+        network_passphrase: network.passphrase.clone(),
+        fee: fee.to_string(),
+        step: None,
+        wasm_hash: None,
+        result: simulation.return_value.as_ref().map(scval::scval_to_json),
+        logs: events::logs(&simulated_events),
+        events: simulated_events,
+        cost,
+        auth,
+    })
+}
@@ -0,0 +1,174 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// On-disk layout, relative to the working directory:
+//   base_project/                      template workspace for new projects
+//   projects/<user_id>/<project_id>/   one cargo workspace per user project
+const BASE_PROJECT_DIR: &str = "base_project";
+const PROJECTS_DIR: &str = "projects";
+
+const MAX_ID_LENGTH: usize = 128;
+
+/// A user's project, with ids that are safe to use as path components.
+///
+/// Ids may only contain ASCII letters, digits, `-` and `_` (Supabase UUIDs
+/// and slugs), so they can never point outside the projects tree.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProjectRef {
+    user_id: String,
+    project_id: String,
+}
+
+impl ProjectRef {
+    pub fn new(user_id: &str, project_id: &str) -> Result<Self> {
+        validate_id("user_id", user_id)?;
+        validate_id("project_id", project_id)?;
+
+        Ok(Self {
+            user_id: user_id.to_string(),
+            project_id: project_id.to_string(),
+        })
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+}
+
+impl fmt::Display for ProjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.user_id, self.project_id)
+    }
+}
+
+fn validate_id(field: &str, id: &str) -> Result<()> {
+    if id.is_empty() || id.len() > MAX_ID_LENGTH {
+        return Err(anyhow::anyhow!(
+            "{} must be between 1 and {} characters",
+            field,
+            MAX_ID_LENGTH
+        ));
+    }
+
+    if !id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow::anyhow!(
+            "Invalid {} {:?}: only letters, digits, '-' and '_' are allowed",
+            field,
+            id
+        ));
+    }
+
+    Ok(())
+}
+
+/// Template workspace new projects are copied from
+pub fn base_project_dir() -> PathBuf {
+    PathBuf::from(BASE_PROJECT_DIR)
+}
+
+/// Workspace directory of a project, which may not exist yet
+pub fn project_dir(project: &ProjectRef) -> Result<PathBuf> {
+    Ok(ensure_projects_dir()?
+        .join(&project.user_id)
+        .join(&project.project_id))
+}
+
+/// Helper function to recursively copy directories
+pub fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst).context("Failed to create destination directory")?;
+
+    for entry in fs::read_dir(src).context("Failed to read source directory")? {
+        let entry = entry.context("Failed to read directory entry")?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+
+        if src_path.is_dir() {
+            // Skip target directory and .git directory to avoid copying build artifacts
+            if let Some(dir_name) = src_path.file_name() {
+                if dir_name == "target" || dir_name == ".git" {
+                    continue;
+                }
+            }
+            copy_dir_all(&src_path, &dst_path)?;
+        } else {
+            fs::copy(&src_path, &dst_path)
+                .with_context(|| format!("Failed to copy file: {:?}", src_path))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Helper function to ensure projects directory exists
+pub fn ensure_projects_dir() -> Result<PathBuf> {
+    let projects_dir = PathBuf::from(PROJECTS_DIR);
+    if !projects_dir.exists() {
+        fs::create_dir_all(&projects_dir)
+            .context("Failed to create projects directory")?;
+    }
+    Ok(projects_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuids_are_accepted() {
+        let project = ProjectRef::new(
+            "0b3c9f1e-5d2a-4c7b-9e8f-1a2b3c4d5e6f",
+            "7f6e5d4c-3b2a-4190-8f7e-6d5c4b3a2910",
+        )
+        .unwrap();
+        assert_eq!(project.user_id(), "0b3c9f1e-5d2a-4c7b-9e8f-1a2b3c4d5e6f");
+        assert_eq!(
+            project.to_string(),
+            "0b3c9f1e-5d2a-4c7b-9e8f-1a2b3c4d5e6f/7f6e5d4c-3b2a-4190-8f7e-6d5c4b3a2910"
+        );
+        assert!(ProjectRef::new("user_1", &"a".repeat(MAX_ID_LENGTH)).is_ok());
+    }
+
+    #[test]
+    fn ids_that_could_leave_the_projects_tree_are_rejected() {
+        for id in ["..", ".", "../x", "a/b", "/abs", "a\\b", "..\\x"] {
+            let err = ProjectRef::new("user", id).unwrap_err();
+            assert!(err.to_string().starts_with("Invalid project_id"), "{}", id);
+            let err = ProjectRef::new(id, "project").unwrap_err();
+            assert!(err.to_string().starts_with("Invalid user_id"), "{}", id);
+        }
+    }
+
+    #[test]
+    fn ids_must_have_a_bounded_length() {
+        for id in [String::new(), "a".repeat(MAX_ID_LENGTH + 1)] {
+            let err = ProjectRef::new("user", &id).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "project_id must be between 1 and 128 characters"
+            );
+        }
+    }
+
+    #[test]
+    fn ids_must_be_plain_ascii() {
+        for id in [
+            "projekt-ü",
+            "proj\0ect",
+            "pro ject",
+            "proj\nect",
+            "ｐｒｏｊ",
+        ] {
+            let err = ProjectRef::new("user", id).unwrap_err();
+            assert!(
+                err.to_string().starts_with("Invalid project_id"),
+                "{:?}",
+                id
+            );
+        }
+    }
+}